@@ -0,0 +1,23 @@
+use lazy_static::lazy_static;
+use maelstrom::gossip;
+use maelstrom::states::maelstrom_node_state::MaelstromState;
+use std::{sync::mpsc::sync_channel, thread};
+use shared_lib::stdio::while_reply;
+use shared_lib::router::Handlers;
+use shared_lib::read_respond::read_respond_loop;
+use shared_lib::worker_pool::WorkerPool;
+
+lazy_static! {
+    static ref MESSAGE_HANDLERS: Handlers<MaelstromState> = maelstrom::broadcast_handlers();
+    static ref NODE_STATE: MaelstromState = {
+        let (reply_sender, reply_receiver) = sync_channel(1);
+        let state = MaelstromState::init(reply_sender);
+        state.set_writer(thread::spawn(|| while_reply(reply_receiver)));
+        state
+    };
+}
+
+fn main() {
+    gossip::start(&NODE_STATE);
+    read_respond_loop(&*NODE_STATE, &*MESSAGE_HANDLERS, WorkerPool::default())
+}
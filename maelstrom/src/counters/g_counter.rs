@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use json::{object::Object, JsonValue};
+use shared_lib::error::{malformed_request, DefiniteError};
 
 pub struct GCounter {
     values: HashMap<String, i32>,
@@ -21,19 +22,22 @@ impl GCounter {
         JsonValue::Object(object)
     }
 
-    pub fn from_json(jv: &JsonValue) -> GCounter {
+    pub fn from_json(jv: &JsonValue) -> Result<GCounter, DefiniteError> {
         let mut map: HashMap<String, i32> = HashMap::new();
-        jv.entries().for_each(|(k, v)| {
-            map.insert(String::from(k), v.as_i32().unwrap());
-        });
-        GCounter { values: map }
+        for (k, v) in jv.entries() {
+            let value = v
+                .as_i32()
+                .ok_or_else(|| malformed_request(format!("Invalid counter value for {}", k)))?;
+            map.insert(String::from(k), value);
+        }
+        Ok(GCounter { values: map })
     }
 
     pub fn read(&self) -> i32 {
         self.values.iter().fold(0, |sum, (_, v)| sum + v)
     }
 
-    pub fn merge(&mut self, other: GCounter) {
+    pub fn merge(&mut self, other: &GCounter) {
         other.values.iter().for_each(|(k, v1)| {
             let my_val = self.values.get(k);
             match my_val {
@@ -50,7 +54,7 @@ impl GCounter {
     }
 
     pub fn add(&mut self, node_id: String, delta: i32) {
-        let value = self.values.get(&node_id).get_or_insert(&0).clone();
+        let value = *self.values.get(&node_id).unwrap_or(&0);
         self.values.insert(node_id, value + delta);
     }
 }
@@ -1,4 +1,5 @@
 use json::{object, JsonValue};
+use shared_lib::error::DefiniteError;
 
 use super::g_counter::GCounter;
 
@@ -15,11 +16,11 @@ impl PnCounter {
         }
     }
 
-    pub fn from_json(json: &JsonValue) -> PnCounter {
-        PnCounter {
-            inc: GCounter::from_json(&json["inc"]),
-            dec: GCounter::from_json(&json["dec"]),
-        }
+    pub fn from_json(json: &JsonValue) -> Result<PnCounter, DefiniteError> {
+        Ok(PnCounter {
+            inc: GCounter::from_json(&json["inc"])?,
+            dec: GCounter::from_json(&json["dec"])?,
+        })
     }
 
     pub fn to_json(&self) -> JsonValue {
@@ -30,9 +31,9 @@ impl PnCounter {
         self.inc.read() - self.dec.read()
     }
 
-    pub fn merge(&mut self, other: PnCounter) {
-        self.inc.merge(other.inc);
-        self.dec.merge(other.dec);
+    pub fn merge(&mut self, other: &PnCounter) {
+        self.inc.merge(&other.inc);
+        self.dec.merge(&other.dec);
     }
 
     pub fn add(&mut self, node_id: String, delta: i32) {
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use json::{object, JsonValue};
+use shared_lib::rpc::call_with;
+
+use crate::states::maelstrom_node_state::MaelstromState;
+
+const GOSSIP_INTERVAL: Duration = Duration::from_millis(100);
+const GOSSIP_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Sends each neighbor the broadcasts it has not acknowledged, one round in flight per
+/// neighbor. Unacknowledged broadcasts go out again on a later round, so a partitioned
+/// neighbor catches up once it heals.
+pub fn start(state: &'static MaelstromState) {
+    state.scheduler().every(GOSSIP_INTERVAL, move || {
+        if !state.is_running() {
+            return;
+        }
+        let mut peers = state.neighbors();
+        if peers.is_empty() {
+            peers = state.other_nodes();
+        }
+        peers.iter().for_each(|peer| gossip(state, peer));
+    });
+}
+
+fn gossip(state: &'static MaelstromState, peer: &str) {
+    let messages = state.unacknowledged(peer);
+    if messages.is_empty() || !state.start_gossip(peer) {
+        return;
+    }
+    let mut body = object! {type: "gossip", messages: messages};
+    let peer_id = peer.to_string();
+    call_with(state, &mut body, peer, GOSSIP_TIMEOUT, move |response: Option<JsonValue>| {
+        if let Some(response) = response {
+            if response["body"]["type"] == "gossip_ok" {
+                let acked: Vec<i32> = response["body"]["messages"].members().filter_map(|jv| jv.as_i32()).collect();
+                state.acknowledge(&peer_id, &acked);
+            }
+        }
+        state.finish_gossip(&peer_id);
+    });
+}
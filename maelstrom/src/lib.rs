@@ -0,0 +1,56 @@
+pub mod counters;
+pub mod gossip;
+pub mod lin_kv_service;
+pub mod message_handlers;
+pub mod messages;
+pub mod replicator;
+pub mod states;
+
+use std::time::Duration;
+use shared_lib::dedup::DEFAULT_DEDUP_WINDOW;
+use shared_lib::message_handlers::stats_handler::StatsHandler;
+use shared_lib::middleware::{CatchPanics, Dedup, RequestLogging, Timing};
+use shared_lib::router::{Handlers, Router};
+use crate::lin_kv_service::LinKvService;
+use crate::message_handlers::{
+    add_handler::AddHandler, broadcast_handler::BroadcastHandler,
+    broadcast_read_handler::BroadcastReadHandler, echo_handler::EchoHandler,
+    gossip_handler::GossipHandler, init_handler::InitHandler, read_handler::ReadHandler,
+    replicate_handler::ReplicateHandler, topology_handler::TopologyHandler, txn_handler::TxnHandler,
+};
+use crate::states::maelstrom_node_state::MaelstromState;
+
+fn router() -> Router<MaelstromState> {
+    Router::init()
+        .with(RequestLogging {})
+        .with(Timing::init(Duration::from_millis(500)))
+        .with(CatchPanics {})
+        .with(Dedup::init(DEFAULT_DEDUP_WINDOW))
+}
+
+/// The echo, counter and txn workloads; `read` returns the counter. Needs
+/// `replicator::send_values`.
+pub fn handlers(lin_kv_service: &'static LinKvService) -> Handlers<MaelstromState> {
+    router()
+        .route("init", InitHandler::init(lin_kv_service))
+        .route("echo", EchoHandler {})
+        .route("read", ReadHandler {})
+        .route("topology", TopologyHandler {})
+        .route("add", AddHandler {})
+        .route("replicate", ReplicateHandler {})
+        .route("txn", TxnHandler::init(lin_kv_service))
+        .route("stats", StatsHandler {})
+        .build()
+}
+
+/// The broadcast workload; `read` returns the broadcasts seen. Needs `gossip::start`.
+pub fn broadcast_handlers() -> Handlers<MaelstromState> {
+    router()
+        .route("init", InitHandler::without_kv())
+        .route("topology", TopologyHandler {})
+        .route("broadcast", BroadcastHandler {})
+        .route("gossip", GossipHandler {})
+        .route("read", BroadcastReadHandler {})
+        .route("stats", StatsHandler {})
+        .build()
+}
@@ -1,22 +1,21 @@
 use std::{
     collections::HashMap,
-    io::{stderr, Write},
-    sync::{mpsc::sync_channel, Mutex},
-    thread,
+    sync::Mutex,
     time::Duration,
 };
 
-use json::{JsonValue, object, stringify};
-use shared_lib::error::DefiniteError;
-use shared_lib::node_state::NodeState;
-use shared_lib::rpc::{retry_rpc, send_rpc};
+use json::JsonValue;
+use shared_lib::error::{txn_conflict, DefiniteError, ErrorCode};
+use shared_lib::retry::RetryPolicy;
+use shared_lib::services::{KvClient, LIN_KV};
+use shared_lib::logging::Level;
 use crate::states::{kv_thunk::KVValue, maelstrom_node_state::MaelstromState, serializable_map::SerializableMap, thunk::Thunk};
-use std::borrow::{Borrow, BorrowMut};
 
 pub struct LinKvService {
     state: &'static MaelstromState,
     cache: Mutex<HashMap<String, JsonValue>>,
     root: Mutex<Thunk<SerializableMap>>,
+    kv: KvClient<'static>,
 }
 
 impl LinKvService {
@@ -29,6 +28,11 @@ impl LinKvService {
                 Some(SerializableMap::from_json(&JsonValue::new_object())),
                 false,
             )),
+            kv: KvClient::lin_kv(state).with_retry(
+                RetryPolicy::init(LIN_KV)
+                    .with_rpc_timeout(Duration::from_millis(1000))
+                    .with_deadline(Duration::from_millis(5000)),
+            ),
         }
     }
 
@@ -36,59 +40,50 @@ impl LinKvService {
         self.root.lock().unwrap().clone()
     }
 
-    pub fn update_root(&self) {
+    pub fn update_root(&self) -> Result<(), DefiniteError> {
         let mut root = self.root.lock().unwrap();
-        let jv = retry_rpc(self.state, object! {type: "read", key: "root"}.borrow_mut());
-        *root = Thunk::init(jv["value"].to_string(), None, true);
+        let root_id: JsonValue = self.kv.read("root")?;
+        *root = Thunk::init(root_id.to_string(), None, true);
+        Ok(())
     }
 
     pub fn init_root(&self) -> Thunk<SerializableMap> {
         let map = SerializableMap::init();
         let thunk = Thunk::init(self.state.next_thunk_id(), Some(map), false);
-        thunk.save(self);
-        send_rpc(self.state, &mut object! {type: "write", key: "root", value: thunk.id.clone()}, "lin-kv");
+        let _ = thunk.save(self);
+        let _ = self.kv.write("root", thunk.id.clone());
         let mut root = self.root.lock().unwrap();
         *root = thunk.clone();
         thunk
     }
 
     pub fn cas_root(&self, original_id: String, new_id: String) -> Result<(), DefiniteError> {
-        let response = send_rpc(self.state,
-            &mut object! {type: "cas", key: "root", from: original_id.clone(), to: new_id, create_if_not_exists: true},
-        "lin-kv").unwrap();
-        if response["body"]["type"].to_string() != "cas_ok" {
-            stderr()
-                .write_all(format!("Cas failed to update root at {}\n", original_id).as_bytes());
-            return Err(shared_lib::error::txn_conflict("cas root failed".to_string()));
-        }
-        Ok(())
+        self.kv.cas("root", original_id.clone(), new_id, true).map_err(|error| {
+            self.state.log(Level::Info, "maelstrom::lin_kv").log(&format!("Cas failed to update root at {}: {}", original_id, error));
+            match error.error_code() {
+                ErrorCode::PreconditionFailed => txn_conflict("cas root failed".to_string()),
+                _ => error,
+            }
+        })
     }
 
-    pub fn read_thunk_json<T: KVValue>(&self, thunk: &Thunk<T>) -> JsonValue {
+    pub fn read_thunk_json<T: KVValue>(&self, thunk: &Thunk<T>) -> Result<JsonValue, DefiniteError> {
         let mut cache = self.cache.lock().unwrap();
         if cache.contains_key(&thunk.id) {
             let value = cache.get(&thunk.id).unwrap();
-            stderr().write_all(
-                format!(
-                    "Reading {}, from cache {:?}\n",
-                    thunk.id.clone(),
-                    value.clone()
-                )
-                    .as_bytes(),
-            );
-            return value.clone();
+            self.state.log(Level::Trace, "maelstrom::lin_kv").log(&format!("Reading {}, from cache {:?}", thunk.id.clone(), value.clone()));
+            return Ok(value.clone());
         }
-        let json = retry_rpc(self.state, object! {type: "read", key: thunk.id.clone()}.borrow_mut())["value"].clone();
+        let json: JsonValue = self.kv.read(thunk.id.clone())?;
         cache.insert(thunk.id.clone(), json.clone());
-        json
+        Ok(json)
     }
 
-    pub fn save_thunk<T: KVValue>(&self, thunk: &Thunk<T>) -> JsonValue {
-        let thunk_json = thunk.value(self).to_json();
+    pub fn save_thunk<T: KVValue>(&self, thunk: &Thunk<T>) -> Result<(), DefiniteError> {
+        let thunk_json = thunk.value(self)?.to_json();
         let mut cache = self.cache.lock().unwrap();
         cache.insert(thunk.id.clone(), thunk_json.clone());
-        let mut request = object! {type: "write", key: thunk.id.clone(), value: thunk_json};
-        send_rpc(self.state, &mut request, "lin-kv").unwrap()
+        self.kv.write(thunk.id.clone(), thunk_json)
     }
 
     pub fn new_id(&self) -> String {
@@ -1,5 +1,8 @@
 pub mod add_handler;
+pub mod broadcast_handler;
+pub mod broadcast_read_handler;
 pub mod echo_handler;
+pub mod gossip_handler;
 pub mod init_handler;
 pub mod read_handler;
 pub mod replicate_handler;
@@ -1,18 +1,19 @@
-use json::{object, JsonValue};
-use shared_lib::{error::MaelstromError, message_handler::MessageHandler};
+use shared_lib::{error::MaelstromError, message::Message, message_handler::RequestHandler};
+use crate::messages::{Add, AddOk};
 use crate::states::maelstrom_node_state::MaelstromState;
 
 pub struct AddHandler {}
 
-impl MessageHandler<MaelstromState> for AddHandler {
+impl RequestHandler<MaelstromState> for AddHandler {
+    type Request = Add;
+    type Response = AddOk;
 
     fn make_response_body(
         &self,
-        message: &JsonValue,
+        message: &Message<Add>,
         curr_state: &MaelstromState,
-    ) -> Result<JsonValue, MaelstromError> {
-        let delta = message["body"]["delta"].as_i32().unwrap();
-        curr_state.new_message(delta);
-        Ok(object! {type: "add_ok"})
+    ) -> Result<AddOk, MaelstromError> {
+        curr_state.new_message(message.body.delta);
+        Ok(AddOk {})
     }
 }
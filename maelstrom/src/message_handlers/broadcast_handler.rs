@@ -0,0 +1,19 @@
+use shared_lib::{error::MaelstromError, message::Message, message_handler::RequestHandler};
+use crate::messages::{Broadcast, BroadcastOk};
+use crate::states::maelstrom_node_state::MaelstromState;
+
+pub struct BroadcastHandler {}
+
+impl RequestHandler<MaelstromState> for BroadcastHandler {
+    type Request = Broadcast;
+    type Response = BroadcastOk;
+
+    fn make_response_body(
+        &self,
+        message: &Message<Broadcast>,
+        curr_state: &MaelstromState,
+    ) -> Result<BroadcastOk, MaelstromError> {
+        curr_state.add_broadcasts(&[message.body.message], None);
+        Ok(BroadcastOk {})
+    }
+}
@@ -0,0 +1,18 @@
+use shared_lib::{error::MaelstromError, message::Message, message_handler::RequestHandler};
+use crate::messages::{BroadcastReadOk, Read};
+use crate::states::maelstrom_node_state::MaelstromState;
+
+pub struct BroadcastReadHandler {}
+
+impl RequestHandler<MaelstromState> for BroadcastReadHandler {
+    type Request = Read;
+    type Response = BroadcastReadOk;
+
+    fn make_response_body(
+        &self,
+        _message: &Message<Read>,
+        curr_state: &MaelstromState,
+    ) -> Result<BroadcastReadOk, MaelstromError> {
+        Ok(BroadcastReadOk { messages: curr_state.read_broadcasts() })
+    }
+}
@@ -1,16 +1,18 @@
-use json::{object, JsonValue};
-use shared_lib::{error::MaelstromError, message_handler::MessageHandler};
-use crate::{states::maelstrom_node_state::MaelstromState};
+use shared_lib::{error::MaelstromError, message::Message, message_handler::RequestHandler};
+use crate::messages::{Echo, EchoOk};
+use crate::states::maelstrom_node_state::MaelstromState;
 
 pub struct EchoHandler {}
 
-impl MessageHandler<MaelstromState> for EchoHandler {
+impl RequestHandler<MaelstromState> for EchoHandler {
+    type Request = Echo;
+    type Response = EchoOk;
 
     fn make_response_body(
         &self,
-        message: &JsonValue,
+        message: &Message<Echo>,
         _curr_state: &MaelstromState,
-    ) -> Result<JsonValue, MaelstromError> {
-        Ok(object! {type: "echo_ok", echo: message["body"]["echo"].clone()})
+    ) -> Result<EchoOk, MaelstromError> {
+        Ok(EchoOk { echo: message.body.echo.clone() })
     }
 }
@@ -0,0 +1,19 @@
+use shared_lib::{error::MaelstromError, message::Message, message_handler::RequestHandler};
+use crate::messages::{Gossip, GossipOk};
+use crate::states::maelstrom_node_state::MaelstromState;
+
+pub struct GossipHandler {}
+
+impl RequestHandler<MaelstromState> for GossipHandler {
+    type Request = Gossip;
+    type Response = GossipOk;
+
+    fn make_response_body(
+        &self,
+        message: &Message<Gossip>,
+        curr_state: &MaelstromState,
+    ) -> Result<GossipOk, MaelstromError> {
+        curr_state.add_broadcasts(&message.body.messages, Some(&message.src));
+        Ok(GossipOk { messages: message.body.messages.clone() })
+    }
+}
@@ -1,38 +1,50 @@
-use json::{object, JsonValue};
-use shared_lib::{error::MaelstromError, message_handler::MessageHandler, message_utils::get_body};
+use shared_lib::{error::MaelstromError, message_handler::RequestHandler};
+use shared_lib::message::{Init, InitOk, Message};
 use crate::{
     lin_kv_service::LinKvService,
     states::maelstrom_node_state::MaelstromState,
 };
 
 pub struct InitHandler<'a> {
-    kv_service: &'a LinKvService,
+    kv_service: Option<&'a LinKvService>,
 }
 
 impl InitHandler<'_> {
-    pub fn init(service: &LinKvService) -> InitHandler {
+    pub fn init(service: &LinKvService) -> InitHandler<'_> {
         InitHandler {
-            kv_service: service,
+            kv_service: Some(service),
+        }
+    }
+
+    /// For workloads that keep nothing in `lin-kv`.
+    pub fn without_kv() -> InitHandler<'static> {
+        InitHandler {
+            kv_service: None,
         }
     }
 }
 
-impl MessageHandler<MaelstromState> for InitHandler<'_> {
+impl RequestHandler<MaelstromState> for InitHandler<'_> {
+    type Request = Init;
+    type Response = InitOk;
 
     fn make_response_body(
         &self,
-        message: &json::JsonValue,
+        message: &Message<Init>,
         curr_state: &MaelstromState,
-    ) -> Result<JsonValue, MaelstromError> {
-        let body = get_body(message);
-        curr_state.set_node_id(body["node_id"].to_string());
+    ) -> Result<InitOk, MaelstromError> {
+        let body = &message.body;
+        curr_state.set_node_id(body.node_id.clone());
+        curr_state.init_id_gen(body.node_id.clone());
         curr_state.set_other_node_ids(
-            body["node_ids"]
-                .members()
-                .map(|jv| jv.as_str().unwrap())
+            body.node_ids
+                .iter()
+                .map(|id| id.as_str())
                 .collect(),
         );
-        self.kv_service.init_root();
-        Ok(object! {type: "init_ok"})
+        if let Some(kv_service) = self.kv_service {
+            kv_service.init_root();
+        }
+        Ok(InitOk {})
     }
 }
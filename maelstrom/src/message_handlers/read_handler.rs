@@ -1,17 +1,19 @@
-use json::{object, JsonValue};
-use shared_lib::{error::MaelstromError, message_handler::MessageHandler};
+use shared_lib::{error::MaelstromError, message::Message, message_handler::RequestHandler};
+use crate::messages::{Read, ReadOk};
 use crate::states::maelstrom_node_state::MaelstromState;
 
 pub struct ReadHandler {}
 
-impl MessageHandler<MaelstromState> for ReadHandler {
+impl RequestHandler<MaelstromState> for ReadHandler {
+    type Request = Read;
+    type Response = ReadOk;
 
     fn make_response_body(
         &self,
-        _message: &JsonValue,
+        _message: &Message<Read>,
         curr_state: &MaelstromState,
-    ) -> Result<JsonValue, MaelstromError> {
+    ) -> Result<ReadOk, MaelstromError> {
         let curr_value = curr_state.read_counters();
-        Ok(object!(type: "read_ok", value: JsonValue::from(curr_value)))
+        Ok(ReadOk { value: curr_value })
     }
 }
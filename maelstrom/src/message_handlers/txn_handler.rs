@@ -1,15 +1,9 @@
-use std::{
-    borrow::BorrowMut,
-    io::{stderr, Write},
-    thread,
-    time::Duration,
-};
+use std::time::Duration;
 
-use json::{array, object, JsonValue};
-use rand::prelude::ThreadRng;
-use shared_lib::{error::{MaelstromError, DefiniteError}, message_handler::MessageHandler};
+use shared_lib::{error::{MaelstromError, DefiniteError, ErrorCode}, message::Message, message_handler::RequestHandler};
 use crate::{
     lin_kv_service::LinKvService,
+    messages::{Txn, TxnOk, TxnOp},
     states::{maelstrom_node_state::MaelstromState, serializable_map::SerializableMap, thunk::Thunk},
 };
 
@@ -18,23 +12,25 @@ pub struct TxnHandler<'a> {
 }
 
 impl TxnHandler<'_> {
-    pub fn init(service: &LinKvService) -> TxnHandler {
+    pub fn init(service: &LinKvService) -> TxnHandler<'_> {
         TxnHandler {
             kv_service: service,
         }
     }
 }
 
-impl MessageHandler<MaelstromState> for TxnHandler<'_> {
+impl RequestHandler<MaelstromState> for TxnHandler<'_> {
+    type Request = Txn;
+    type Response = TxnOk;
 
     fn make_response_body(
         &self,
-        message: &json::JsonValue,
+        message: &Message<Txn>,
         curr_state: &MaelstromState,
-    ) -> Result<JsonValue, MaelstromError> {
-        let txns = self.handle_txns(curr_state, &message["body"]["txn"]);
-        txns.map(|txn| object! {type: "txn_ok", txn: txn})
-            .map_err(|s| self.make_error(message, s))
+    ) -> Result<TxnOk, MaelstromError> {
+        let txns = self.handle_txns(curr_state, &message.body.txn);
+        txns.map(|txn| TxnOk { txn })
+            .map_err(|s| message.error(s))
     }
 }
 
@@ -42,82 +38,50 @@ impl TxnHandler<'_> {
     fn handle_txns(
         &self,
         curr_state: &MaelstromState,
-        txns: &JsonValue,
-    ) -> Result<JsonValue, DefiniteError> {
-        let mut arr = JsonValue::new_array();
+        txns: &[TxnOp],
+    ) -> Result<Vec<TxnOp>, DefiniteError> {
+        let mut arr = Vec::new();
         let thunk = self.kv_service.read_root();
-        let mut map = thunk.value(self.kv_service);
-        for txn_json in txns.members() {
-            let txn = parse_txn(txn_json).unwrap();
-            let txn2 = self.execute_txn(txn, &mut map);
+        let mut map = thunk.value(self.kv_service)?;
+        for txn in txns {
+            let txn2 = self.execute_txn(txn, &mut map)?;
             arr.push(txn2);
         }
-        let save_res = map.save_thunks(self.kv_service);
-        if save_res.is_err() {
-            return Err(save_res.err().unwrap());
-        }
+        map.save_thunks(self.kv_service)?;
         let new_id = if map.has_changed() {
             let new_thunk = Thunk::init(curr_state.next_thunk_id(), Some(map), false);
-            let save_res = new_thunk.save(self.kv_service);
-            if save_res.is_err() {
-                return Err(save_res.err().unwrap());
-            }
+            new_thunk.save(self.kv_service)?;
             new_thunk.id
         } else {
             thunk.id.clone()
         };
-        let cas_res = self.kv_service.cas_root(thunk.id.clone(), new_id);
-        if cas_res.is_err() {
-            random_sleep();
-            self.kv_service.update_root();
-            return self.handle_txns(curr_state, txns);
-        }
-        Ok(arr)
-    }
-
-    fn make_error(&self, message: &JsonValue, error: DefiniteError) -> MaelstromError {
-        MaelstromError {
-            in_reply_to: (message["body"]["msg_id"].as_i32().unwrap()),
-            error: error,
+        match self.kv_service.cas_root(thunk.id.clone(), new_id) {
+            Ok(()) => Ok(arr),
+            // Only a lost race is safe to retry; after a timeout the root may already have moved.
+            Err(error) if error.error_code() == ErrorCode::TxnConflict => {
+                random_sleep(curr_state);
+                self.kv_service.update_root()?;
+                self.handle_txns(curr_state, txns)
+            }
+            Err(error) => Err(error),
         }
     }
 
-    fn execute_txn(&self, txn: TxnOp, map: &mut SerializableMap) -> JsonValue {
-        match txn {
-            TxnOp::Read(k) => {
-                let v = map.read(k, self.kv_service);
-                array!["r", k, v]
+    fn execute_txn(&self, txn: &TxnOp, map: &mut SerializableMap) -> Result<TxnOp, DefiniteError> {
+        match *txn {
+            TxnOp::Read(k, _) => {
+                let v = map.read(k, self.kv_service)?;
+                Ok(TxnOp::Read(k, v))
             }
             TxnOp::Append(k, v) => {
-                map.append(self.kv_service, k, v);
-                array!["append", k, v]
+                map.append(self.kv_service, k, v)?;
+                Ok(TxnOp::Append(k, v))
             }
         }
     }
 }
 
-fn random_sleep() {
-    let r = 50 + rand::random::<u64>() % 950;
-    thread::sleep(Duration::from_millis(r));
-}
-
-fn parse_txn(txn: &JsonValue) -> Option<TxnOp> {
-    let op = txn[0].as_str().unwrap();
-    match op {
-        "r" => Some(TxnOp::Read(txn[1].as_i32().unwrap())),
-        "append" => Some(TxnOp::Append(
-            txn[1].as_i32().unwrap(),
-            txn[2].as_i32().unwrap(),
-        )),
-        _ => {
-            stderr().write_all(format!("Received unknown transaction {:?}\n", txn).as_bytes());
-            return None;
-        }
-    }
-}
-
-#[derive(Debug)]
-enum TxnOp {
-    Read(i32),
-    Append(i32, i32),
+fn random_sleep(curr_state: &MaelstromState) {
+    let r = curr_state.random().gen_range(50..1000);
+    curr_state.clock().sleep(Duration::from_millis(r));
 }
@@ -0,0 +1,275 @@
+use json::{array, object, JsonValue};
+use shared_lib::error::{malformed_request, DefiniteError};
+use shared_lib::message::{i32_array_field, i32_field, Body};
+use shared_lib::topology;
+
+use crate::counters::pn_counter::PnCounter;
+
+pub struct Echo {
+    pub echo: JsonValue,
+}
+
+impl Body for Echo {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(Echo { echo: body["echo"].clone() })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "echo", echo: self.echo.clone()}
+    }
+}
+
+pub struct EchoOk {
+    pub echo: JsonValue,
+}
+
+impl Body for EchoOk {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(EchoOk { echo: body["echo"].clone() })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "echo_ok", echo: self.echo.clone()}
+    }
+}
+
+pub struct Topology {
+    pub topology: topology::Topology,
+}
+
+impl Body for Topology {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        topology::Topology::from_json(&body["topology"])
+            .map(|topology| Topology { topology })
+            .ok_or_else(|| malformed_request("Expected field `topology` to map node ids to arrays of node ids".to_string()))
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "topology", topology: self.topology.to_json()}
+    }
+}
+
+pub struct TopologyOk {}
+
+impl Body for TopologyOk {
+    fn from_json(_body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(TopologyOk {})
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "topology_ok"}
+    }
+}
+
+pub struct Read {}
+
+impl Body for Read {
+    fn from_json(_body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(Read {})
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "read"}
+    }
+}
+
+pub struct ReadOk {
+    pub value: i32,
+}
+
+impl Body for ReadOk {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(ReadOk { value: i32_field(body, "value")? })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "read_ok", value: self.value}
+    }
+}
+
+/// The broadcast workload's answer to `read`: every broadcast the node has seen.
+pub struct BroadcastReadOk {
+    pub messages: Vec<i32>,
+}
+
+impl Body for BroadcastReadOk {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(BroadcastReadOk { messages: i32_array_field(body, "messages")? })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "read_ok", messages: self.messages.clone()}
+    }
+}
+
+pub struct Broadcast {
+    pub message: i32,
+}
+
+impl Body for Broadcast {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(Broadcast { message: i32_field(body, "message")? })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "broadcast", message: self.message}
+    }
+}
+
+pub struct BroadcastOk {}
+
+impl Body for BroadcastOk {
+    fn from_json(_body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(BroadcastOk {})
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "broadcast_ok"}
+    }
+}
+
+/// Broadcasts passed between neighbors; the reply lists the ones taken in.
+pub struct Gossip {
+    pub messages: Vec<i32>,
+}
+
+impl Body for Gossip {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(Gossip { messages: i32_array_field(body, "messages")? })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "gossip", messages: self.messages.clone()}
+    }
+}
+
+pub struct GossipOk {
+    pub messages: Vec<i32>,
+}
+
+impl Body for GossipOk {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(GossipOk { messages: i32_array_field(body, "messages")? })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "gossip_ok", messages: self.messages.clone()}
+    }
+}
+
+pub struct Add {
+    pub delta: i32,
+}
+
+impl Body for Add {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(Add { delta: i32_field(body, "delta")? })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "add", delta: self.delta}
+    }
+}
+
+pub struct AddOk {}
+
+impl Body for AddOk {
+    fn from_json(_body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(AddOk {})
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "add_ok"}
+    }
+}
+
+pub struct Replicate {
+    pub value: PnCounter,
+}
+
+impl Body for Replicate {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(Replicate { value: PnCounter::from_json(&body["value"])? })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "replicate", value: self.value.to_json()}
+    }
+}
+
+#[derive(Debug)]
+pub enum TxnOp {
+    Read(i32, Option<Vec<i32>>),
+    Append(i32, i32),
+}
+
+impl TxnOp {
+    fn from_json(txn: &JsonValue) -> Result<TxnOp, DefiniteError> {
+        let key = txn[1].as_i32();
+        match (txn[0].as_str(), key) {
+            (Some("r"), Some(k)) => Ok(TxnOp::Read(k, read_values(&txn[2]))),
+            (Some("append"), Some(k)) => txn[2]
+                .as_i32()
+                .map(|v| TxnOp::Append(k, v))
+                .ok_or_else(|| malformed_request(format!("Invalid append value in {}", txn))),
+            _ => Err(malformed_request(format!("Received unknown transaction {}", txn))),
+        }
+    }
+
+    fn to_json(&self) -> JsonValue {
+        match self {
+            TxnOp::Read(k, v) => array!["r", *k, v.clone()],
+            TxnOp::Append(k, v) => array!["append", *k, *v],
+        }
+    }
+}
+
+fn read_values(values: &JsonValue) -> Option<Vec<i32>> {
+    if !values.is_array() {
+        return None;
+    }
+    values.members().map(|jv| jv.as_i32()).collect()
+}
+
+pub struct Txn {
+    pub txn: Vec<TxnOp>,
+}
+
+impl Body for Txn {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        parse_txn_ops(&body["txn"]).map(|txn| Txn { txn })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "txn", txn: txn_ops_to_json(&self.txn)}
+    }
+}
+
+pub struct TxnOk {
+    pub txn: Vec<TxnOp>,
+}
+
+impl Body for TxnOk {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        parse_txn_ops(&body["txn"]).map(|txn| TxnOk { txn })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "txn_ok", txn: txn_ops_to_json(&self.txn)}
+    }
+}
+
+fn parse_txn_ops(txns: &JsonValue) -> Result<Vec<TxnOp>, DefiniteError> {
+    if !txns.is_array() {
+        return Err(malformed_request("Expected field `txn` to be an array".to_string()));
+    }
+    txns.members().map(TxnOp::from_json).collect()
+}
+
+fn txn_ops_to_json(txns: &[TxnOp]) -> JsonValue {
+    let mut arr = JsonValue::new_array();
+    for txn in txns {
+        let _ = arr.push(txn.to_json());
+    }
+    arr
+}
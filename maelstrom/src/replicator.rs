@@ -1,19 +1,23 @@
-use std::{thread, time::Duration};
+use std::time::Duration;
 
-use json::{object, stringify, JsonValue};
+use json::{object, JsonValue};
 
-use crate::states::node_state::NodeState;
+use crate::states::maelstrom_node_state::MaelstromState;
 
-pub fn send_values(state: &'static NodeState) {
-    thread::spawn(move || loop {
-        thread::sleep(Duration::from_millis(5000));
+const REPLICATION_INTERVAL: Duration = Duration::from_millis(5000);
+
+pub fn send_values(state: &'static MaelstromState) {
+    state.scheduler().every(REPLICATION_INTERVAL, move || {
+        if !state.is_running() {
+            return;
+        }
         let values = state.counters_state();
         let body = object! {type: "replicate", value: values};
         let common_message = object! {body: body, src: state.node_id()};
         state.other_nodes().iter().for_each(|node_id| {
             let mut message = common_message.clone();
             message["dest"] = JsonValue::from(node_id.clone());
-            state.get_channel().send(stringify(message));
+            state.send_message(message);
         });
     });
 }
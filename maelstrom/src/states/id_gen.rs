@@ -8,7 +8,7 @@ pub struct IdGenerator {
 impl IdGenerator {
     pub fn init(node_id: String) -> IdGenerator {
         IdGenerator {
-            node_id: node_id,
+            node_id,
             i: Mutex::new(0),
         }
     }
@@ -14,7 +14,7 @@ impl KVValue for Vec<i32> {
     fn to_json(&self) -> JsonValue {
         let mut arr = JsonValue::new_array();
         self.iter().for_each(|i| {
-            arr.push(*i);
+            let _ = arr.push(*i);
         });
         arr
     }
@@ -1,44 +1,49 @@
 use super::id_gen::IdGenerator;
 use crate::counters::pn_counter::PnCounter;
 use json::JsonValue;
-use std::{
-    cell::RefCell,
-    collections::HashMap,
-    sync::{mpsc::SyncSender, Mutex, RwLock},
-};
-use shared_lib::{node_state::NodeState};
-use std::sync::RwLockWriteGuard;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{mpsc::SyncSender, Mutex, RwLock};
+use shared_lib::node_state::NodeState;
 use std::ops::Deref;
 
 pub struct MaelstromState {
     node_state : NodeState,
-    neighbors: RwLock<Vec<String>>,
 
     counters: RwLock<PnCounter>,
     id_gen: RwLock<Option<IdGenerator>>,
+    broadcasts: RwLock<BTreeSet<i32>>,
+    known_by: Mutex<HashMap<String, BTreeSet<i32>>>,
+    gossiping_to: Mutex<HashSet<String>>,
 }
 
 impl MaelstromState {
     pub fn init(response_channel: SyncSender<String>) -> MaelstromState {
         MaelstromState {
             node_state: NodeState::init(response_channel),
-            neighbors: RwLock::new(Vec::new()),
             counters: RwLock::new(PnCounter::init()),
             id_gen: RwLock::new(None),
+            broadcasts: RwLock::new(BTreeSet::new()),
+            known_by: Mutex::new(HashMap::new()),
+            gossiping_to: Mutex::new(HashSet::new()),
         }
     }
 
     pub fn next_thunk_id(&self) -> String {
         let gen = self.id_gen.read().unwrap();
         if gen.is_none() {
-            panic!(format!(
+            panic!(
                 "Tried to get id generator but it has not been inialized. Id is: {}",
                 self.node_id()
-            ));
+            );
         }
         gen.as_ref().unwrap().get_next_id()
     }
 
+    pub fn init_id_gen(&self, node_id: String) {
+        let mut gen = self.id_gen.write().unwrap();
+        gen.replace(IdGenerator::init(node_id));
+    }
+
     pub fn read_counters(&self) -> i32 {
         self.counters.read().unwrap().read()
     }
@@ -47,20 +52,52 @@ impl MaelstromState {
         self.counters.read().unwrap().to_json()
     }
 
-    pub fn replace_topology(&self, new_neighbors: Vec<String>) {
-        let mut vec = self.neighbors.write().unwrap();
-        new_neighbors.iter().for_each(|id| vec.push(id.clone()));
-    }
-
     pub fn new_message(&self, message: i32) {
         let mut counters = self.counters.write().unwrap();
         counters.add(self.node_id(), message);
     }
 
-    pub fn merge_messages(&self, received_values: PnCounter) {
+    pub fn merge_messages(&self, received_values: &PnCounter) {
         let mut counters = self.counters.write().unwrap();
         counters.merge(received_values);
     }
+
+    pub fn read_broadcasts(&self) -> Vec<i32> {
+        self.broadcasts.read().unwrap().iter().copied().collect()
+    }
+
+    /// Adds broadcasts received from a client or, with `from`, from a neighbor that
+    /// then needs not be sent them back.
+    pub fn add_broadcasts(&self, messages: &[i32], from: Option<&str>) {
+        self.broadcasts.write().unwrap().extend(messages.iter().copied());
+        if let Some(from) = from {
+            self.acknowledge(from, messages);
+        }
+    }
+
+    /// The broadcasts `node_id` has not acknowledged yet.
+    pub fn unacknowledged(&self, node_id: &str) -> Vec<i32> {
+        let known_by = self.known_by.lock().unwrap();
+        let broadcasts = self.broadcasts.read().unwrap();
+        match known_by.get(node_id) {
+            Some(known) => broadcasts.difference(known).copied().collect(),
+            None => broadcasts.iter().copied().collect(),
+        }
+    }
+
+    pub fn acknowledge(&self, node_id: &str, messages: &[i32]) {
+        let mut known_by = self.known_by.lock().unwrap();
+        known_by.entry(node_id.to_string()).or_default().extend(messages.iter().copied());
+    }
+
+    /// Marks a gossip round to `node_id` as in flight. Returns false if one already is.
+    pub fn start_gossip(&self, node_id: &str) -> bool {
+        self.gossiping_to.lock().unwrap().insert(node_id.to_string())
+    }
+
+    pub fn finish_gossip(&self, node_id: &str) {
+        self.gossiping_to.lock().unwrap().remove(node_id);
+    }
 }
 
 impl Deref for MaelstromState {
@@ -1,9 +1,6 @@
-use std::{
-    collections::HashMap,
-    io::{stderr, Write},
-};
+use std::collections::HashMap;
 
-use json::{stringify, JsonValue};
+use json::JsonValue;
 use shared_lib::error::DefiniteError;
 use crate::lin_kv_service::LinKvService;
 
@@ -24,7 +21,7 @@ impl KVValue for SerializableMap {
             map.insert(key, Thunk::init(id, None, true));
         }
         SerializableMap {
-            map: map,
+            map,
             has_changed: false
         }
     }
@@ -32,7 +29,7 @@ impl KVValue for SerializableMap {
     fn to_json(&self) -> JsonValue {
         let mut jv = JsonValue::new_object();
         for (k, thunk) in self.map.iter() {
-            jv.insert(&k.to_string(), thunk.id.clone());
+            let _ = jv.insert(&k.to_string(), thunk.id.clone());
         }
         jv
     }
@@ -46,25 +43,23 @@ impl SerializableMap {
         }
     }
 
-    pub fn read(&self, k: i32, service: &LinKvService) -> Option<Vec<i32>> {
-        self.map.get(&k).map(|thunk| thunk.value(service))
+    pub fn read(&self, k: i32, service: &LinKvService) -> Result<Option<Vec<i32>>, DefiniteError> {
+        self.map.get(&k).map(|thunk| thunk.value(service)).transpose()
     }
 
-    pub fn append(&mut self, service: &LinKvService, k: i32, v: i32) {
-        let mut vec = self.read(k, service).unwrap_or(Vec::new());
+    pub fn append(&mut self, service: &LinKvService, k: i32, v: i32) -> Result<(), DefiniteError> {
+        let mut vec = self.read(k, service)?.unwrap_or_default();
         vec.push(v);
         let new_id = service.new_id();
         let thunk = Thunk::init(new_id, Some(vec), false);
         self.map.insert(k, thunk);
         self.has_changed = true;
+        Ok(())
     }
 
     pub fn save_thunks(&self, service: &LinKvService) -> Result<(), DefiniteError> {
-        for (_key, thunk) in &self.map {
-            let save_res = thunk.save(service);
-            if save_res.is_err() {
-                return save_res;
-            }
+        for thunk in self.map.values() {
+            thunk.save(service)?;
         }
         Ok(())
     }
@@ -1,9 +1,5 @@
-use std::{
-    io::{stderr, Write},
-    sync::RwLock,
-};
+use std::sync::RwLock;
 
-use json::JsonValue;
 use shared_lib::error::DefiniteError;
 use crate::lin_kv_service::LinKvService;
 
@@ -19,7 +15,7 @@ pub struct Thunk<T: KVValue> {
 impl<T: KVValue> Clone for Thunk<T> {
     fn clone(&self) -> Self {
         let is_saved = *self.saved.read().unwrap();
-        Thunk::init(self.id.clone(), None, is_saved.clone())
+        Thunk::init(self.id.clone(), None, is_saved)
     }
 }
 
@@ -32,28 +28,27 @@ impl<T: KVValue> Thunk<T> {
         }
     }
 
-    pub fn value(&self, service: &LinKvService) -> T {
+    pub fn value(&self, service: &LinKvService) -> Result<T, DefiniteError> {
         let m_val = self.value.read().unwrap();
         if m_val.is_some() {
-            return m_val.as_ref().unwrap().clone();
+            return Ok(m_val.as_ref().unwrap().clone());
         }
         drop(m_val);
-        let json = service.read_thunk_json(&self);
+        let json = service.read_thunk_json(self)?;
         let val = T::from_json(&json);
         let mut thunk_val = self.value.write().unwrap();
         *thunk_val = Some(val.clone());
-        val
+        Ok(val)
     }
 
     pub fn save(&self, service: &LinKvService) -> Result<(), DefiniteError> {
         if *self.saved.read().unwrap() {
             return Ok(());
         }
-        let result = service.save_thunk(self)["body"]["type"].to_string();
-        if result != "write_ok" {
+        if let Err(error) = service.save_thunk(self) {
             return Err(shared_lib::error::abort(format!(
-                "Failed to save thunk with id {}",
-                self.id
+                "Failed to save thunk with id {}: {}",
+                self.id, error.text
             )));
         }
         let mut saved = self.saved.write().unwrap();
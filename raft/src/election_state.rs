@@ -1,28 +1,24 @@
 use std::time::{Duration, Instant};
-use std::thread::Thread;
-use std::thread;
-use std::sync::{RwLock, Arc, LockResult, RwLockReadGuard, PoisonError};
-use rand::{Rng, thread_rng, random, RngCore};
-use std::io::{stderr, Write};
-use std::ops::{Deref};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 use std::collections::{HashSet, HashMap};
 use json::{object, JsonValue};
-use shared_lib::{node_state::NodeState, rpc::{broadcast_rpc, send_rpc}};
+use shared_lib::rpc::{send_rpc, DEFAULT_RPC_TIMEOUT};
+use shared_lib::quorum;
+use shared_lib::scheduler::TimerId;
 use crate::election_state::State::{FOLLOWER, LEADER, CANDIDATE};
 use crate::raft_node_state::RaftState;
-use std::borrow::Borrow;
-use lazy_static::lazy_static;
-use std::sync::mpsc::{sync_channel, SyncSender, TryIter, TryRecvError, Receiver, channel};
-use shared_lib::stdio::write_log;
+use shared_lib::logging::{Event, Level};
+use shared_lib::message::{Body, Message};
 use shared_lib::message_utils::get_body;
 use std::cmp::max;
 use crate::log::Op;
+use crate::messages::{AppendEntries, AppendEntriesRes, RequestVote, RequestVoteRes};
 use crate::message_handlers::cas_handler::check_cas_result;
 use crate::message_handlers::read_handler::check_read_result;
-use shared_lib::error::MaelstromError;
 use shared_lib::message_handler::construct_error_body;
 use shared_lib::rpc::send_ff;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum State {
     LEADER,
@@ -30,10 +26,12 @@ pub enum State {
     CANDIDATE,
 }
 
+const REPLICATION_INTERVAL: Duration = Duration::from_millis(50);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct ElectionState<'a> {
-    next_election: RwLock<Instant>,
-    step_down: RwLock<Instant>,
-    replication_time: RwLock<Instant>,
+    election_timer: OnceLock<TimerId>,
+    step_down_timer: OnceLock<TimerId>,
     term: RwLock<i32>,
     curr_state: RwLock<State>,
     node_state:  &'a RaftState,
@@ -46,11 +44,10 @@ pub struct ElectionState<'a> {
 }
 
 impl ElectionState<'_> {
-    fn init(state: &RaftState) -> ElectionState {
+    fn init(state: &RaftState) -> ElectionState<'_> {
         ElectionState {
-            next_election: RwLock::new(Instant::now()),
-            step_down: RwLock::new(Instant::now()),
-            replication_time: RwLock::new(Instant::now()),
+            election_timer: OnceLock::new(),
+            step_down_timer: OnceLock::new(),
             term: RwLock::new(0),
             curr_state: RwLock::new(FOLLOWER),
             node_state: state,
@@ -64,11 +61,9 @@ impl ElectionState<'_> {
     }
 
     pub fn reset_election_time(&self) {
-        let mut next_election = self.next_election.write().unwrap();
-        let mut rng = rand::thread_rng();
-        let rand: u64 = rng.gen_range(0..10);
-        let standard_timeout = Duration::new(2, 0);
-        *next_election = Instant::now() + (standard_timeout + Duration::from_secs(rand + 1))
+        if let Some(timer) = self.election_timer.get() {
+            self.node_state.scheduler().reschedule(*timer, election_timeout(self.node_state));
+        }
     }
 
     pub fn commit_index(&self) -> usize {
@@ -81,18 +76,16 @@ impl ElectionState<'_> {
     }
 
     fn reset_step_down_time(&self) {
-        let mut step_down_time = self.step_down.write().unwrap();
-        let mut rng = rand::thread_rng();
-        let rand: u64 = rng.gen_range(0..10);
-        let standard_timeout = Duration::new(2, 0);
-        *step_down_time = Instant::now() + (standard_timeout + Duration::from_secs(rand + 1))
+        if let Some(timer) = self.step_down_timer.get() {
+            self.node_state.scheduler().reschedule(*timer, election_timeout(self.node_state));
+        }
     }
 
     fn advance_term(&self, new_term: i32) -> Result<(), String> {
         let mut curr_term = self.term.write().unwrap();
         if new_term < *curr_term {
-            let error_message = format!("Cannot change term from {} to {}\n", curr_term, new_term);
-            stderr().write(error_message.as_bytes());
+            let error_message = format!("Cannot change term from {} to {}", curr_term, new_term);
+            self.log(Level::Error, *curr_term).log(&error_message);
             return Err(error_message);
         }
         *curr_term = new_term;
@@ -101,23 +94,23 @@ impl ElectionState<'_> {
         Ok(())
     }
 
-    fn become_candidate(&self) -> Vec<Receiver<JsonValue>> {
+    fn become_candidate(&self) -> JsonValue {
         let mut curr_state = self.curr_state.write().unwrap();
         let curr_term = *self.term.read().unwrap();
         *curr_state = CANDIDATE;
-        self.advance_term(curr_term + 1);
+        let _ = self.advance_term(curr_term + 1);
         self.reset_election_time();
         self.reset_step_down_time();
         *self.voted_for.write().unwrap() = Some(self.node_state.node_id());
         *self.leader.write().unwrap() = None;
-        write_log(format!("Becoming candidate at term {}\n", (curr_term.clone() + 1)).as_ref());
+        self.log(Level::Info, curr_term + 1).log("Becoming candidate");
         self.request_votes()
     }
 
     fn become_follower(&self) {
         let mut curr_state = self.curr_state.write().unwrap();
         let curr_term = self.term.read().unwrap();
-        write_log(format!("Becoming follower at term {}\n", curr_term).as_ref());
+        self.log(Level::Info, *curr_term).log("Becoming follower");
         self.reset_election_time();
         *self.voted_for.write().unwrap() = None;
         self.clear_indices();
@@ -129,7 +122,7 @@ impl ElectionState<'_> {
         let mut curr_state = self.curr_state.write().unwrap();
         let curr_term = self.term.read().unwrap();
         if CANDIDATE != *curr_state {
-            write_log(format!("Tried to become leader while state was {:?} at term {}", curr_state, curr_term).as_str());
+            self.log(Level::Warn, *curr_term).log(&format!("Tried to become leader while state was {:?}", curr_state));
             return;
         }
         self.reset_step_down_time();
@@ -141,37 +134,36 @@ impl ElectionState<'_> {
             next_idx.insert(other_node.clone(), current_log_size + 1);
             match_idx.insert(other_node.clone(), 0);
         }
-        write_log(format!("Becoming leader at term {}\n", curr_term).as_ref());
+        self.log(Level::Info, *curr_term).log("Becoming leader");
         *curr_state = LEADER;
     }
 
-    fn request_votes(&self) -> Vec<Receiver<JsonValue>> {
+    fn request_votes(&self) -> JsonValue {
         let candidate_id = self.node_state.node_id();
-        let term = self.term.read().unwrap().clone();
-        let mut request = object! {type: "request_vote",
-                                             term: term,
-                                             candidate_id: candidate_id.clone(),
-                                             last_log_index: self.node_state.log_size(),
-                                             last_log_term: self.node_state.log_last().term
-                                            };
-        broadcast_rpc(self.node_state, &mut request)
+        let term = *self.term.read().unwrap();
+        RequestVote {
+            term,
+            candidate_id,
+            last_log_index: self.node_state.log_size(),
+            last_log_term: self.node_state.log_last().term,
+        }.to_json()
     }
 
     pub(crate) fn maybe_step_down(&self, remote_term: i32) -> bool {
         let term = self.term.write().unwrap();
-        write_log(format!("Might Step down term: {}, remote term {}", term, remote_term).as_str());
+        self.log(Level::Trace, *term).log(&format!("Might step down for remote term {}", remote_term));
         if *term < remote_term {
             drop(term);
-            self.advance_term(remote_term);
+            let _ = self.advance_term(remote_term);
             self.become_follower();
-            write_log("Stepping down");
+            self.log(Level::Info, remote_term).log("Stepping down");
             return true;
         }
         false
     }
 
     pub fn set_leader(&self, leader_id: String) {
-        self.leader.write().unwrap().insert(leader_id);
+        *self.leader.write().unwrap() = Some(leader_id);
     }
 
     pub fn get_leader(&self) -> Option<String> {
@@ -182,10 +174,6 @@ impl ElectionState<'_> {
         *self.term.read().unwrap()
     }
 
-    fn next_election_time(&self) -> Instant {
-        *self.next_election.read().unwrap()
-    }
-
     fn clear_indices(&self) {
         let mut match_idx = self.match_index.write().unwrap();
         match_idx.clear();
@@ -194,10 +182,7 @@ impl ElectionState<'_> {
     }
 
     pub(crate) fn voted_for(&self) -> Option<String> {
-        match self.voted_for.read().unwrap().as_ref() {
-            None => None,
-            Some(str) => Some(str.clone())
-        }
+        self.voted_for.read().unwrap().as_ref().map(|str| str.clone())
     }
 
     pub(crate) fn vote_for(&self, id: String) {
@@ -222,13 +207,13 @@ impl ElectionState<'_> {
         map.insert(node_id.to_string(), i);
     }
 
-    fn vote_granted(&self, body: JsonValue) -> bool {
+    fn vote_granted(&self, body: &RequestVoteRes) -> bool {
         let curr_state = *self.curr_state.read().unwrap();
         let curr_term = self.current_term();
         let result = curr_state == CANDIDATE &&
-            curr_term == body["term"].as_i32().unwrap() &&
-            body["vote_granted"].as_bool().unwrap();
-        write_log(format!("Checking vote, state: {:?} term: {} body_term: {}, granted: {}, result: {}", curr_state, curr_term, body["term"], body["vote_granted"], result).as_str());
+            curr_term == body.term &&
+            body.vote_granted;
+        self.log(Level::Debug, curr_term).log(&format!("Checking vote, state: {:?} body_term: {}, granted: {}, result: {}", curr_state, body.term, body.vote_granted, result));
         result
     }
 
@@ -245,7 +230,7 @@ impl ElectionState<'_> {
                 continue;
             }
             let op = m_op.unwrap();
-            write_log(format!("Applying op: {:?}", op).as_str());
+            self.log(Level::Debug, self.current_term()).log(&format!("Applying op: {:?}", op));
             let (to, response) = match op {
                 Op::CAS { key, from,to, requester , msg_id} => {
                     let cas_result = self.node_state.cas_value(key, from, to);
@@ -263,7 +248,7 @@ impl ElectionState<'_> {
             if self.current_state() == LEADER {
                 match response {
                     Ok(mut jv) => {
-                        write_log(format!("Sending RPC from state machine: {}", jv).as_str());
+                        self.log(Level::Trace, self.current_term()).log(&format!("Sending RPC from state machine: {}", jv));
                         send_ff(self.node_state, &mut jv, &to);
                     }
                     Err(err) => {
@@ -273,36 +258,36 @@ impl ElectionState<'_> {
                 }
             }
         }
-        drop(last_applied);
         *self.last_applied.write().unwrap() = commit_index;
     }
 
     pub(crate) fn current_state(&self) -> State {
-        (*self.curr_state.read().unwrap()).clone()
+        *self.curr_state.read().unwrap() 
     }
 
     fn validate_election(&self, votes: &HashSet<String>) -> bool {
-        write_log(format!("Validating election with votes {:?}", votes).as_str());
+        self.log(Level::Debug, self.current_term()).log(&format!("Validating election with votes {:?}", votes));
         let majority = self.node_state.majority();
-        if majority <= votes.len() as i32 {
+        if majority <= votes.len() {
             self.become_leader();
             return true;
         }
-        return false;
+        false
     }
 
     fn median_commit_index(&self) -> usize {
         let current_match_indices = self.match_index.read().unwrap();
-        let mut values: Vec<&usize> = current_match_indices.values().collect();
+        // The leader has every entry in its own log.
+        let mut values: Vec<usize> = current_match_indices.values().copied().collect();
+        values.push(self.node_state.log_size());
         values.sort();
-        let majority = self.node_state.majority() as usize;
-        *values[(values.len() - majority)]
+        values[values.len() - self.node_state.majority()]
     }
 
     fn advance_commit_index(&self) {
         if self.current_state() == LEADER {
             let n = self.median_commit_index();
-            write_log(format!("Median commit index is: {}", n).as_str());
+            self.log(Level::Trace, self.current_term()).log(&format!("Median commit index is: {}", n));
             if self.commit_index() < n && self.node_state.log_entry(n).unwrap().term == self.current_term() {
                 self.set_commit_index(n);
             }
@@ -310,8 +295,8 @@ impl ElectionState<'_> {
         self.advance_state_machine();
     }
 
-    fn step_down_time(&self) -> Instant {
-        *self.step_down.read().unwrap()
+    fn log(&self, level: Level, term: i32) -> Event {
+        self.node_state.log(level, "raft::election").term(term)
     }
 
     pub fn candidate_id(&self) -> String {
@@ -320,173 +305,139 @@ impl ElectionState<'_> {
 }
 
 
-pub fn start(node_state: &'static RaftState) -> Arc<ElectionState<'static>> {
-    let state = ElectionState::init(node_state);
-    let state_arc = Arc::new(state);
-    let result = Arc::clone(&state_arc);
-    thread::spawn(move || {
-        start_elections(node_state, &state_arc);
-        step_down_loop(&state_arc);
-        replicate_log(state_arc);
-    });
-    result
+fn election_timeout(node_state: &RaftState) -> Duration {
+    let rand: u64 = node_state.random().gen_range(0..10);
+    Duration::new(2, 0) + Duration::from_secs(rand + 1)
 }
 
-fn start_elections(node_state: &'static RaftState, state_arc: &Arc<ElectionState<'static>>) {
-    let arc = Arc::clone(state_arc);
-    thread::spawn(move || {
-        loop {
-            if node_state.is_initialized() {
-                election_loop(&arc);
-            }
-            thread::sleep(Duration::from_millis(10));
-        }
-    });
+pub fn start(node_state: &'static RaftState) -> Arc<ElectionState<'static>> {
+    let state_arc = Arc::new(ElectionState::init(node_state));
+    let scheduler = node_state.scheduler();
+
+    let election_state = Arc::clone(&state_arc);
+    let election_timer = scheduler.every(election_timeout(node_state), move || on_election_timeout(&election_state));
+    let _ = state_arc.election_timer.set(election_timer);
+    scheduler.reschedule(election_timer, Duration::from_millis(50 + node_state.random().gen_range(0..100)));
+
+    let election_state = Arc::clone(&state_arc);
+    let step_down_timer = scheduler.every(election_timeout(node_state), move || on_step_down_timeout(&election_state));
+    let _ = state_arc.step_down_timer.set(step_down_timer);
+
+    let election_state = Arc::clone(&state_arc);
+    let last_replication = Mutex::new(node_state.clock().now());
+    scheduler.every(REPLICATION_INTERVAL, move || replicate_log(&election_state, &last_replication));
+    state_arc
 }
 
-pub fn election_loop(state_arc: &Arc<ElectionState<'static>>) {
-    let election_state = Arc::clone(state_arc);
-    loop {
-        let rand = rand::thread_rng().next_u64() % 100;
-        thread::sleep(Duration::from_millis(50 + rand));
-        let next_election = election_state.next_election_time();
-        let current_state = election_state.current_state();
-        if next_election < Instant::now() && current_state != LEADER {
-            let vote_channels = election_state.become_candidate();
-            count_votes(Arc::clone(state_arc), vote_channels);
-        }
+fn on_election_timeout(state_arc: &Arc<ElectionState<'static>>) {
+    if !state_arc.node_state.wait_until_running(election_timeout(state_arc.node_state)) {
+        return;
+    }
+    if state_arc.current_state() != LEADER {
+        let request = state_arc.become_candidate();
+        count_votes(Arc::clone(state_arc), request);
     }
 }
 
-pub fn step_down_loop(state_arc: &Arc<ElectionState<'static>>) {
-    let election_state = Arc::clone(state_arc);
-    thread::spawn(move || {
-        loop {
-            thread::sleep(Duration::from_millis(100));
-            let current_state = election_state.current_state();
-            let step_down_deadline = election_state.step_down_time();
-            if current_state == LEADER && step_down_deadline < Instant::now() {
-                election_state.become_follower();
-            }
-        }
-    });
+fn on_step_down_timeout(election_state: &ElectionState<'static>) {
+    if election_state.current_state() == LEADER {
+        election_state.become_follower();
+    }
 }
 
-fn count_votes(election_state: Arc<ElectionState<'static>>, receivers: Vec<Receiver<JsonValue>>) {
-    thread::spawn(move || {
+fn count_votes(election_state: Arc<ElectionState<'static>>, request: JsonValue) {
+    election_state.node_state.spawn(move || {
         election_state.reset_step_down_time();
         let mut votes = HashSet::new();
-        let mut have_voted: Vec<usize> = Vec::new();
         votes.insert(election_state.candidate_id());
-        let mut received = 0;
-        let total_votes = receivers.len();
-        while received < total_votes {
-            for (i, receiver) in receivers.iter().enumerate() {
-                if !have_voted.contains(&i) {
-                    let result = receiver.try_recv();
-                    match result {
-                        Ok(msg) => {
-                            let body = &msg["body"];
-                            let stepping_down = election_state.maybe_step_down(body["term"].as_i32().unwrap());
-                            if stepping_down {
-                                return;
-                            }
-                            let vote_granted = election_state.vote_granted(body.clone());
-                            if vote_granted {
-                                votes.insert(msg["src"].to_string());
-                                have_voted.push(i);
-                                received = received + 1;
-                            }
-                        }
-                        Err(error) => {
-                            match error {
-                                TryRecvError::Empty => (),
-                                TryRecvError::Disconnected => {
-                                    write_log("Channel disconnected before response received during election.");
-                                    have_voted.push(i);
-                                    received = received + 1;
-                                }
-                            }
-                        }
-                    }
+        let majority = election_state.node_state.majority();
+        let mut stepped_down = false;
+        let peers = election_state.node_state.other_nodes();
+        quorum::gather(election_state.node_state, &request, &peers, DEFAULT_RPC_TIMEOUT, |reply| {
+            let response = match Message::<RequestVoteRes>::from_json(&reply.message) {
+                Ok(response) => response,
+                Err(error) => {
+                    election_state.log(Level::Warn, election_state.current_term()).log(&format!("Invalid vote response {}: {}", reply.message, error.text));
+                    return false;
                 }
+            };
+            if election_state.maybe_step_down(response.body.term) {
+                stepped_down = true;
+                return true;
             }
-            if votes.len() >= election_state.node_state.majority() as usize {
-                break;
+            if election_state.vote_granted(&response.body) {
+                votes.insert(response.src);
             }
+            votes.len() >= majority
+        });
+        if stepped_down {
+            return;
         }
-        write_log(format!("Have votes: {:?}", votes).as_str());
+        election_state.log(Level::Debug, election_state.current_term()).log(&format!("Have votes: {:?}", votes));
         election_state.validate_election(&votes);
     });
 }
 
-fn replicate_log(election_state: Arc<ElectionState<'static>>) {
-    thread::spawn(move || {
-        let mut last_replication = Instant::now();
-        let min_replication_interval = Duration::from_millis(50);
-        let heartbeat_interval = Duration::from_secs(1);
-        loop {
-            let mut replicated = false;
-            if election_state.current_state() == LEADER {
-                let time_since_replication = Instant::now() - last_replication;
-
-                if time_since_replication > min_replication_interval {
-                    for other_node in election_state.node_state.other_nodes() {
-                        let next_index = election_state.next_index_of_node(&other_node);
-                        let entries = election_state.node_state.log_from_index(next_index);
-                        let entries_len = entries.len();
-                        if entries_len > 0 || heartbeat_interval < time_since_replication {
-                            write_log(format!("Replicating {} to {}", next_index, other_node).as_str());
-                            replicated = true;
-                            let commit_index = election_state.commit_index();
-
-                            let mut message = object! {
-                                type: "append_entries",
-                                term: election_state.current_term(),
-                                leader_id: election_state.node_state.node_id(),
-                                prev_log_index: next_index - 1,
-                                prev_log_term: election_state.node_state.log_entry(next_index - 1).map_or_else(|| 1, |entry| entry.term),
-                                entries: entries,
-                                leader_commit: commit_index
-                            };
-                            let new_arc = election_state.clone();
-                            thread::spawn(move || {
-                                let thread_state = new_arc;
-                                let response = send_rpc(thread_state.node_state, &mut message, &other_node);
-                                if response.is_none() {
-                                    write_log("Append log failed with a RPC timeout");
-                                    return;
-                                }
-                                let response_value = response.unwrap();
-                                let response_body = get_body(&response_value);
-                                if response_body["term"].as_i32().is_none() {
-                                    write_log(format!("Append entries failed with message: {}", response_body["text"].as_str().unwrap()).as_str());
-                                    return;
-                                }
-                                let response_term = response_body["term"].as_i32().unwrap();
-                                thread_state.maybe_step_down(response_term);
-                                if thread_state.current_state() == LEADER && response_term == thread_state.current_term() {
-                                    thread_state.reset_step_down_time();
-                                    if response_body["success"].as_bool().unwrap() {
-                                        let new_next_index = max(next_index + entries_len, thread_state.next_index_of_node(&other_node));
-                                        thread_state.set_node_next_index(&other_node, new_next_index);
-                                        let new_match_index = max(next_index + entries_len - 1, thread_state.match_index_of_node(&other_node));
-                                        thread_state.set_node_match_index(&other_node, new_match_index);
-                                        write_log(format!("Node: {} next index: {} match index: {}", other_node, new_next_index, new_match_index).as_str());
-                                        thread_state.advance_commit_index();
-                                    } else {
-                                        thread_state.set_node_next_index(&other_node, thread_state.next_index_of_node(&other_node) - 1);
-                                    }
-                                }
-                            });
-                        }
+fn replicate_log(election_state: &Arc<ElectionState<'static>>, last_replication: &Mutex<Instant>) {
+    if election_state.current_state() != LEADER {
+        return;
+    }
+    let mut last_replication = last_replication.lock().unwrap();
+    let now = election_state.node_state.clock().now();
+    let time_since_replication = now - *last_replication;
+    let mut replicated = false;
+    for other_node in election_state.node_state.other_nodes() {
+        let next_index = election_state.next_index_of_node(&other_node);
+        let entries = election_state.node_state.log_from_index(next_index);
+        let entries_len = entries.len();
+        if entries_len > 0 || HEARTBEAT_INTERVAL < time_since_replication {
+            election_state.log(Level::Trace, election_state.current_term()).log(&format!("Replicating {} to {}", next_index, other_node));
+            replicated = true;
+            let commit_index = election_state.commit_index();
+
+            let mut message = AppendEntries {
+                term: election_state.current_term(),
+                leader_id: election_state.node_state.node_id(),
+                prev_log_index: next_index - 1,
+                prev_log_term: election_state.node_state.log_entry(next_index - 1).map_or_else(|| 1, |entry| entry.term),
+                entries,
+                leader_commit: commit_index
+            }.to_json();
+            let new_arc = election_state.clone();
+            election_state.node_state.spawn(move || {
+                let thread_state = new_arc;
+                let response = send_rpc(thread_state.node_state, &mut message, &other_node);
+                if response.is_none() {
+                    thread_state.log(Level::Warn, thread_state.current_term()).log("Append log failed with a RPC timeout");
+                    return;
+                }
+                let response_value = response.unwrap();
+                let response_body = match AppendEntriesRes::from_json(get_body(&response_value)) {
+                    Ok(body) => body,
+                    Err(_) => {
+                        thread_state.log(Level::Warn, thread_state.current_term()).log(&format!("Append entries failed with message: {}", get_body(&response_value)["text"]));
+                        return;
+                    }
+                };
+                let response_term = response_body.term;
+                thread_state.maybe_step_down(response_term);
+                if thread_state.current_state() == LEADER && response_term == thread_state.current_term() {
+                    thread_state.reset_step_down_time();
+                    if response_body.success {
+                        let new_next_index = max(next_index + entries_len, thread_state.next_index_of_node(&other_node));
+                        thread_state.set_node_next_index(&other_node, new_next_index);
+                        let new_match_index = max(next_index + entries_len - 1, thread_state.match_index_of_node(&other_node));
+                        thread_state.set_node_match_index(&other_node, new_match_index);
+                        thread_state.log(Level::Debug, thread_state.current_term()).log(&format!("Node: {} next index: {} match index: {}", other_node, new_next_index, new_match_index));
+                        thread_state.advance_commit_index();
+                    } else {
+                        thread_state.set_node_next_index(&other_node, thread_state.next_index_of_node(&other_node) - 1);
                     }
                 }
-            }
-            if replicated {
-                last_replication = Instant::now();
-            }
-            thread::sleep(min_replication_interval);
+            });
         }
-    });
+    }
+    if replicated {
+        *last_replication = now;
+    }
 }
@@ -0,0 +1,36 @@
+pub mod message_handlers;
+pub mod raft_node_state;
+pub mod election_state;
+pub mod log;
+pub mod messages;
+
+use std::sync::Arc;
+use std::time::Duration;
+use shared_lib::dedup::DEFAULT_DEDUP_WINDOW;
+use shared_lib::message_handlers::stats_handler::StatsHandler;
+use shared_lib::middleware::{CatchPanics, Dedup, RequestLogging, Timing};
+use shared_lib::router::{Handlers, Router};
+use crate::election_state::ElectionState;
+use crate::message_handlers::append_entries_handler::AppendEntriesHandler;
+use crate::message_handlers::cas_handler::CasHandler;
+use crate::message_handlers::init_handler::InitHandler;
+use crate::message_handlers::read_handler::ReadHandler;
+use crate::message_handlers::request_vote_handler::RequestVoteHandler;
+use crate::message_handlers::write_handler::WriteHandler;
+use crate::raft_node_state::RaftState;
+
+pub fn handlers(election_state: &Arc<ElectionState<'static>>) -> Handlers<RaftState> {
+    Router::init()
+        .with(RequestLogging {})
+        .with(Timing::init(Duration::from_millis(500)))
+        .with(CatchPanics {})
+        .with(Dedup::init(DEFAULT_DEDUP_WINDOW))
+        .route("init", InitHandler::init())
+        .route("read", ReadHandler::init(election_state.clone()))
+        .route("cas", CasHandler::init(election_state.clone()))
+        .route("write", WriteHandler::init(election_state.clone()))
+        .route("request_vote", RequestVoteHandler::init(election_state.clone()))
+        .route("append_entries", AppendEntriesHandler::init(election_state.clone()))
+        .route("stats", StatsHandler {})
+        .build()
+}
@@ -2,6 +2,7 @@ use json::{JsonValue, object};
 use std::str::FromStr;
 use crate::log::Op::*;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Debug)]
 pub enum Op {
     CAS {key: i32, from: i32, to: i32, requester: String, msg_id: i32 },
@@ -21,7 +22,7 @@ impl FromStr for Op {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let owned = s.clone();
+        let owned = s;
         let split: Vec<&str> = owned.split(":").collect();
         let op = split[0];
         let args: Vec<&str> = split[1].split(",").collect();
@@ -53,20 +54,18 @@ pub struct Entry {
 
 #[derive(Debug)]
 pub struct Log {
-    node: String,
     entries: Vec<Entry>,
 }
 
 impl Log {
-    pub fn init<'a>(node: String) -> Log {
+    pub fn init() -> Log {
         Log {
-            node,
             entries: vec![Entry { term: 0, op: None }],
         }
     }
 
     pub fn get(&self, index: usize) -> Option<Entry> {
-        if self.entries.len() <= index - 1 || index <= 0 {
+        if index == 0 || self.entries.len() < index {
             return None;
         }
         Some(self.entries[index - 1].clone())
@@ -121,5 +120,5 @@ pub fn parse_entries(jv: &JsonValue) -> Vec<Entry> {
         let entry = Entry::from(entry_jv);
         v.push(entry);
     }
-    return v;
+    v
 }
@@ -1,13 +1,12 @@
-use shared_lib::message_handler::MessageHandler;
+use shared_lib::message_handler::RequestHandler;
+use shared_lib::message::Message;
 use crate::raft_node_state::RaftState;
-use json::{JsonValue, object};
-use shared_lib::error::{MaelstromError, DefiniteError, abort};
-use crate::election_state::{ElectionState, election_loop};
+use shared_lib::error::{MaelstromError, abort};
+use crate::election_state::ElectionState;
+use crate::messages::{AppendEntries, AppendEntriesRes};
 use std::sync::Arc;
-use shared_lib::message_utils::{get_body, get_in_response_to};
-use crate::log::{Entry, parse_entries};
 use std::cmp::min;
-use shared_lib::stdio::write_log;
+use shared_lib::logging::Level;
 
 pub struct AppendEntriesHandler<'a> {
     election_state: Arc<ElectionState<'a>>,
@@ -19,45 +18,48 @@ impl AppendEntriesHandler<'_> {
     }
 }
 
-impl MessageHandler<RaftState> for AppendEntriesHandler<'_> {
-    fn make_response_body(&self, message: &JsonValue, curr_state: &RaftState) -> Result<JsonValue, MaelstromError> {
-        let body = get_body(message);
-        let remote_term = body["term"].as_i32().unwrap();
+impl RequestHandler<RaftState> for AppendEntriesHandler<'_> {
+    type Request = AppendEntries;
+    type Response = AppendEntriesRes;
+
+    fn make_response_body(&self, message: &Message<AppendEntries>, curr_state: &RaftState) -> Result<AppendEntriesRes, MaelstromError> {
+        let body = &message.body;
+        let remote_term = body.term;
         self.election_state.maybe_step_down(remote_term);
-        let mut response = object!(type: "append_entries_res", term: self.election_state.current_term(), success: false);
+        let mut response = AppendEntriesRes { term: self.election_state.current_term(), success: false };
         if remote_term < self.election_state.current_term() {
-            write_log("Remote term less than current term returning.");
+            curr_state.log(Level::Debug, "raft::append_entries").term(remote_term).log("Remote term less than current term returning.");
             return Ok(response);
         }
-        self.election_state.set_leader(body["leader_id"].as_str().unwrap().into());
+        self.election_state.set_leader(body.leader_id.clone());
         self.election_state.reset_election_time();
-        let prev_log_index = body["prev_log_index"].as_i32().unwrap() as usize;
-        if prev_log_index <= 0 {
-            write_log("Out of bounds on log index");
+        let prev_log_index = body.prev_log_index;
+        if prev_log_index == 0 {
+            curr_state.log(Level::Debug, "raft::append_entries").term(remote_term).log("Out of bounds on log index");
             let def_error = abort(format!("Out of bounds previous log index: {}", prev_log_index));
-            return Err(MaelstromError { in_reply_to: message["id"].as_i32().unwrap(), error: def_error });
+            return Err(message.error(def_error));
         }
-        let prev_log_term = body["prev_log_term"].as_i32().unwrap() as usize;
+        let prev_log_term = body.prev_log_term;
         let m_entry = curr_state.log_entry(prev_log_index);
         if m_entry.is_none() {
-            write_log(format!("No entry found at {}", prev_log_index).as_str());
+            curr_state.log(Level::Debug, "raft::append_entries").term(remote_term).log(&format!("No entry found at {}", prev_log_index));
             return Ok(response);
         }
         let entry = m_entry.unwrap();
-        if entry.term as usize != prev_log_term {
-            write_log(format!("Entry term of {} did not match prev_log_term of {}", entry.term, prev_log_term).as_str());
+        if entry.term != prev_log_term {
+            curr_state.log(Level::Debug, "raft::append_entries").term(remote_term).log(&format!("Entry term of {} did not match prev_log_term of {}", entry.term, prev_log_term));
             return Ok(response);
         }
         curr_state.truncate_log(prev_log_index);
-        curr_state.append_log_entries(&mut parse_entries(&body["entries"]));
+        curr_state.append_log_entries(&mut body.entries.clone());
 
-        let leader_commit = body["leader_commit"].as_i32().unwrap() as usize;
+        let leader_commit = body.leader_commit;
         if self.election_state.commit_index() < leader_commit {
             let new_commit_index = min(leader_commit, curr_state.log_size());
             self.election_state.set_commit_index(new_commit_index);
             self.election_state.advance_state_machine();
         }
-        response["success"] = JsonValue::from(true);
-        return Ok(response);
+        response.success = true;
+        Ok(response)
     }
-}
\ No newline at end of file
+}
@@ -1,46 +1,40 @@
-use shared_lib::message_handler::MessageHandler;
+use shared_lib::message_handler::RequestHandler;
+use shared_lib::message::{Body, Message};
 use crate::raft_node_state::RaftState;
-use json::{JsonValue, object};
-use shared_lib::error::{MaelstromError, DefiniteError, temporarily_unavailable};
-use shared_lib::message_utils::{get_body, get_in_response_to};
+use json::JsonValue;
+use shared_lib::error::{MaelstromError, DefiniteError};
 use crate::election_state::ElectionState;
 use std::sync::Arc;
 use crate::election_state::State::LEADER;
-use crate::log::Op::CAS;
+use crate::log::Op;
+use crate::messages::{Cas, CasOk};
 use crate::message_handlers::proxy_to_leader::proxy_request_to_leader;
-use std::option::Option::Some;
 
 pub struct CasHandler<'a> {
     election_state: Arc<ElectionState<'a>>,
 }
 
 impl CasHandler<'_> {
-    pub fn init(election_state: Arc<ElectionState<'_>>) -> CasHandler {
+    pub fn init(election_state: Arc<ElectionState<'_>>) -> CasHandler<'_> {
         CasHandler {
             election_state
         }
     }
 }
 
-impl MessageHandler<RaftState> for CasHandler<'_> {
+impl RequestHandler<RaftState> for CasHandler<'_> {
+    type Request = Cas;
+    type Response = CasOk;
 
-
-    fn make_response_body(&self, message: &JsonValue, curr_state: &RaftState) -> Result<JsonValue, MaelstromError> {
-        unreachable!()
-    }
-
-    fn get_response_body(&self, message: &JsonValue, curr_state: &RaftState) -> Result<Option<JsonValue>, MaelstromError> {
-        let body = get_body(message);
+    fn get_response_body(&self, message: &Message<Cas>, curr_state: &RaftState) -> Result<Option<CasOk>, MaelstromError> {
         if self.election_state.current_state() != LEADER {
-            let result = proxy_request_to_leader(body, curr_state, self.election_state.clone());
-            return result.map(|jv| Some(jv));
+            let result = proxy_request_to_leader(message, curr_state, self.election_state.clone());
+            return result.map(Some);
         }
-        let key = body["key"].as_i32().unwrap();
-        let from = body["from"].as_i32().unwrap();
-        let to = body["to"].as_i32().unwrap();
-        let requester = message["src"].as_str().unwrap().to_string();
-        let msg_id = body["msg_id"].as_i32().unwrap();
-        curr_state.append_single_entry(CAS { key, from, to, requester, msg_id }, self.election_state.current_term());
+        let Cas { key, from, to } = message.body;
+        let requester = message.src.clone();
+        let msg_id = message.msg_id.unwrap_or_default();
+        curr_state.append_single_entry(Op::CAS { key, from, to, requester, msg_id }, self.election_state.current_term());
         Ok(None)
     }
 }
@@ -48,11 +42,14 @@ impl MessageHandler<RaftState> for CasHandler<'_> {
 
 pub fn check_cas_result(cas_result: Result<(), DefiniteError>, in_reply_to: i32) -> Result<JsonValue, MaelstromError> {
     match cas_result {
-        Ok(()) => Ok(object! {type: "cas_ok", in_reply_to: in_reply_to}),
+        Ok(()) => {
+            let mut body = CasOk {}.to_json();
+            body["in_reply_to"] = JsonValue::from(in_reply_to);
+            Ok(body)
+        },
         Err(definite_error) => Err(MaelstromError {
             in_reply_to,
             error: definite_error,
         })
     }
 }
-
@@ -1,5 +1,5 @@
-use json::{object, JsonValue};
-use shared_lib::{error::MaelstromError, message_handler::MessageHandler, node_state::NodeState, message_utils::get_body};
+use shared_lib::{error::MaelstromError, message_handler::RequestHandler};
+use shared_lib::message::{Init, InitOk, Message};
 use crate::raft_node_state::RaftState;
 
 pub struct InitHandler {}
@@ -10,23 +10,24 @@ impl InitHandler {
 
 }
 
-impl MessageHandler<RaftState> for InitHandler {
+impl RequestHandler<RaftState> for InitHandler {
+    type Request = Init;
+    type Response = InitOk;
 
     fn make_response_body(
         &self,
-        message: &json::JsonValue,
+        message: &Message<Init>,
         curr_state: &RaftState,
-    ) -> Result<JsonValue, MaelstromError> {
-        let body = get_body(message);
-        let node_id = body["node_id"].to_string();
-        curr_state.set_node_id(node_id.clone());
-        curr_state.init_log(node_id.clone());
+    ) -> Result<InitOk, MaelstromError> {
+        let body = &message.body;
+        curr_state.set_node_id(body.node_id.clone());
+        curr_state.init_log();
         curr_state.set_other_node_ids(
-            body["node_ids"]
-                .members()
-                .map(|jv| jv.as_str().unwrap())
+            body.node_ids
+                .iter()
+                .map(|id| id.as_str())
                 .collect(),
         );
-        Ok(object! {type: "init_ok"})
+        Ok(InitOk {})
     }
 }
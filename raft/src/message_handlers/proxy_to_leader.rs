@@ -1,15 +1,14 @@
-use shared_lib::error::{DefiniteError, temporarily_unavailable, MaelstromError};
+use shared_lib::error::{temporarily_unavailable, DefiniteError, MaelstromError};
+use shared_lib::message::{Body, Message};
 use std::sync::Arc;
 use crate::election_state::ElectionState;
-use json::JsonValue;
 use crate::raft_node_state::RaftState;
-use crate::election_state::State::LEADER;
 use shared_lib::rpc::send_rpc;
-use shared_lib::stdio::write_log;
+use shared_lib::logging::Level;
 
-pub fn proxy_request_to_leader(body: &JsonValue, curr_state: &RaftState, election_state: Arc<ElectionState>) -> Result<JsonValue, MaelstromError> {
+pub fn proxy_request_to_leader<Req: Body, Res: Body>(message: &Message<Req>, curr_state: &RaftState, election_state: Arc<ElectionState>) -> Result<Res, MaelstromError> {
+    let in_reply_to = message.msg_id.unwrap_or_default();
     let maybe_leader = election_state.get_leader();
-    let in_reply_to = body["msg_id"].as_i32().unwrap();
     if maybe_leader.is_none() {
         return Err(
             MaelstromError {
@@ -18,7 +17,7 @@ pub fn proxy_request_to_leader(body: &JsonValue, curr_state: &RaftState, electio
             });
     }
     let leader = maybe_leader.unwrap();
-    let leader_response = send_rpc(curr_state, &mut body.clone(), &leader);
+    let leader_response = send_rpc(curr_state, &mut message.body.to_json(), &leader);
     match leader_response {
         None => {
             Err(
@@ -28,9 +27,13 @@ pub fn proxy_request_to_leader(body: &JsonValue, curr_state: &RaftState, electio
                 })
         },
         Some(jv) => {
-            let body = jv["body"].clone();
-            write_log(format!("Leader response is: {}", body).as_str());
-            Ok(body)
+            let body = &jv["body"];
+            curr_state.log(Level::Debug, "raft::proxy").msg_id(in_reply_to).log(&format!("Leader response is: {}", body));
+            if let Some(error) = DefiniteError::from_body(body) {
+                return Err(MaelstromError { in_reply_to, error });
+            }
+            Res::from_json(body).map_err(|error| MaelstromError { in_reply_to, error })
         }
     }
-}
\ No newline at end of file
+}
+
@@ -1,57 +1,57 @@
-use shared_lib::message_handler::MessageHandler;
+use shared_lib::message_handler::RequestHandler;
+use shared_lib::message::Message;
 use crate::raft_node_state::RaftState;
-use json::{JsonValue, object};
-use shared_lib::error::{MaelstromError, DefiniteError, temporarily_unavailable};
-use shared_lib::message_utils::{get_body, get_in_response_to};
+use json::JsonValue;
+use shared_lib::error::{MaelstromError, DefiniteError};
 use std::sync::Arc;
 use crate::election_state::ElectionState;
 use crate::election_state::State::LEADER;
-use crate::log::Op::Read;
+use crate::log::Op;
+use crate::messages::{Read, ReadOk};
 use crate::message_handlers::proxy_to_leader::proxy_request_to_leader;
-use std::option::Option::Some;
+use shared_lib::message::Body;
 
 pub struct ReadHandler<'a> {
     election_state: Arc<ElectionState<'a>>
 }
 
 impl ReadHandler<'_> {
-    pub fn init(election_state: Arc<ElectionState<'_>>) -> ReadHandler {
+    pub fn init(election_state: Arc<ElectionState<'_>>) -> ReadHandler<'_> {
         ReadHandler {
             election_state
         }
     }
 }
 
-impl MessageHandler<RaftState> for ReadHandler<'_> {
-    fn make_response_body(&self, message: &JsonValue, curr_state: &RaftState) -> Result<JsonValue, MaelstromError> {
-        unreachable!()
-    }
+impl RequestHandler<RaftState> for ReadHandler<'_> {
+    type Request = Read;
+    type Response = ReadOk;
 
-    fn get_response_body(&self, message: &JsonValue, curr_state: &RaftState) -> Result<Option<JsonValue>, MaelstromError> {
-        let body = get_body(message);
+    fn get_response_body(&self, message: &Message<Read>, curr_state: &RaftState) -> Result<Option<ReadOk>, MaelstromError> {
         if self.election_state.current_state() != LEADER {
-            let result = proxy_request_to_leader(body, curr_state, self.election_state.clone());
-            return result.map(|jv| Some(jv));
+            let result = proxy_request_to_leader(message, curr_state, self.election_state.clone());
+            return result.map(Some);
         }
-        let key = body["key"].as_i32().unwrap();
-        let requester = message["src"].as_str().unwrap().to_string();
-        let msg_id = body["msg_id"].as_i32().unwrap();
-        curr_state.append_single_entry(Read{key, requester, msg_id }, self.election_state.current_term());
+        let key = message.body.key;
+        let requester = message.src.clone();
+        let msg_id = message.msg_id.unwrap_or_default();
+        curr_state.append_single_entry(Op::Read{key, requester, msg_id }, self.election_state.current_term());
         Ok(None)
     }
 }
 
-    pub fn check_read_result(read_result: Result<i32, DefiniteError>, msg_id: i32) -> Result<JsonValue, MaelstromError> {
-        match read_result {
-            Ok(read_value) => {
-                Ok(object! {type: "read_ok", value: read_value.clone(), in_reply_to: msg_id })
-            }
-            Err(definite_error) => {
-                Err(MaelstromError {
-                    error: definite_error,
-                    in_reply_to: msg_id
-                })
-            }
+pub fn check_read_result(read_result: Result<i32, DefiniteError>, msg_id: i32) -> Result<JsonValue, MaelstromError> {
+    match read_result {
+        Ok(value) => {
+            let mut body = ReadOk { value }.to_json();
+            body["in_reply_to"] = JsonValue::from(msg_id);
+            Ok(body)
+        }
+        Err(definite_error) => {
+            Err(MaelstromError {
+                error: definite_error,
+                in_reply_to: msg_id
+            })
         }
     }
-
+}
@@ -0,0 +1,195 @@
+use json::{object, JsonValue};
+use shared_lib::error::{malformed_request, DefiniteError};
+use shared_lib::message::{bool_field, i32_field, string_field, usize_field, Body};
+use crate::log::{parse_entries, Entry};
+
+pub struct Read {
+    pub key: i32,
+}
+
+impl Body for Read {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(Read { key: i32_field(body, "key")? })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "read", key: self.key}
+    }
+}
+
+pub struct ReadOk {
+    pub value: i32,
+}
+
+impl Body for ReadOk {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(ReadOk { value: i32_field(body, "value")? })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "read_ok", value: self.value}
+    }
+}
+
+pub struct Write {
+    pub key: i32,
+    pub value: i32,
+}
+
+impl Body for Write {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(Write {
+            key: i32_field(body, "key")?,
+            value: i32_field(body, "value")?,
+        })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "write", key: self.key, value: self.value}
+    }
+}
+
+pub struct WriteOk {}
+
+impl Body for WriteOk {
+    fn from_json(_body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(WriteOk {})
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "write_ok"}
+    }
+}
+
+pub struct Cas {
+    pub key: i32,
+    pub from: i32,
+    pub to: i32,
+}
+
+impl Body for Cas {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(Cas {
+            key: i32_field(body, "key")?,
+            from: i32_field(body, "from")?,
+            to: i32_field(body, "to")?,
+        })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "cas", key: self.key, from: self.from, to: self.to}
+    }
+}
+
+pub struct CasOk {}
+
+impl Body for CasOk {
+    fn from_json(_body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(CasOk {})
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "cas_ok"}
+    }
+}
+
+pub struct RequestVote {
+    pub term: i32,
+    pub candidate_id: String,
+    pub last_log_index: usize,
+    pub last_log_term: i32,
+}
+
+impl Body for RequestVote {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(RequestVote {
+            term: i32_field(body, "term")?,
+            candidate_id: string_field(body, "candidate_id")?,
+            last_log_index: usize_field(body, "last_log_index")?,
+            last_log_term: i32_field(body, "last_log_term")?,
+        })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {
+            type: "request_vote",
+            term: self.term,
+            candidate_id: self.candidate_id.clone(),
+            last_log_index: self.last_log_index,
+            last_log_term: self.last_log_term
+        }
+    }
+}
+
+pub struct RequestVoteRes {
+    pub term: i32,
+    pub vote_granted: bool,
+}
+
+impl Body for RequestVoteRes {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(RequestVoteRes {
+            term: i32_field(body, "term")?,
+            vote_granted: bool_field(body, "vote_granted")?,
+        })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "request_vote_res", term: self.term, vote_granted: self.vote_granted}
+    }
+}
+
+pub struct AppendEntries {
+    pub term: i32,
+    pub leader_id: String,
+    pub prev_log_index: usize,
+    pub prev_log_term: i32,
+    pub entries: Vec<Entry>,
+    pub leader_commit: usize,
+}
+
+impl Body for AppendEntries {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        if !body["entries"].is_array() {
+            return Err(malformed_request("Expected field `entries` to be an array".to_string()));
+        }
+        Ok(AppendEntries {
+            term: i32_field(body, "term")?,
+            leader_id: string_field(body, "leader_id")?,
+            prev_log_index: usize_field(body, "prev_log_index")?,
+            prev_log_term: i32_field(body, "prev_log_term")?,
+            entries: parse_entries(&body["entries"]),
+            leader_commit: usize_field(body, "leader_commit")?,
+        })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {
+            type: "append_entries",
+            term: self.term,
+            leader_id: self.leader_id.clone(),
+            prev_log_index: self.prev_log_index,
+            prev_log_term: self.prev_log_term,
+            entries: self.entries.clone(),
+            leader_commit: self.leader_commit
+        }
+    }
+}
+
+pub struct AppendEntriesRes {
+    pub term: i32,
+    pub success: bool,
+}
+
+impl Body for AppendEntriesRes {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(AppendEntriesRes {
+            term: i32_field(body, "term")?,
+            success: bool_field(body, "success")?,
+        })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "append_entries_res", term: self.term, success: self.success}
+    }
+}
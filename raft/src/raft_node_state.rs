@@ -1,14 +1,11 @@
+use shared_lib::quorum;
 use shared_lib::node_state::NodeState;
 use std::sync::mpsc::SyncSender;
-use std::sync::{Mutex, RwLock, RwLockReadGuard};
+use std::sync::{Mutex, RwLock};
 use std::ops::Deref;
 use std::collections::HashMap;
 use shared_lib::error::{key_does_not_exist, DefiniteError, precondition_failed};
-use std::io::{stderr, Write};
-use crate::election_state::ElectionState;
 use crate::log::{Log, Entry, Op};
-use std::thread;
-use shared_lib::stdio::write_log;
 
 pub struct RaftState {
     node_state: NodeState,
@@ -17,7 +14,7 @@ pub struct RaftState {
 }
 
 impl RaftState {
-    pub fn init<'a>(response_channel: SyncSender<String>) -> RaftState {
+    pub fn init(response_channel: SyncSender<String>) -> RaftState {
         RaftState {
             node_state: NodeState::init(response_channel),
             values: Mutex::new(HashMap::new()),
@@ -46,13 +43,13 @@ impl RaftState {
             }
             let mut map = self.values.lock().unwrap();
             map.insert(key, to);
-            return Ok(());
+            Ok(())
         })
     }
 
-    pub fn init_log(&self, node_id: String) {
+    pub fn init_log(&self) {
         let mut log = self.log.write().unwrap();
-        log.replace(Log::init(node_id));
+        log.replace(Log::init());
     }
 
     pub fn log_size(&self) -> usize {
@@ -65,19 +62,19 @@ impl RaftState {
     pub fn append_single_entry(&self, op: Op, term: i32) {
         let entry = Entry {op: Some(op), term };
         let mut log_loc = self.log.write().unwrap();
-        log_loc.as_mut().map(|mut log| {
+        if let Some(log) = log_loc.as_mut() {
             let mut vec = vec![entry];
             log.append(&mut vec);
-        });
+        }
     }
 
     pub fn append_log_entries(&self, entries: &mut Vec<Entry>) {
-        self.log.write().unwrap().as_mut().map(|mut log| log.append(entries));
+        if let Some(log) = self.log.write().unwrap().as_mut() { log.append(entries) }
 
     }
 
     pub fn truncate_log(&self, len: usize) {
-        self.log.write().unwrap().as_mut().map(|mut log| log.truncate(len));
+        if let Some(log) = self.log.write().unwrap().as_mut() { log.truncate(len) }
     }
 
     pub fn log_entry(&self, i: usize) -> Option<Entry> {
@@ -86,17 +83,16 @@ impl RaftState {
         })
     }
 
-    pub fn majority(&self) -> i32 {
-        (self.other_nodes().len() as i32 / 2) + 1
+    /// Votes or acknowledgements needed out of the whole cluster, this node included.
+    pub fn majority(&self) -> usize {
+        quorum::majority(self.other_nodes().len() + 1)
     }
 
     pub fn log_last(&self) -> Entry {
         self.log.read().unwrap().as_ref().unwrap().last()
     }
 
-    pub fn log_from_index(&self, i: usize) -> Vec<Entry> { self.log.read().unwrap().as_ref().and_then(|log| {
-        Some(log.upto_index(i))
-    }).unwrap_or(vec![]) }
+    pub fn log_from_index(&self, i: usize) -> Vec<Entry> { self.log.read().unwrap().as_ref().map(|log| log.upto_index(i)).unwrap_or(vec![]) }
 }
 
 impl Deref for RaftState {
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::sync::mpsc::SyncSender;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use json::{object, stringify, JsonValue};
+use crate::metrics::Metrics;
+use crate::scheduler::Scheduler;
+
+pub const BATCH_TYPE: &str = "batch";
+pub const DEFAULT_BATCH_WINDOW: Duration = Duration::from_millis(5);
+pub const DEFAULT_MAX_BATCH: usize = 64;
+
+pub type Outbox = Arc<Mutex<Option<SyncSender<String>>>>;
+
+/// Coalesces messages to the same peer that are sent within `window` of each other into
+/// one `batch` envelope. A batch is sent early once it holds `max_batch` messages, and a
+/// lone message goes out as it is.
+pub struct Batcher {
+    window: Duration,
+    max_batch: usize,
+    outbox: Outbox,
+    scheduler: Arc<Scheduler>,
+    metrics: Arc<Metrics>,
+    pending: Mutex<HashMap<String, Vec<JsonValue>>>,
+}
+
+impl Batcher {
+    pub fn init(window: Duration, max_batch: usize, outbox: Outbox, scheduler: Arc<Scheduler>, metrics: Arc<Metrics>) -> Arc<Batcher> {
+        Arc::new(Batcher {
+            window,
+            max_batch: max_batch.max(1),
+            outbox,
+            scheduler,
+            metrics,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn push(self: &Arc<Self>, message: JsonValue) {
+        let dest = message["dest"].as_str().unwrap_or_default().to_string();
+        let full = {
+            let mut pending = self.pending.lock().unwrap();
+            let queued = pending.entry(dest.clone()).or_default();
+            if queued.is_empty() {
+                let batcher = Arc::clone(self);
+                let flush_dest = dest.clone();
+                self.scheduler.once(self.window, move || batcher.flush(&flush_dest));
+            }
+            queued.push(message);
+            if queued.len() >= self.max_batch {
+                pending.remove(&dest)
+            } else {
+                None
+            }
+        };
+        if let Some(messages) = full {
+            self.send(messages);
+        }
+    }
+
+    pub fn flush(&self, dest: &str) {
+        let messages = self.pending.lock().unwrap().remove(dest);
+        if let Some(messages) = messages {
+            self.send(messages);
+        }
+    }
+
+    pub fn flush_all(&self) {
+        let pending: Vec<Vec<JsonValue>> = self.pending.lock().unwrap().drain().map(|(_, messages)| messages).collect();
+        pending.into_iter().for_each(|messages| self.send(messages));
+    }
+
+    fn send(&self, mut messages: Vec<JsonValue>) {
+        let message = match messages.len() {
+            0 => return,
+            1 => messages.remove(0),
+            count => {
+                self.metrics.add("messages.batched", messages[0]["dest"].as_str().unwrap_or_default(), count as u64);
+                let envelope = object! {
+                    src: messages[0]["src"].clone(),
+                    dest: messages[0]["dest"].clone(),
+                    body: {type: BATCH_TYPE, messages: JsonValue::new_array()},
+                };
+                messages.into_iter().fold(envelope, |mut envelope, message| {
+                    let _ = envelope["body"]["messages"].push(message["body"].clone());
+                    envelope
+                })
+            }
+        };
+        if let Some(channel) = self.outbox.lock().unwrap().as_ref() {
+            let _ = channel.send(stringify(message));
+        }
+    }
+}
+
+/// Splits a `batch` envelope back into the messages it carries.
+pub fn unpack(message: &JsonValue) -> Option<Vec<JsonValue>> {
+    if message["body"]["type"] != BATCH_TYPE {
+        return None;
+    }
+    let messages = message["body"]["messages"]
+        .members()
+        .map(|body| object! {src: message["src"].clone(), dest: message["dest"].clone(), body: body.clone()})
+        .collect();
+    Some(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{sync_channel, Receiver};
+    use crate::clock::ManualClock;
+    use super::*;
+
+    fn batcher(max_batch: usize) -> (Arc<Batcher>, Arc<ManualClock>, Arc<Scheduler>, Receiver<String>) {
+        let (sender, lines) = sync_channel(16);
+        let clock = Arc::new(ManualClock::init());
+        let scheduler = Arc::new(Scheduler::init(clock.clone()));
+        let outbox: Outbox = Arc::new(Mutex::new(Some(sender)));
+        let batcher = Batcher::init(DEFAULT_BATCH_WINDOW, max_batch, outbox, scheduler.clone(), Arc::new(Metrics::init()));
+        (batcher, clock, scheduler, lines)
+    }
+
+    fn message(dest: &str, n: i32) -> JsonValue {
+        object! {src: "n1", dest: dest, body: {type: "gossip", n: n}}
+    }
+
+    #[test]
+    fn packs_messages_within_the_window_and_unpacks_them_in_order() {
+        let (batcher, clock, scheduler, lines) = batcher(DEFAULT_MAX_BATCH);
+        batcher.push(message("n2", 1));
+        batcher.push(message("n2", 2));
+        batcher.push(message("n3", 3));
+        assert!(lines.try_recv().is_err());
+
+        clock.advance(DEFAULT_BATCH_WINDOW);
+        scheduler.run_due();
+        let mut sent: Vec<JsonValue> = lines.try_iter().map(|line| json::parse(&line).unwrap()).collect();
+        sent.sort_by_key(|message| message["dest"].to_string());
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0]["body"]["type"], BATCH_TYPE);
+        assert_eq!(unpack(&sent[0]), Some(vec![message("n2", 1), message("n2", 2)]));
+        assert_eq!(sent[1], message("n3", 3));
+        assert_eq!(unpack(&sent[1]), None);
+    }
+
+    #[test]
+    fn sends_a_full_batch_without_waiting() {
+        let (batcher, _, _, lines) = batcher(2);
+        batcher.push(message("n2", 1));
+        batcher.push(message("n2", 2));
+        let sent = json::parse(&lines.try_recv().unwrap()).unwrap();
+        assert_eq!(unpack(&sent).map(|messages| messages.len()), Some(2));
+    }
+}
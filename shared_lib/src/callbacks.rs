@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+use json::JsonValue;
+use crate::clock::{Clock, SystemClock};
+use crate::logging;
+use crate::mailbox::Mailbox;
+
+const REAPER_INTERVAL: Duration = Duration::from_millis(10);
+
+pub type Completion = Box<dyn FnOnce(Option<JsonValue>) + Send>;
+
+pub enum Callback {
+    Mailbox(Arc<Mailbox<JsonValue>>),
+    Closure(Completion),
+}
+
+struct Pending {
+    callback: Callback,
+    deadline: Instant,
+}
+
+pub struct Callbacks {
+    pending: Mutex<HashMap<i32, Pending>>,
+    inline: AtomicBool,
+    closed: AtomicBool,
+    clock: RwLock<Arc<dyn Clock>>,
+    completions: Sender<(Completion, Option<JsonValue>)>,
+}
+
+impl Callbacks {
+    pub fn init() -> Arc<Callbacks> {
+        let (completions, receiver) = channel();
+        let callbacks = Arc::new(Callbacks {
+            pending: Mutex::new(HashMap::new()),
+            inline: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            clock: RwLock::new(Arc::new(SystemClock {})),
+            completions,
+        });
+        start_reaper(Arc::downgrade(&callbacks));
+        thread::spawn(move || run_completions(receiver));
+        callbacks
+    }
+
+    /// Closures normally run one at a time on a completion thread, in the order their
+    /// replies arrived, so they must not wait on another closure's reply. Inline, they run
+    /// on whichever thread completes them.
+    pub fn run_inline(&self, inline: bool) {
+        self.inline.store(inline, Ordering::SeqCst);
+    }
+
+    /// Deadlines are measured on `clock`. The background reaper only expires RPCs under a
+    /// real-time clock; otherwise whoever advances the clock calls `expire`.
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self.clock.write().unwrap() = clock;
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.read().unwrap().clone()
+    }
+
+    pub fn register(&self, msg_id: i32, callback: Callback, timeout: Duration) {
+        if self.closed.load(Ordering::SeqCst) {
+            self.finish(callback, None);
+            return;
+        }
+        let pending = Pending { callback, deadline: self.clock().now() + timeout };
+        self.pending.lock().unwrap().insert(msg_id, pending);
+    }
+
+    pub fn complete(&self, msg_id: i32, response: JsonValue) -> bool {
+        let pending = self.pending.lock().unwrap().remove(&msg_id);
+        match pending {
+            Some(pending) => {
+                self.finish(pending.callback, Some(response));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn cancel(&self, msg_id: i32) -> bool {
+        self.pending.lock().unwrap().remove(&msg_id).is_some()
+    }
+
+    /// Fails every pending RPC and any registered later, for when no more replies can arrive.
+    pub fn close(&self) -> usize {
+        self.closed.store(true, Ordering::SeqCst);
+        let pending: Vec<Pending> = self.pending.lock().unwrap().drain().map(|(_, p)| p).collect();
+        let count = pending.len();
+        for pending in pending {
+            self.finish(pending.callback, None);
+        }
+        count
+    }
+
+    pub fn outstanding(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    pub fn expire(&self, now: Instant) -> usize {
+        let expired: Vec<(i32, Pending)> = {
+            let mut pending = self.pending.lock().unwrap();
+            let mut ids: Vec<i32> = pending
+                .iter()
+                .filter(|(_, p)| p.deadline <= now)
+                .map(|(id, _)| *id)
+                .collect();
+            // In msg_id order, so a simulation fails RPCs the same way every run.
+            ids.sort();
+            ids.into_iter()
+                .filter_map(|id| pending.remove(&id).map(|p| (id, p)))
+                .collect()
+        };
+        let count = expired.len();
+        for (msg_id, pending) in expired {
+            logging::warn("callbacks").msg_id(msg_id).log("RPC timed out");
+            self.finish(pending.callback, None);
+        }
+        count
+    }
+
+    fn finish(&self, callback: Callback, response: Option<JsonValue>) {
+        match callback {
+            Callback::Mailbox(mailbox) => {
+                if let Some(response) = response {
+                    mailbox.push(response);
+                }
+            }
+            Callback::Closure(on_complete) if self.inline.load(Ordering::SeqCst) => on_complete(response),
+            Callback::Closure(on_complete) => {
+                let _ = self.completions.send((on_complete, response));
+            }
+        }
+    }
+}
+
+fn run_completions(receiver: Receiver<(Completion, Option<JsonValue>)>) {
+    while let Ok((on_complete, response)) = receiver.recv() {
+        if panic::catch_unwind(AssertUnwindSafe(|| on_complete(response))).is_err() {
+            logging::error("callbacks").log("Recovered from a panicking completion");
+        }
+    }
+}
+
+fn start_reaper(callbacks: Weak<Callbacks>) {
+    thread::spawn(move || loop {
+        thread::sleep(REAPER_INTERVAL);
+        match callbacks.upgrade() {
+            Some(callbacks) => {
+                let clock = callbacks.clock();
+                if clock.is_real_time() {
+                    callbacks.expire(clock.now());
+                }
+            }
+            None => return,
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn closure(id: i32, done: &Sender<(i32, thread::ThreadId, bool)>) -> Callback {
+        let done = done.clone();
+        Callback::Closure(Box::new(move |response| {
+            done.send((id, thread::current().id(), response.is_some())).unwrap();
+        }))
+    }
+
+    #[test]
+    fn closures_complete_in_order_on_one_thread() {
+        let callbacks = Callbacks::init();
+        let (done, completed) = channel();
+        callbacks.register(1, Callback::Closure(Box::new(|_| panic!("completion failed"))), Duration::from_secs(30));
+        for id in 2..5 {
+            callbacks.register(id, closure(id, &done), Duration::from_secs(30));
+        }
+        for id in 1..4 {
+            assert!(callbacks.complete(id, JsonValue::from(id)));
+        }
+        assert!(!callbacks.complete(1, JsonValue::Null));
+        assert_eq!(callbacks.close(), 1);
+
+        let completed: Vec<(i32, thread::ThreadId, bool)> =
+            (2..5).map(|_| completed.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+        let outcomes: Vec<(i32, bool)> = completed.iter().map(|(id, _, replied)| (*id, *replied)).collect();
+        assert_eq!(outcomes, vec![(2, true), (3, true), (4, false)]);
+        assert!(completed.iter().all(|(_, thread, _)| *thread == completed[0].1));
+        assert_ne!(completed[0].1, thread::current().id());
+    }
+
+    #[test]
+    fn mailboxes_get_replies_and_inline_closures_run_on_the_caller() {
+        let callbacks = Callbacks::init();
+        callbacks.run_inline(true);
+        let mailbox = Mailbox::init();
+        let (done, completed) = channel();
+        callbacks.register(1, Callback::Mailbox(mailbox.clone()), Duration::from_secs(30));
+        callbacks.register(2, closure(2, &done), Duration::from_secs(30));
+
+        assert!(callbacks.complete(1, JsonValue::from("reply")));
+        assert!(callbacks.complete(2, JsonValue::from("reply")));
+        assert_eq!(completed.try_recv().unwrap(), (2, thread::current().id(), true));
+        assert_eq!(callbacks.outstanding(), 0);
+        assert_eq!(mailbox.try_take(), Some(JsonValue::from("reply")));
+    }
+}
@@ -0,0 +1,337 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PARK_POLL: Duration = Duration::from_millis(1);
+
+pub type Ready = Arc<dyn Fn() -> bool + Send + Sync>;
+pub type Task = Box<dyn FnOnce() + Send>;
+
+thread_local! {
+    static IN_TASK: Cell<bool> = const { Cell::new(false) };
+}
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration);
+
+    /// False for clocks that only move when something advances them, which then also
+    /// has to drive anything waiting on them.
+    fn is_real_time(&self) -> bool {
+        true
+    }
+
+    /// Blocks until `ready` returns true or the clock reaches `deadline`.
+    fn park(&self, deadline: Instant, ready: Ready) {
+        while !ready() {
+            let now = self.now();
+            if now >= deadline {
+                return;
+            }
+            thread::sleep(PARK_POLL.min(deadline - now));
+        }
+    }
+
+    /// Runs `task` on a thread of its own.
+    fn spawn(&self, task: Task) {
+        thread::spawn(task);
+    }
+}
+
+pub struct SystemClock {}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+struct Waiter {
+    deadline: Instant,
+    ready: Ready,
+    task: bool,
+}
+
+struct Parked {
+    next_id: u64,
+    /// Threads parked on the clock, by when they parked.
+    waiters: BTreeMap<u64, Waiter>,
+    /// Tasks started with `spawn` that are neither parked nor finished.
+    running: usize,
+}
+
+impl Parked {
+    fn add(&mut self, deadline: Instant, ready: Ready, task: bool) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.waiters.insert(id, Waiter { deadline, ready, task });
+        id
+    }
+}
+
+struct Waiters {
+    parked: Mutex<Parked>,
+    changed: Condvar,
+}
+
+impl Waiters {
+    fn task_finished(&self) {
+        self.parked.lock().unwrap().running -= 1;
+        self.changed.notify_all();
+    }
+}
+
+/// Starts at the instant it was created and only moves when `advance` is called. Threads
+/// that sleep or park on it block until another thread moves it past their deadline.
+///
+/// A stepped clock also decides when parked threads resume: each waits until `wake_next`
+/// picks it, and tasks started with `spawn` begin parked. A driver that alternates
+/// `wait_until_parked` and `wake_next` runs one task at a time, in the same order every
+/// run. Tasks must not block on anything but the clock, or the driver cannot tell they
+/// are waiting.
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+    stepped: bool,
+    waiters: Arc<Waiters>,
+}
+
+impl ManualClock {
+    pub fn init() -> ManualClock {
+        ManualClock {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+            stepped: false,
+            waiters: Arc::new(Waiters {
+                parked: Mutex::new(Parked { next_id: 0, waiters: BTreeMap::new(), running: 0 }),
+                changed: Condvar::new(),
+            }),
+        }
+    }
+
+    pub fn stepped() -> ManualClock {
+        ManualClock { stepped: true, ..ManualClock::init() }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+        self.notify();
+    }
+
+    /// Moves the clock forward to `elapsed` since its start; never moves it back.
+    pub fn advance_to(&self, elapsed: Duration) {
+        {
+            let mut current = self.elapsed.lock().unwrap();
+            *current = (*current).max(elapsed);
+        }
+        self.notify();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// How many threads are blocked in `sleep` or `park`.
+    pub fn parked(&self) -> usize {
+        self.waiters.parked.lock().unwrap().waiters.len()
+    }
+
+    /// The earliest deadline anyone is parked until.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.waiters.parked.lock().unwrap().waiters.values().map(|waiter| waiter.deadline).min()
+    }
+
+    /// Blocks until every task is parked or finished. Returns false if one is still
+    /// running after `timeout` of real time, which usually means it is blocked on a lock.
+    pub fn wait_until_parked(&self, timeout: Duration) -> bool {
+        let started = Instant::now();
+        let mut parked = self.waiters.parked.lock().unwrap();
+        while parked.running > 0 {
+            let waited = started.elapsed();
+            if waited >= timeout {
+                return false;
+            }
+            parked = self.waiters.changed.wait_timeout(parked, timeout - waited).unwrap().0;
+        }
+        true
+    }
+
+    /// Resumes the longest-parked thread whose deadline has passed or that is ready.
+    /// Returns false if none can run until the clock moves.
+    pub fn wake_next(&self) -> bool {
+        let mut parked = self.waiters.parked.lock().unwrap();
+        let now = self.now();
+        let next = parked
+            .waiters
+            .iter()
+            .find(|(_, waiter)| waiter.deadline <= now || (waiter.ready)())
+            .map(|(id, _)| *id);
+        let waiter = match next.and_then(|id| parked.waiters.remove(&id)) {
+            Some(waiter) => waiter,
+            None => return false,
+        };
+        if waiter.task {
+            parked.running += 1;
+        }
+        self.waiters.changed.notify_all();
+        true
+    }
+
+    fn notify(&self) {
+        let _parked = self.waiters.parked.lock().unwrap();
+        self.waiters.changed.notify_all();
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::init()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    /// Blocks until another thread advances the clock by `duration`.
+    fn sleep(&self, duration: Duration) {
+        self.park(self.now() + duration, Arc::new(|| false));
+    }
+
+    fn park(&self, deadline: Instant, ready: Ready) {
+        let changed = &self.waiters.changed;
+        let mut parked = self.waiters.parked.lock().unwrap();
+        let task = IN_TASK.with(|in_task| in_task.get());
+        let id = parked.add(deadline, ready.clone(), task);
+        if !self.stepped {
+            while !ready() && self.now() < deadline {
+                parked = changed.wait_timeout(parked, PARK_POLL).unwrap().0;
+            }
+            parked.waiters.remove(&id);
+            return;
+        }
+        if task {
+            parked.running -= 1;
+            changed.notify_all();
+        }
+        while parked.waiters.contains_key(&id) {
+            parked = changed.wait(parked).unwrap();
+        }
+    }
+
+    /// On a stepped clock the task starts parked, so it only runs once `wake_next` picks it.
+    fn spawn(&self, task: Task) {
+        if !self.stepped {
+            thread::spawn(task);
+            return;
+        }
+        let id = self.waiters.parked.lock().unwrap().add(self.now(), Arc::new(|| true), true);
+        let waiters = Arc::clone(&self.waiters);
+        thread::spawn(move || {
+            IN_TASK.with(|in_task| in_task.set(true));
+            let _finished = Finished(&waiters);
+            let mut parked = waiters.parked.lock().unwrap();
+            while parked.waiters.contains_key(&id) {
+                parked = waiters.changed.wait(parked).unwrap();
+            }
+            drop(parked);
+            task();
+        });
+    }
+
+    fn is_real_time(&self) -> bool {
+        false
+    }
+}
+
+/// Counts a task as finished even if it panics.
+struct Finished<'a>(&'a Waiters);
+
+impl Drop for Finished<'_> {
+    fn drop(&mut self) {
+        self.0.task_finished();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use super::*;
+
+    fn wait_for_parked(clock: &ManualClock, count: usize) {
+        while clock.parked() < count {
+            thread::sleep(PARK_POLL);
+        }
+    }
+
+    #[test]
+    fn sleep_blocks_until_the_clock_is_advanced() {
+        let clock = Arc::new(ManualClock::init());
+        let woke = Arc::new(AtomicBool::new(false));
+        let sleeper = {
+            let (clock, woke) = (clock.clone(), woke.clone());
+            thread::spawn(move || {
+                clock.sleep(Duration::from_millis(100));
+                woke.store(true, Ordering::SeqCst);
+            })
+        };
+        wait_for_parked(&clock, 1);
+        assert_eq!(clock.next_deadline(), Some(clock.now() + Duration::from_millis(100)));
+        clock.advance(Duration::from_millis(99));
+        thread::sleep(Duration::from_millis(20));
+        assert!(!woke.load(Ordering::SeqCst));
+
+        clock.advance(Duration::from_millis(1));
+        sleeper.join().unwrap();
+        assert!(woke.load(Ordering::SeqCst));
+        assert_eq!(clock.elapsed(), Duration::from_millis(100));
+        assert_eq!(clock.parked(), 0);
+    }
+
+    #[test]
+    fn park_returns_once_ready_without_moving_the_clock() {
+        let clock = Arc::new(ManualClock::init());
+        let ready = Arc::new(AtomicBool::new(false));
+        let parker = {
+            let (clock, ready) = (clock.clone(), ready.clone());
+            thread::spawn(move || clock.park(clock.now() + Duration::from_secs(1), Arc::new(move || ready.load(Ordering::SeqCst))))
+        };
+        wait_for_parked(&clock, 1);
+        ready.store(true, Ordering::SeqCst);
+        parker.join().unwrap();
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+    }
+
+    #[test]
+    fn stepped_tasks_run_one_at_a_time_in_wake_order() {
+        let clock = Arc::new(ManualClock::stepped());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for name in ["a", "b"] {
+            let (task_clock, order) = (clock.clone(), order.clone());
+            clock.spawn(Box::new(move || {
+                order.lock().unwrap().push(format!("{} started", name));
+                task_clock.sleep(Duration::from_millis(10));
+                order.lock().unwrap().push(format!("{} woke", name));
+            }));
+        }
+        let step = || {
+            assert!(clock.wait_until_parked(Duration::from_secs(5)));
+            clock.wake_next()
+        };
+        while step() {}
+        assert_eq!(*order.lock().unwrap(), vec!["a started", "b started"]);
+
+        clock.advance(Duration::from_millis(10));
+        while step() {}
+        assert_eq!(*order.lock().unwrap(), vec!["a started", "b started", "a woke", "b woke"]);
+        assert_eq!(clock.parked(), 0);
+    }
+}
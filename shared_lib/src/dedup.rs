@@ -0,0 +1,140 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use json::JsonValue;
+use crate::clock::Clock;
+
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(30);
+
+pub enum Lookup {
+    New,
+    InProgress,
+    Replied(JsonValue),
+}
+
+enum Entry {
+    Pending,
+    Replied(JsonValue),
+}
+
+struct Entries {
+    by_request: HashMap<(String, i32), (Instant, Entry)>,
+    /// Requests in the order they started, so expired ones are evicted from the front.
+    order: VecDeque<(Instant, (String, i32))>,
+}
+
+/// Remembers requests by (src, msg_id) and the reply sent for each, so a retransmitted
+/// request can be answered again without re-running its handler.
+pub struct ReplyCache {
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    entries: Mutex<Entries>,
+}
+
+impl ReplyCache {
+    pub fn init(window: Duration, clock: Arc<dyn Clock>) -> ReplyCache {
+        ReplyCache {
+            window,
+            clock,
+            entries: Mutex::new(Entries { by_request: HashMap::new(), order: VecDeque::new() }),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn begin(&self, src: &str, msg_id: i32) -> Lookup {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+        while let Some((started, _)) = entries.order.front() {
+            if now.duration_since(*started) < self.window {
+                break;
+            }
+            if let Some((started, request)) = entries.order.pop_front() {
+                if entries.by_request.get(&request).is_some_and(|(began, _)| *began == started) {
+                    entries.by_request.remove(&request);
+                }
+            }
+        }
+        let request = (src.to_string(), msg_id);
+        match entries.by_request.get(&request) {
+            Some((_, Entry::Pending)) => Lookup::InProgress,
+            Some((_, Entry::Replied(reply))) => Lookup::Replied(reply.clone()),
+            None => {
+                entries.by_request.insert(request.clone(), (now, Entry::Pending));
+                entries.order.push_back((now, request));
+                Lookup::New
+            }
+        }
+    }
+
+    pub fn record(&self, dest: &str, in_reply_to: i32, reply: &JsonValue) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some((_, entry)) = entries.by_request.get_mut(&(dest.to_string(), in_reply_to)) {
+            if let Entry::Pending = entry {
+                *entry = Entry::Replied(reply.clone());
+            }
+        }
+    }
+
+    /// Forgets a request whose handler finished without replying, so a retransmit of it
+    /// is handled again instead of being dropped as in progress.
+    pub fn abandon(&self, src: &str, msg_id: i32) {
+        let mut entries = self.entries.lock().unwrap();
+        let request = (src.to_string(), msg_id);
+        if let Some((_, Entry::Pending)) = entries.by_request.get(&request) {
+            entries.by_request.remove(&request);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().by_request.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::ManualClock;
+    use super::*;
+
+    #[test]
+    fn moves_from_new_to_in_progress_to_replied() {
+        let cache = ReplyCache::init(Duration::from_secs(1), Arc::new(ManualClock::init()));
+        assert!(matches!(cache.begin("c1", 1), Lookup::New));
+        assert!(matches!(cache.begin("c1", 1), Lookup::InProgress));
+        assert!(matches!(cache.begin("c2", 1), Lookup::New));
+
+        cache.record("c1", 1, &JsonValue::from("reply"));
+        match cache.begin("c1", 1) {
+            Lookup::Replied(reply) => assert_eq!(reply, "reply"),
+            _ => panic!("expected the cached reply"),
+        }
+        cache.record("c3", 1, &JsonValue::from("unknown"));
+        assert_eq!(cache.len(), 2);
+
+        cache.abandon("c1", 1);
+        cache.abandon("c2", 1);
+        assert!(matches!(cache.begin("c1", 1), Lookup::Replied(_)));
+        assert!(matches!(cache.begin("c2", 1), Lookup::New));
+    }
+
+    #[test]
+    fn forgets_requests_after_the_window() {
+        let clock = Arc::new(ManualClock::init());
+        let cache = ReplyCache::init(Duration::from_secs(1), clock.clone());
+        cache.begin("c1", 1);
+        cache.record("c1", 1, &JsonValue::from("reply"));
+        clock.advance(Duration::from_millis(600));
+        cache.begin("c1", 2);
+
+        clock.advance(Duration::from_millis(400));
+        assert!(matches!(cache.begin("c1", 1), Lookup::New));
+        assert!(matches!(cache.begin("c1", 2), Lookup::InProgress));
+        assert_eq!(cache.len(), 2);
+    }
+}
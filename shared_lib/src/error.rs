@@ -1,73 +1,248 @@
+use std::error::Error;
+use std::fmt;
+use json::JsonValue;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ErrorCode {
+    Timeout,
+    NodeNotFound,
+    NotSupported,
+    TemporarilyUnavailable,
+    MalformedRequest,
+    Crash,
+    Abort,
+    KeyDoesNotExist,
+    KeyAlreadyExists,
+    PreconditionFailed,
+    TxnConflict,
+    Custom(i32),
+}
+
+impl ErrorCode {
+    pub fn from_code(code: i32) -> ErrorCode {
+        match code {
+            0 => ErrorCode::Timeout,
+            1 => ErrorCode::NodeNotFound,
+            10 => ErrorCode::NotSupported,
+            11 => ErrorCode::TemporarilyUnavailable,
+            12 => ErrorCode::MalformedRequest,
+            13 => ErrorCode::Crash,
+            14 => ErrorCode::Abort,
+            20 => ErrorCode::KeyDoesNotExist,
+            21 => ErrorCode::KeyAlreadyExists,
+            22 => ErrorCode::PreconditionFailed,
+            30 => ErrorCode::TxnConflict,
+            code => ErrorCode::Custom(code),
+        }
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            ErrorCode::Timeout => 0,
+            ErrorCode::NodeNotFound => 1,
+            ErrorCode::NotSupported => 10,
+            ErrorCode::TemporarilyUnavailable => 11,
+            ErrorCode::MalformedRequest => 12,
+            ErrorCode::Crash => 13,
+            ErrorCode::Abort => 14,
+            ErrorCode::KeyDoesNotExist => 20,
+            ErrorCode::KeyAlreadyExists => 21,
+            ErrorCode::PreconditionFailed => 22,
+            ErrorCode::TxnConflict => 30,
+            ErrorCode::Custom(code) => *code,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::NodeNotFound => "node-not-found",
+            ErrorCode::NotSupported => "not-supported",
+            ErrorCode::TemporarilyUnavailable => "temporarily-unavailable",
+            ErrorCode::MalformedRequest => "malformed-request",
+            ErrorCode::Crash => "crash",
+            ErrorCode::Abort => "abort",
+            ErrorCode::KeyDoesNotExist => "key-does-not-exist",
+            ErrorCode::KeyAlreadyExists => "key-already-exists",
+            ErrorCode::PreconditionFailed => "precondition-failed",
+            ErrorCode::TxnConflict => "txn-conflict",
+            ErrorCode::Custom(_) => "custom",
+        }
+    }
+
+    /// A definite error means the operation certainly did not take place. Timeouts,
+    /// crashes and codes we don't know about leave it unknown.
+    pub fn is_definite(&self) -> bool {
+        !matches!(self, ErrorCode::Timeout | ErrorCode::Crash | ErrorCode::Custom(_))
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ErrorCode::Timeout | ErrorCode::TemporarilyUnavailable | ErrorCode::Crash)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name(), self.code())
+    }
+}
+
 #[derive(Debug)]
 pub struct MaelstromError {
     pub in_reply_to: i32,
     pub error: DefiniteError,
 }
+
+impl fmt::Display for MaelstromError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in reply to {}", self.error, self.in_reply_to)
+    }
+}
+
+impl Error for MaelstromError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
 #[derive(Debug)]
 pub struct DefiniteError {
     pub code: i32,
     pub text: String,
 }
 
-pub fn node_not_found(text: String) -> DefiniteError {
-    DefiniteError {
-        code: 1,
-        text: text,
+impl DefiniteError {
+    pub fn init(code: ErrorCode, text: String) -> DefiniteError {
+        DefiniteError {
+            code: code.code(),
+            text,
+        }
+    }
+
+    pub fn from_body(body: &JsonValue) -> Option<DefiniteError> {
+        if body["type"] != "error" {
+            return None;
+        }
+        Some(DefiniteError {
+            code: body["code"].as_i32().unwrap_or_else(|| ErrorCode::Crash.code()),
+            text: body["text"].as_str().unwrap_or_default().to_string(),
+        })
+    }
+
+    pub fn error_code(&self) -> ErrorCode {
+        ErrorCode::from_code(self.code)
+    }
+
+    pub fn is_definite(&self) -> bool {
+        self.error_code().is_definite()
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.error_code().is_retryable()
     }
 }
 
-pub fn not_supported(text: String) -> DefiniteError {
-    DefiniteError {
-        code: 10,
-        text: text,
+impl fmt::Display for DefiniteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.error_code(), self.text)
     }
 }
 
+impl Error for DefiniteError {}
+
+pub fn timeout(text: String) -> DefiniteError {
+    DefiniteError::init(ErrorCode::Timeout, text)
+}
+
+pub fn node_not_found(text: String) -> DefiniteError {
+    DefiniteError::init(ErrorCode::NodeNotFound, text)
+}
+
+pub fn not_supported(text: String) -> DefiniteError {
+    DefiniteError::init(ErrorCode::NotSupported, text)
+}
+
 pub fn temporarily_unavailable(text: String) -> DefiniteError {
-    DefiniteError {
-        code: 11,
-        text: text,
-    }
+    DefiniteError::init(ErrorCode::TemporarilyUnavailable, text)
 }
 
 pub fn malformed_request(text: String) -> DefiniteError {
-    DefiniteError {
-        code: 12,
-        text: text,
-    }
+    DefiniteError::init(ErrorCode::MalformedRequest, text)
+}
+
+pub fn crash(text: String) -> DefiniteError {
+    DefiniteError::init(ErrorCode::Crash, text)
 }
 
 pub fn abort(text: String) -> DefiniteError {
-    DefiniteError {
-        code: 14,
-        text: text,
-    }
+    DefiniteError::init(ErrorCode::Abort, text)
 }
 
 pub fn key_does_not_exist(text: String) -> DefiniteError {
-    DefiniteError {
-        code: 20,
-        text: text,
-    }
+    DefiniteError::init(ErrorCode::KeyDoesNotExist, text)
 }
 
 pub fn key_already_exists(text: String) -> DefiniteError {
-    DefiniteError {
-        code: 21,
-        text: text,
-    }
+    DefiniteError::init(ErrorCode::KeyAlreadyExists, text)
 }
 
 pub fn precondition_failed(text: String) -> DefiniteError {
-    DefiniteError {
-        code: 22,
-        text: text,
-    }
+    DefiniteError::init(ErrorCode::PreconditionFailed, text)
 }
 
 pub fn txn_conflict(text: String) -> DefiniteError {
-    DefiniteError {
-        code: 30,
-        text: text,
+    DefiniteError::init(ErrorCode::TxnConflict, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use json::object;
+
+    #[test]
+    fn codes_round_trip_and_unknown_ones_are_custom() {
+        for code in [0, 1, 10, 11, 12, 13, 14, 20, 21, 22, 30, 1000] {
+            assert_eq!(ErrorCode::from_code(code).code(), code);
+        }
+        assert_eq!(ErrorCode::from_code(0), ErrorCode::Timeout);
+        assert_eq!(ErrorCode::from_code(13), ErrorCode::Crash);
+        assert_eq!(ErrorCode::from_code(1000), ErrorCode::Custom(1000));
+    }
+
+    #[test]
+    fn only_timeouts_crashes_and_unknown_codes_are_indefinite() {
+        let indefinite = [ErrorCode::Timeout, ErrorCode::Crash, ErrorCode::Custom(1000)];
+        for code in [0, 1, 10, 11, 12, 13, 14, 20, 21, 22, 30, 1000] {
+            let error = ErrorCode::from_code(code);
+            assert_eq!(error.is_definite(), !indefinite.contains(&error), "{}", error);
+        }
+        assert!(ErrorCode::TemporarilyUnavailable.is_retryable());
+        assert!(!ErrorCode::KeyDoesNotExist.is_retryable());
+        assert!(!ErrorCode::PreconditionFailed.is_retryable());
+    }
+
+    #[test]
+    fn parses_error_bodies() {
+        let error = DefiniteError::from_body(&object! {type: "error", code: 22, text: "expected 1"}).unwrap();
+        assert_eq!(error.error_code(), ErrorCode::PreconditionFailed);
+        assert!(error.is_definite());
+        assert_eq!(error.to_string(), "precondition-failed (22): expected 1");
+
+        let error = DefiniteError::from_body(&object! {type: "error"}).unwrap();
+        assert_eq!(error.error_code(), ErrorCode::Crash);
+        assert!(!error.is_definite());
+
+        assert!(DefiniteError::from_body(&object! {type: "read_ok", value: 1}).is_none());
+    }
+
+    #[test]
+    fn propagates_with_the_question_mark_operator() {
+        fn read() -> Result<(), Box<dyn Error>> {
+            Err(MaelstromError { in_reply_to: 7, error: key_does_not_exist("no key 1".to_string()) })?;
+            Ok(())
+        }
+        let error = read().unwrap_err();
+        assert_eq!(error.to_string(), "key-does-not-exist (20): no key 1 in reply to 7");
+        assert_eq!(error.source().unwrap().to_string(), "key-does-not-exist (20): no key 1");
     }
 }
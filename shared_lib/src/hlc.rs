@@ -0,0 +1,149 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use json::{object, JsonValue};
+use crate::clock::Clock;
+
+pub const HLC_FIELD: &str = "hlc";
+
+/// A hybrid logical clock reading: physical milliseconds plus a counter that orders
+/// events within the same millisecond or while a peer's clock runs ahead.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct Timestamp {
+    pub wall: u64,
+    pub logical: u32,
+}
+
+impl Timestamp {
+    pub fn from_json(json: &JsonValue) -> Option<Timestamp> {
+        Some(Timestamp {
+            wall: json["wall"].as_u64()?,
+            logical: json["logical"].as_u32()?,
+        })
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        object! {wall: self.wall, logical: self.logical}
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.wall, self.logical)
+    }
+}
+
+pub struct HybridClock {
+    clock: Arc<dyn Clock>,
+    anchor: Instant,
+    anchor_wall: u64,
+    last: Mutex<Timestamp>,
+}
+
+impl HybridClock {
+    /// Physical time is read from `clock`. Under a real-time clock it starts at the wall
+    /// clock; otherwise it starts at zero so runs are reproducible.
+    pub fn init(clock: Arc<dyn Clock>) -> HybridClock {
+        let anchor_wall = if clock.is_real_time() {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default()
+        } else {
+            0
+        };
+        HybridClock {
+            anchor: clock.now(),
+            clock,
+            anchor_wall,
+            last: Mutex::new(Timestamp::default()),
+        }
+    }
+
+    fn physical(&self) -> u64 {
+        self.anchor_wall + self.clock.now().saturating_duration_since(self.anchor).as_millis() as u64
+    }
+
+    pub fn current(&self) -> Timestamp {
+        *self.last.lock().unwrap()
+    }
+
+    /// Advances the clock for a local or send event and returns the new reading.
+    pub fn tick(&self) -> Timestamp {
+        let physical = self.physical();
+        let mut last = self.last.lock().unwrap();
+        *last = if physical > last.wall {
+            Timestamp { wall: physical, logical: 0 }
+        } else {
+            Timestamp { wall: last.wall, logical: last.logical + 1 }
+        };
+        *last
+    }
+
+    /// Merges a timestamp received from a peer, so everything after it is ordered after it.
+    pub fn update(&self, remote: Timestamp) -> Timestamp {
+        let physical = self.physical();
+        let mut last = self.last.lock().unwrap();
+        let wall = physical.max(last.wall).max(remote.wall);
+        let logical = if wall == last.wall && wall == remote.wall {
+            last.logical.max(remote.logical) + 1
+        } else if wall == last.wall {
+            last.logical + 1
+        } else if wall == remote.wall {
+            remote.logical + 1
+        } else {
+            0
+        };
+        *last = Timestamp { wall, logical };
+        *last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::clock::ManualClock;
+    use super::*;
+
+    fn timestamp(wall: u64, logical: u32) -> Timestamp {
+        Timestamp { wall, logical }
+    }
+
+    #[test]
+    fn ticks_within_a_millisecond_bump_the_counter() {
+        let clock = Arc::new(ManualClock::init());
+        let hlc = HybridClock::init(clock.clone());
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(hlc.tick(), timestamp(5, 0));
+        assert_eq!(hlc.tick(), timestamp(5, 1));
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(hlc.tick(), timestamp(6, 0));
+    }
+
+    #[test]
+    fn merges_stay_ahead_of_local_and_remote_readings() {
+        let clock = Arc::new(ManualClock::init());
+        let hlc = HybridClock::init(clock.clone());
+        clock.advance(Duration::from_millis(10));
+        let remotes = [timestamp(50, 3), timestamp(20, 9), timestamp(50, 7), timestamp(0, 0), timestamp(50, 2)];
+        let mut previous = hlc.tick();
+        for remote in remotes {
+            let merged = hlc.update(remote);
+            assert!(merged > previous, "{} not after {}", merged, previous);
+            assert!(merged > remote, "{} not after remote {}", merged, remote);
+            previous = merged;
+            clock.advance(Duration::from_millis(3));
+        }
+        assert_eq!(previous, timestamp(50, 10));
+
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(hlc.update(timestamp(50, 20)), timestamp(125, 0));
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let reading = timestamp(1234, 5);
+        assert_eq!(Timestamp::from_json(&reading.to_json()), Some(reading));
+        assert_eq!(Timestamp::from_json(&object! {wall: 1}), None);
+    }
+}
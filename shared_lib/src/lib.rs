@@ -1,8 +1,30 @@
+pub mod quorum;
 pub mod read_respond;
+pub mod batching;
+pub mod callbacks;
+pub mod clock;
+pub mod dedup;
+pub mod message;
 pub mod message_handler;
 pub mod node_state;
 pub mod error;
+pub mod hlc;
+pub mod lifecycle;
+pub mod logging;
+pub mod mailbox;
 pub mod message_handlers;
 pub mod message_utils;
+pub mod metrics;
+pub mod middleware;
+pub mod random;
+pub mod recording;
+pub mod replay;
+pub mod retry;
+pub mod router;
 pub mod rpc;
+pub mod scheduler;
+pub mod services;
 pub mod stdio;
+pub mod topology;
+pub mod transport;
+pub mod worker_pool;
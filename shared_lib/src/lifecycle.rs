@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+use json::JsonValue;
+
+pub const DEFAULT_INIT_QUEUE: usize = 128;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Uninitialized,
+    Running,
+    ShuttingDown,
+}
+
+pub enum Admission {
+    Run(JsonValue),
+    Held,
+    Rejected(JsonValue),
+}
+
+struct Inner {
+    phase: Phase,
+    held: VecDeque<JsonValue>,
+    capacity: usize,
+}
+
+/// Tracks where the node is in its life and holds messages that arrive before `init`
+/// has been handled, up to a bound. With a capacity of zero they are all rejected.
+pub struct Lifecycle {
+    inner: Mutex<Inner>,
+    changed: Condvar,
+}
+
+impl Lifecycle {
+    pub fn init(capacity: usize) -> Lifecycle {
+        Lifecycle {
+            inner: Mutex::new(Inner {
+                phase: Phase::Uninitialized,
+                held: VecDeque::new(),
+                capacity,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.inner.lock().unwrap().phase
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        self.inner.lock().unwrap().capacity = capacity;
+    }
+
+    pub fn held(&self) -> usize {
+        self.inner.lock().unwrap().held.len()
+    }
+
+    pub fn admit(&self, message: JsonValue) -> Admission {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.phase != Phase::Uninitialized || message["body"]["type"] == "init" {
+            return Admission::Run(message);
+        }
+        if inner.held.len() >= inner.capacity {
+            return Admission::Rejected(message);
+        }
+        inner.held.push_back(message);
+        Admission::Held
+    }
+
+    /// Hands back the messages held so far, in arrival order, without starting, so they can
+    /// be queued for handling ahead of anything that arrives once the node is running.
+    pub fn take_held(&self) -> Vec<JsonValue> {
+        self.inner.lock().unwrap().held.drain(..).collect()
+    }
+
+    /// Moves to running unless messages are still held, in which case it returns false and
+    /// they should be taken first.
+    pub fn start(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.phase != Phase::Uninitialized {
+            return true;
+        }
+        if !inner.held.is_empty() {
+            return false;
+        }
+        inner.phase = Phase::Running;
+        self.changed.notify_all();
+        true
+    }
+
+    /// Blocks until the node starts running or shuts down, for at most `timeout`. Returns
+    /// whether it is running.
+    pub fn wait_until_running(&self, timeout: Duration) -> bool {
+        let inner = self.inner.lock().unwrap();
+        let (inner, _) = self.changed.wait_timeout_while(inner, timeout, |inner| inner.phase == Phase::Uninitialized).unwrap();
+        inner.phase == Phase::Running
+    }
+
+    /// Moves to shutting down and returns how many held messages were dropped.
+    pub fn begin_shutdown(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        inner.phase = Phase::ShuttingDown;
+        let dropped = inner.held.len();
+        inner.held.clear();
+        self.changed.notify_all();
+        dropped
+    }
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Lifecycle::init(DEFAULT_INIT_QUEUE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use json::object;
+    use super::*;
+
+    fn message(message_type: &str) -> JsonValue {
+        object! {src: "c1", dest: "n1", body: {type: message_type, msg_id: 1}}
+    }
+
+    #[test]
+    fn holds_messages_until_start() {
+        let lifecycle = Lifecycle::init(2);
+        assert!(matches!(lifecycle.admit(message("read")), Admission::Held));
+        assert!(matches!(lifecycle.admit(message("init")), Admission::Run(_)));
+        assert!(matches!(lifecycle.admit(message("write")), Admission::Held));
+        assert!(matches!(lifecycle.admit(message("cas")), Admission::Rejected(_)));
+
+        assert!(!lifecycle.start());
+        assert_eq!(lifecycle.take_held(), vec![message("read"), message("write")]);
+        assert_eq!(lifecycle.phase(), Phase::Uninitialized);
+        assert!(lifecycle.start());
+        assert_eq!(lifecycle.phase(), Phase::Running);
+        assert!(matches!(lifecycle.admit(message("read")), Admission::Run(_)));
+        assert!(lifecycle.take_held().is_empty());
+    }
+
+    #[test]
+    fn waiters_wake_when_the_node_starts() {
+        let lifecycle = Arc::new(Lifecycle::default());
+        assert!(!lifecycle.wait_until_running(Duration::from_millis(5)));
+
+        let waiter = {
+            let lifecycle = lifecycle.clone();
+            thread::spawn(move || lifecycle.wait_until_running(Duration::from_secs(30)))
+        };
+        thread::sleep(Duration::from_millis(5));
+        assert!(lifecycle.start());
+        assert!(waiter.join().unwrap());
+    }
+
+    #[test]
+    fn shutdown_drops_held_messages() {
+        let lifecycle = Lifecycle::default();
+        lifecycle.admit(message("read"));
+        assert_eq!(lifecycle.begin_shutdown(), 1);
+        assert_eq!(lifecycle.held(), 0);
+        assert_eq!(lifecycle.phase(), Phase::ShuttingDown);
+        assert!(lifecycle.start());
+        assert_eq!(lifecycle.phase(), Phase::ShuttingDown);
+        assert!(!lifecycle.wait_until_running(Duration::from_secs(30)));
+    }
+}
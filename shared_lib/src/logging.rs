@@ -0,0 +1,293 @@
+use std::env;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use json::{object, JsonValue};
+use crate::stdio::write_log;
+
+pub const LOG_ENV: &str = "MAELSTROM_LOG";
+pub const LOG_FORMAT_ENV: &str = "MAELSTROM_LOG_FORMAT";
+
+static CONFIG: RwLock<Option<LogConfig>> = RwLock::new(None);
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn parse(text: &str) -> Option<Level> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" | "warning" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// A filter spec looks like `info,rpc=debug,raft::election=trace`: a bare level sets
+/// the default and `target=level` overrides it for that target and its children.
+#[derive(Clone, Debug)]
+pub struct LogConfig {
+    level: Level,
+    targets: Vec<(String, Level)>,
+    format: Format,
+}
+
+impl LogConfig {
+    pub fn init(level: Level, format: Format) -> LogConfig {
+        LogConfig {
+            level,
+            targets: Vec::new(),
+            format,
+        }
+    }
+
+    pub fn parse(spec: &str, format: Format) -> LogConfig {
+        let mut config = LogConfig::init(Level::Info, format);
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => match Level::parse(level) {
+                    Some(level) => config = config.with_target(target.trim(), level),
+                    None => write_log(&format!("Ignoring log directive {}", directive)),
+                },
+                None => match Level::parse(directive) {
+                    Some(level) => config.level = level,
+                    None => write_log(&format!("Ignoring log directive {}", directive)),
+                },
+            }
+        }
+        config
+    }
+
+    pub fn from_env() -> LogConfig {
+        let format = match env::var(LOG_FORMAT_ENV) {
+            Ok(format) if format.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Text,
+        };
+        LogConfig::parse(&env::var(LOG_ENV).unwrap_or_default(), format)
+    }
+
+    pub fn with_target(mut self, target: &str, level: Level) -> LogConfig {
+        self.targets.retain(|(t, _)| t != target);
+        self.targets.push((target.to_string(), level));
+        self
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn level_for(&self, target: &str) -> Level {
+        self.targets
+            .iter()
+            .filter(|(t, _)| target == t || target.starts_with(&format!("{}::", t)))
+            .max_by_key(|(t, _)| t.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.level)
+    }
+
+    pub fn enabled(&self, level: Level, target: &str) -> bool {
+        level <= self.level_for(target)
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig::init(Level::Info, Format::Text)
+    }
+}
+
+pub fn configure(config: LogConfig) {
+    CONFIG.write().unwrap().replace(config);
+}
+
+fn with_config<R>(f: impl FnOnce(&LogConfig) -> R) -> R {
+    if let Some(config) = CONFIG.read().unwrap().as_ref() {
+        return f(config);
+    }
+    let mut config = CONFIG.write().unwrap();
+    f(config.get_or_insert_with(LogConfig::from_env))
+}
+
+pub fn enabled(level: Level, target: &str) -> bool {
+    with_config(|config| config.enabled(level, target))
+}
+
+pub struct Event {
+    level: Level,
+    target: &'static str,
+    node: Option<String>,
+    term: Option<i32>,
+    msg_id: Option<i32>,
+}
+
+impl Event {
+    pub fn node(mut self, node_id: &str) -> Event {
+        self.node = Some(node_id.to_string());
+        self
+    }
+
+    pub fn term(mut self, term: i32) -> Event {
+        self.term = Some(term);
+        self
+    }
+
+    pub fn msg_id(mut self, msg_id: i32) -> Event {
+        self.msg_id = Some(msg_id);
+        self
+    }
+
+    pub fn enabled(&self) -> bool {
+        enabled(self.level, self.target)
+    }
+
+    pub fn log(self, text: &str) {
+        let format = match with_config(|config| config.enabled(self.level, self.target).then(|| config.format())) {
+            Some(format) => format,
+            None => return,
+        };
+        match format {
+            Format::Text => write_log(&self.to_text(text)),
+            Format::Json => write_log(&self.to_json(text).dump()),
+        }
+    }
+
+    fn to_text(&self, text: &str) -> String {
+        let mut line = format!("{:<5} [{}]", self.level.as_str().to_ascii_uppercase(), self.target);
+        if let Some(node) = &self.node {
+            line.push_str(&format!(" node={}", node));
+        }
+        if let Some(term) = self.term {
+            line.push_str(&format!(" term={}", term));
+        }
+        if let Some(msg_id) = self.msg_id {
+            line.push_str(&format!(" msg_id={}", msg_id));
+        }
+        line.push(' ');
+        line.push_str(text);
+        line
+    }
+
+    fn to_json(&self, text: &str) -> JsonValue {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let mut line = object! {
+            ts: timestamp,
+            level: self.level.as_str(),
+            target: self.target,
+            msg: text,
+        };
+        if let Some(node) = &self.node {
+            line["node"] = JsonValue::from(node.as_str());
+        }
+        if let Some(term) = self.term {
+            line["term"] = JsonValue::from(term);
+        }
+        if let Some(msg_id) = self.msg_id {
+            line["msg_id"] = JsonValue::from(msg_id);
+        }
+        line
+    }
+}
+
+pub fn event(level: Level, target: &'static str) -> Event {
+    Event {
+        level,
+        target,
+        node: None,
+        term: None,
+        msg_id: None,
+    }
+}
+
+pub fn error(target: &'static str) -> Event {
+    event(Level::Error, target)
+}
+
+pub fn warn(target: &'static str) -> Event {
+    event(Level::Warn, target)
+}
+
+pub fn info(target: &'static str) -> Event {
+    event(Level::Info, target)
+}
+
+pub fn debug(target: &'static str) -> Event {
+    event(Level::Debug, target)
+}
+
+pub fn trace(target: &'static str) -> Event {
+    event(Level::Trace, target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_bare_level_sets_the_default_and_targets_override_it() {
+        let config = LogConfig::parse("warn, rpc=debug, raft::election=trace", Format::Text);
+        assert_eq!(config.level_for("read_respond"), Level::Warn);
+        assert_eq!(config.level_for("rpc"), Level::Debug);
+        assert_eq!(config.level_for("rpc::retry"), Level::Debug);
+        assert_eq!(config.level_for("rpcs"), Level::Warn);
+        assert_eq!(config.level_for("raft::election"), Level::Trace);
+        assert!(config.enabled(Level::Debug, "rpc"));
+        assert!(!config.enabled(Level::Info, "raft"));
+    }
+
+    #[test]
+    fn the_longest_matching_target_wins() {
+        let config = LogConfig::parse("raft=error,raft::election=debug,raft=warn", Format::Text);
+        assert_eq!(config.level_for("raft::log"), Level::Warn);
+        assert_eq!(config.level_for("raft::election::timer"), Level::Debug);
+    }
+
+    #[test]
+    fn bad_directives_are_ignored_and_the_default_is_info() {
+        let config = LogConfig::parse("loud,rpc=chatty,,", Format::Text);
+        assert_eq!(config.level_for("rpc"), Level::Info);
+        assert_eq!(LogConfig::parse("", Format::Json).level_for("raft"), Level::Info);
+        assert_eq!(LogConfig::default().format(), Format::Text);
+    }
+
+    #[test]
+    fn json_lines_carry_only_the_fields_that_were_set() {
+        let line = warn("raft").node("n2").term(4).to_json("Lost the election");
+        assert_eq!(line["level"], "warn");
+        assert_eq!(line["target"], "raft");
+        assert_eq!(line["msg"], "Lost the election");
+        assert_eq!(line["node"], "n2");
+        assert_eq!(line["term"], 4);
+        assert!(line["ts"].as_u64().unwrap() > 0);
+        assert!(!line.has_key("msg_id"));
+
+        assert_eq!(json::parse(&line.dump()).unwrap(), line);
+        assert_eq!(info("rpc").msg_id(3).to_text("Sent"), "INFO  [rpc] msg_id=3 Sent");
+    }
+}
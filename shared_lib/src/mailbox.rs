@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+use crate::clock::Clock;
+
+/// A queue that one thread waits on while others push to it. Deadlines are measured on
+/// the node's clock, so a manual clock decides when a wait times out.
+pub struct Mailbox<T> {
+    items: Mutex<VecDeque<T>>,
+    arrived: Condvar,
+}
+
+impl<T: Send + 'static> Mailbox<T> {
+    pub fn init() -> Arc<Mailbox<T>> {
+        Arc::new(Mailbox { items: Mutex::new(VecDeque::new()), arrived: Condvar::new() })
+    }
+
+    pub fn push(&self, item: T) {
+        self.items.lock().unwrap().push_back(item);
+        self.arrived.notify_all();
+    }
+
+    pub fn try_take(&self) -> Option<T> {
+        self.items.lock().unwrap().pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.lock().unwrap().is_empty()
+    }
+
+    /// Takes the next item, waiting for one until `clock` reaches `deadline`.
+    pub fn take_until(self: &Arc<Self>, clock: &dyn Clock, deadline: Instant) -> Option<T> {
+        if !clock.is_real_time() {
+            let mailbox = Arc::clone(self);
+            clock.park(deadline, Arc::new(move || !mailbox.is_empty()));
+            return self.try_take();
+        }
+        let mut items = self.items.lock().unwrap();
+        loop {
+            if let Some(item) = items.pop_front() {
+                return Some(item);
+            }
+            let now = clock.now();
+            if now >= deadline {
+                return None;
+            }
+            items = self.arrived.wait_timeout(items, deadline - now).unwrap().0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use crate::clock::{ManualClock, SystemClock};
+    use super::*;
+
+    #[test]
+    fn waits_for_an_item_on_the_system_clock() {
+        let mailbox = Mailbox::init();
+        let sender = Arc::clone(&mailbox);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            sender.push(7);
+        });
+        let clock = SystemClock {};
+        assert_eq!(mailbox.take_until(&clock, clock.now() + Duration::from_secs(5)), Some(7));
+        assert_eq!(mailbox.take_until(&clock, clock.now() + Duration::from_millis(10)), None);
+    }
+
+    #[test]
+    fn times_out_only_when_a_manual_clock_passes_the_deadline() {
+        let clock = Arc::new(ManualClock::init());
+        let mailbox: Arc<Mailbox<i32>> = Mailbox::init();
+        let waiter = {
+            let (clock, mailbox) = (clock.clone(), mailbox.clone());
+            thread::spawn(move || mailbox.take_until(&*clock, clock.now() + Duration::from_millis(50)))
+        };
+        while clock.parked() == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        thread::sleep(Duration::from_millis(20));
+        assert!(!waiter.is_finished());
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(waiter.join().unwrap(), None);
+    }
+}
@@ -0,0 +1,219 @@
+use json::{object, JsonValue};
+use crate::error::{malformed_request, DefiniteError, MaelstromError};
+use crate::message_utils::get_body;
+
+pub trait Body: Sized {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError>;
+
+    fn to_json(&self) -> JsonValue;
+}
+
+#[derive(Clone, Debug)]
+pub struct Message<B: Body> {
+    pub src: String,
+    pub dest: String,
+    pub msg_id: Option<i32>,
+    pub in_reply_to: Option<i32>,
+    pub body: B,
+}
+
+impl<B: Body> Message<B> {
+    pub fn from_json(message: &JsonValue) -> Result<Message<B>, DefiniteError> {
+        let body = get_body(message);
+        if !body.is_object() {
+            return Err(malformed_request("Message has no body".to_string()));
+        }
+        Ok(Message {
+            src: string_field(message, "src")?,
+            dest: string_field(message, "dest")?,
+            msg_id: body["msg_id"].as_i32(),
+            in_reply_to: body["in_reply_to"].as_i32(),
+            body: B::from_json(body)?,
+        })
+    }
+
+    pub fn error(&self, error: DefiniteError) -> MaelstromError {
+        MaelstromError {
+            in_reply_to: self.msg_id.unwrap_or_default(),
+            error,
+        }
+    }
+}
+
+impl Body for () {
+    fn from_json(_body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(())
+    }
+
+    fn to_json(&self) -> JsonValue {
+        JsonValue::new_object()
+    }
+}
+
+pub struct Init {
+    pub node_id: String,
+    pub node_ids: Vec<String>,
+}
+
+impl Body for Init {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(Init {
+            node_id: string_field(body, "node_id")?,
+            node_ids: string_array_field(body, "node_ids")?,
+        })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "init", node_id: self.node_id.clone(), node_ids: self.node_ids.clone()}
+    }
+}
+
+pub struct InitOk {}
+
+impl Body for InitOk {
+    fn from_json(_body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(InitOk {})
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "init_ok"}
+    }
+}
+
+pub struct Stats {}
+
+impl Body for Stats {
+    fn from_json(_body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(Stats {})
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "stats"}
+    }
+}
+
+pub struct StatsOk {
+    pub stats: JsonValue,
+}
+
+impl Body for StatsOk {
+    fn from_json(body: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(StatsOk { stats: body["stats"].clone() })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        object! {type: "stats_ok", stats: self.stats.clone()}
+    }
+}
+
+pub fn i32_field(json: &JsonValue, field: &str) -> Result<i32, DefiniteError> {
+    json[field].as_i32().ok_or_else(|| invalid_field(field, "an integer"))
+}
+
+pub fn usize_field(json: &JsonValue, field: &str) -> Result<usize, DefiniteError> {
+    json[field].as_usize().ok_or_else(|| invalid_field(field, "a non-negative integer"))
+}
+
+pub fn bool_field(json: &JsonValue, field: &str) -> Result<bool, DefiniteError> {
+    json[field].as_bool().ok_or_else(|| invalid_field(field, "a boolean"))
+}
+
+pub fn string_field(json: &JsonValue, field: &str) -> Result<String, DefiniteError> {
+    json[field]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| invalid_field(field, "a string"))
+}
+
+pub fn string_array_field(json: &JsonValue, field: &str) -> Result<Vec<String>, DefiniteError> {
+    if !json[field].is_array() {
+        return Err(invalid_field(field, "an array of strings"));
+    }
+    json[field]
+        .members()
+        .map(|jv| jv.as_str().map(|s| s.to_string()))
+        .collect::<Option<Vec<String>>>()
+        .ok_or_else(|| invalid_field(field, "an array of strings"))
+}
+
+pub fn i32_array_field(json: &JsonValue, field: &str) -> Result<Vec<i32>, DefiniteError> {
+    if !json[field].is_array() {
+        return Err(invalid_field(field, "an array of integers"));
+    }
+    json[field]
+        .members()
+        .map(|jv| jv.as_i32())
+        .collect::<Option<Vec<i32>>>()
+        .ok_or_else(|| invalid_field(field, "an array of integers"))
+}
+
+fn invalid_field(field: &str, expected: &str) -> DefiniteError {
+    malformed_request(format!("Expected field `{}` to be {}", field, expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ErrorCode;
+    use super::*;
+
+    fn init() -> JsonValue {
+        object! {src: "c0", dest: "n1", body: {type: "init", msg_id: 1, node_id: "n1", node_ids: ["n1", "n2"]}}
+    }
+
+    #[test]
+    fn parses_the_envelope_and_body() {
+        let message = Message::<Init>::from_json(&init()).unwrap();
+        assert_eq!((message.src.as_str(), message.dest.as_str()), ("c0", "n1"));
+        assert_eq!((message.msg_id, message.in_reply_to), (Some(1), None));
+        assert_eq!(message.body.node_id, "n1");
+        assert_eq!(message.body.node_ids, vec!["n1", "n2"]);
+
+        let body = message.body.to_json();
+        let reparsed = Init::from_json(&body).unwrap();
+        assert_eq!(body["type"], "init");
+        assert_eq!((reparsed.node_id, reparsed.node_ids), (message.body.node_id, message.body.node_ids));
+    }
+
+    #[test]
+    fn rejects_missing_envelope_fields_and_bodies() {
+        let mut no_src = init();
+        no_src.remove("src");
+        let error = Message::<Init>::from_json(&no_src).err().unwrap();
+        assert_eq!(error.error_code(), ErrorCode::MalformedRequest);
+        assert_eq!(error.text, "Expected field `src` to be a string");
+
+        let no_body = object! {src: "c0", dest: "n1"};
+        assert_eq!(Message::<()>::from_json(&no_body).unwrap_err().text, "Message has no body");
+    }
+
+    #[test]
+    fn rejects_missing_and_mistyped_body_fields() {
+        let mut message = init();
+        message["body"]["node_ids"] = JsonValue::from(vec![JsonValue::from("n1"), JsonValue::from(2)]);
+        let error = Message::<Init>::from_json(&message).err().unwrap();
+        assert_eq!(error.text, "Expected field `node_ids` to be an array of strings");
+
+        message["body"].remove("node_id");
+        assert_eq!(Message::<Init>::from_json(&message).err().unwrap().text, "Expected field `node_id` to be a string");
+
+        let body = object! {count: 3, negative: -1, flag: true, name: "x", values: [1, 2], mixed: [1, "2"]};
+        assert_eq!(i32_field(&body, "count").unwrap(), 3);
+        assert_eq!(usize_field(&body, "count").unwrap(), 3);
+        assert!(usize_field(&body, "negative").is_err());
+        assert!(bool_field(&body, "flag").unwrap());
+        assert!(bool_field(&body, "count").is_err());
+        assert!(i32_field(&body, "name").is_err());
+        assert!(string_field(&body, "missing").is_err());
+        assert_eq!(i32_array_field(&body, "values").unwrap(), vec![1, 2]);
+        assert!(i32_array_field(&body, "mixed").is_err());
+        assert!(i32_array_field(&body, "count").is_err());
+    }
+
+    #[test]
+    fn errors_reply_to_the_request() {
+        let message = Message::<Init>::from_json(&init()).unwrap();
+        let error = message.error(malformed_request("bad".to_string()));
+        assert_eq!(error.in_reply_to, 1);
+        assert_eq!(error.error.error_code(), ErrorCode::MalformedRequest);
+    }
+}
@@ -1,52 +1,122 @@
-use json::{JsonValue, stringify, object};
-use crate::{error::MaelstromError, node_state::NodeState};
+use json::{JsonValue, object};
+use crate::{error::{not_supported, MaelstromError}, message::{Body, Message}, node_state::NodeState};
+use crate::logging::Level;
 use std::ops::Deref;
+use std::time::Instant;
 
 pub trait MessageHandler<T>: Sync
     where T: Deref<Target=NodeState> {
+    fn handle_message(&self, message: &JsonValue, curr_state: &T);
+}
+
+pub trait RequestHandler<T>: Sync
+    where T: Deref<Target=NodeState> {
+    type Request: Body;
+    type Response: Body;
+
+    /// Handlers that never reply override `get_response_body` instead; if this default is
+    /// reached anyway the sender is told the request is not supported.
     fn make_response_body(
         &self,
-        message: &JsonValue,
+        message: &Message<Self::Request>,
+        _curr_state: &T,
+    ) -> Result<Self::Response, MaelstromError> {
+        Err(message.error(not_supported("request has no reply".to_string())))
+    }
+
+    fn get_response_body(
+        &self,
+        message: &Message<Self::Request>,
         curr_state: &T,
-    ) -> Result<JsonValue, MaelstromError>;
+    ) -> Result<Option<Self::Response>, MaelstromError> {
+        self.make_response_body(message, curr_state)
+            .map(Some)
+    }
+}
 
+impl<T, H> MessageHandler<T> for H
+    where H: RequestHandler<T>,
+          T: Deref<Target=NodeState> {
     fn handle_message(&self, message: &JsonValue, curr_state: &T) {
-        let response = self.get_response_body(message, curr_state);
-        if response.is_err() {
-            let error = response.expect_err("");
-            let error_body = construct_error_body(&error);
-            let response = wrap_response_body(
-                error_body,
-                JsonValue::from(error.in_reply_to),
-                id_from(&message).clone(),
-                curr_state.next_msg_id(),
-                curr_state.node_id(),
-            );
-            curr_state.get_channel().send(stringify(response));
-            return;
+        let started = Instant::now();
+        let succeeded = respond(self, message, curr_state);
+        let message_type = message["body"]["type"].as_str().unwrap_or_default();
+        let metrics = curr_state.metrics();
+        metrics.increment("messages.handled", message_type);
+        if !succeeded {
+            metrics.increment("messages.errors", message_type);
         }
-        let maybe_response_body = response.expect("");
-        if maybe_response_body.is_some() {
-            let response_body = maybe_response_body.unwrap();
+        metrics.observe("handler.latency", message_type, started.elapsed());
+    }
+}
+
+fn respond<T, H>(handler: &H, message: &JsonValue, curr_state: &T) -> bool
+    where H: RequestHandler<T> + ?Sized,
+          T: Deref<Target=NodeState> {
+    let request = match Message::<H::Request>::from_json(message) {
+        Ok(request) => request,
+        Err(error) => {
+            curr_state.log(Level::Warn, "message_handler").log(&format!("Malformed request {}: {}", message, error.text));
+            let in_reply_to = message["body"]["msg_id"].as_i32().unwrap_or_default();
+            send_error(&MaelstromError { in_reply_to, error }, message, curr_state);
+            return false;
+        }
+    };
+    match handler.get_response_body(&request, curr_state) {
+        Ok(Some(response_body)) => {
             let response = wrap_response_body(
-                response_body,
+                response_body.to_json(),
                 message["body"]["msg_id"].clone(),
-                id_from(&message).clone(),
+                id_from(message).clone(),
                 curr_state.next_msg_id(),
                 curr_state.node_id(),
             );
-            curr_state.get_channel().send(stringify(response));
+            curr_state.send_message(response);
+            true
+        }
+        Ok(None) => true,
+        Err(error) => {
+            send_error(&error, message, curr_state);
+            false
         }
     }
+}
 
-    fn get_response_body(
-        &self,
-        message: &JsonValue,
-        curr_state: &T,
-    ) -> Result<Option<JsonValue>, MaelstromError> {
-        self.make_response_body(message, curr_state)
-            .map(|body| Some(body))
+fn send_error(error: &MaelstromError, message: &JsonValue, curr_state: &NodeState) {
+    if message["body"]["msg_id"].is_null() {
+        curr_state.log(Level::Warn, "message_handler").log(&format!("Cannot reply with error {} to a message without a msg_id", error.error.text));
+        return;
     }
+    reply_with_error(error, id_from(message).clone(), curr_state);
+}
+
+pub fn reply_with_error(error: &MaelstromError, dest: JsonValue, curr_state: &NodeState) {
+    if !curr_state.is_initialized() {
+        curr_state.log(Level::Warn, "message_handler").log(&format!("Cannot reply with error {} before init", error.error.text));
+        return;
+    }
+    let error_body = construct_error_body(error);
+    let response = wrap_response_body(
+        error_body,
+        JsonValue::from(error.in_reply_to),
+        dest,
+        curr_state.next_msg_id(),
+        curr_state.node_id(),
+    );
+    curr_state.send_message(response);
+}
+
+/// Replies to `message` with an error, answering from the address it was sent to, so it
+/// works before `init` has told the node its id.
+pub fn reject_message(error: &MaelstromError, message: &JsonValue, curr_state: &NodeState) {
+    let response = wrap_response_body(
+        construct_error_body(error),
+        JsonValue::from(error.in_reply_to),
+        id_from(message).clone(),
+        curr_state.next_msg_id(),
+        message["dest"].to_string(),
+    );
+    curr_state.send_message(response);
 }
 
 pub fn construct_error_body(error: &MaelstromError) -> JsonValue {
@@ -68,3 +138,65 @@ fn wrap_response_body(
 fn id_from(message: &JsonValue) -> &JsonValue {
     &message["src"]
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+    use crate::error::ErrorCode;
+    use super::*;
+
+    struct TestState(NodeState);
+
+    impl Deref for TestState {
+        type Target = NodeState;
+
+        fn deref(&self) -> &NodeState {
+            &self.0
+        }
+    }
+
+    struct Silent {}
+
+    impl RequestHandler<TestState> for Silent {
+        type Request = ();
+        type Response = ();
+    }
+
+    #[test]
+    fn unimplemented_reply_is_an_error_not_a_panic() {
+        let (sender, replies) = sync_channel(4);
+        let state = TestState(NodeState::init(sender));
+        state.set_node_id("n1".to_string());
+        Silent {}.handle_message(&object! {src: "c1", dest: "n1", body: {type: "ping", msg_id: 7}}, &state);
+
+        let reply = json::parse(&replies.try_recv().unwrap()).unwrap();
+        assert_eq!(reply["body"]["in_reply_to"], 7);
+        assert_eq!(ErrorCode::from_code(reply["body"]["code"].as_i32().unwrap()), ErrorCode::NotSupported);
+    }
+
+    struct Ignores {}
+
+    impl RequestHandler<TestState> for Ignores {
+        type Request = crate::message::Init;
+        type Response = ();
+
+        fn make_response_body(&self, _message: &Message<Self::Request>, _curr_state: &TestState) -> Result<(), MaelstromError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn malformed_requests_get_an_error_reply() {
+        let (sender, replies) = sync_channel(4);
+        let state = TestState(NodeState::init(sender));
+        state.set_node_id("n1".to_string());
+        Ignores {}.handle_message(&object! {src: "c1", dest: "n1", body: {type: "init", msg_id: 9, node_id: 1}}, &state);
+
+        let reply = json::parse(&replies.try_recv().unwrap()).unwrap();
+        assert_eq!(reply["dest"], "c1");
+        assert_eq!(reply["body"]["in_reply_to"], 9);
+        assert_eq!(ErrorCode::from_code(reply["body"]["code"].as_i32().unwrap()), ErrorCode::MalformedRequest);
+        assert_eq!(reply["body"]["text"], "Expected field `node_id` to be a string");
+        assert_eq!(state.metrics().counter("messages.errors", "init"), 1);
+    }
+}
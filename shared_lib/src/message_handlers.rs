@@ -1 +1 @@
-
+pub mod stats_handler;
@@ -0,0 +1,54 @@
+use std::ops::Deref;
+use crate::error::MaelstromError;
+use crate::message::{Message, Stats, StatsOk};
+use crate::message_handler::RequestHandler;
+use crate::node_state::NodeState;
+
+pub struct StatsHandler {}
+
+impl<T> RequestHandler<T> for StatsHandler
+    where T: Deref<Target=NodeState>
+{
+    type Request = Stats;
+    type Response = StatsOk;
+
+    fn make_response_body(&self, _message: &Message<Stats>, curr_state: &T) -> Result<StatsOk, MaelstromError> {
+        Ok(StatsOk { stats: curr_state.stats() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+    use json::object;
+    use crate::message_handler::MessageHandler;
+    use super::*;
+
+    struct TestState(NodeState);
+
+    impl Deref for TestState {
+        type Target = NodeState;
+
+        fn deref(&self) -> &NodeState {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn replies_with_counters_gauges_and_histograms() {
+        let (sender, replies) = sync_channel(4);
+        let state = TestState(NodeState::init(sender));
+        state.set_node_id("n1".to_string());
+        state.metrics().increment("messages.received", "stats");
+        StatsHandler {}.handle_message(&object! {src: "c1", dest: "n1", body: {type: "stats", msg_id: 1}}, &state);
+
+        let reply = json::parse(&replies.try_recv().unwrap()).unwrap();
+        let body = &reply["body"];
+        assert_eq!(body["type"], "stats_ok");
+        assert_eq!(body["in_reply_to"], 1);
+        assert_eq!(body["stats"]["counters"]["messages.received.stats"], 1);
+        assert_eq!(body["stats"]["gauges"]["rpc.outstanding"], 0);
+        assert_eq!(body["stats"]["gauges"]["inputs.rejected"], 0);
+        assert!(body["stats"]["histograms"].is_object());
+    }
+}
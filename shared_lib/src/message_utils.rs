@@ -1,7 +1,7 @@
 use json::JsonValue;
 
-pub fn get_message_type(message: &JsonValue) -> String {
-    get_body(message)["type"].as_str().unwrap().to_string()
+pub fn get_message_type(message: &JsonValue) -> Option<String> {
+    get_body(message)["type"].as_str().map(|t| t.to_string())
 }
 
 pub fn get_body(message: &JsonValue) -> &JsonValue {
@@ -11,3 +11,44 @@ pub fn get_body(message: &JsonValue) -> &JsonValue {
 pub fn get_in_response_to(message: &JsonValue) -> Option<i32> {
     message["body"]["in_reply_to"].as_i32()
 }
+
+pub fn get_sender(message: &JsonValue) -> Option<(String, i32)> {
+    let src = message["src"].as_str()?;
+    let msg_id = message["body"]["msg_id"].as_i32()?;
+    Some((src.to_string(), msg_id))
+}
+
+pub fn recover_sender(line: &str) -> Option<(String, i32)> {
+    let src = raw_field(line, "src")?;
+    let src = src.strip_prefix('"')?;
+    let src = &src[..src.find('"')?];
+    let msg_id = raw_field(line, "msg_id")?;
+    let digits = msg_id
+        .char_indices()
+        .take_while(|(i, c)| c.is_ascii_digit() || (*i == 0 && *c == '-'))
+        .count();
+    let msg_id = msg_id[..digits].parse().ok()?;
+    Some((src.to_string(), msg_id))
+}
+
+fn raw_field<'a>(line: &'a str, field: &str) -> Option<&'a str> {
+    let key = format!("\"{}\"", field);
+    let after_key = &line[line.find(&key)? + key.len()..];
+    let after_colon = after_key.trim_start().strip_prefix(':')?;
+    Some(after_colon.trim_start())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovers_the_sender_from_a_line_that_is_not_json() {
+        assert_eq!(recover_sender(r#"{"src": "c1", "body": {"msg_id": 12, "type": "#), Some(("c1".to_string(), 12)));
+        assert_eq!(recover_sender(r#"{"body":{"msg_id":-3},"src":"n2","#), Some(("n2".to_string(), -3)));
+        assert_eq!(recover_sender(r#"{"body": {"msg_id": 12}}"#), None);
+        assert_eq!(recover_sender(r#"{"src": 4, "body": {"msg_id": 12}}"#), None);
+        assert_eq!(recover_sender(r#"{"src": "c1", "body": {"msg_id": "x"}}"#), None);
+        assert_eq!(recover_sender("garbage"), None);
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use json::{object, JsonValue};
+
+const BUCKETS: usize = 40;
+
+/// Latencies in power-of-two microsecond buckets; percentiles report the bucket's upper bound.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    sum_us: u64,
+    min_us: u64,
+    max_us: u64,
+}
+
+impl Histogram {
+    pub fn init() -> Histogram {
+        Histogram {
+            buckets: [0; BUCKETS],
+            count: 0,
+            sum_us: 0,
+            min_us: u64::MAX,
+            max_us: 0,
+        }
+    }
+
+    pub fn observe(&mut self, latency: Duration) {
+        let micros = latency.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.sum_us = self.sum_us.saturating_add(micros);
+        self.min_us = self.min_us.min(micros);
+        self.max_us = self.max_us.max(micros);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn percentile(&self, p: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((self.count as f64) * p).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let upper = if bucket == 0 { 0 } else { 1u64 << bucket };
+                return upper.min(self.max_us);
+            }
+        }
+        self.max_us
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        object! {
+            count: self.count,
+            mean_us: self.sum_us.checked_div(self.count).unwrap_or_default(),
+            min_us: if self.count == 0 { 0 } else { self.min_us },
+            max_us: self.max_us,
+            p50_us: self.percentile(0.5),
+            p90_us: self.percentile(0.9),
+            p99_us: self.percentile(0.99),
+        }
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::init()
+    }
+}
+
+pub struct Metrics {
+    counters: Mutex<BTreeMap<String, u64>>,
+    gauges: Mutex<BTreeMap<String, i64>>,
+    histograms: Mutex<BTreeMap<String, Histogram>>,
+}
+
+impl Metrics {
+    pub fn init() -> Metrics {
+        Metrics {
+            counters: Mutex::new(BTreeMap::new()),
+            gauges: Mutex::new(BTreeMap::new()),
+            histograms: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn increment(&self, name: &str, label: &str) {
+        self.add(name, label, 1);
+    }
+
+    pub fn add(&self, name: &str, label: &str, by: u64) {
+        *self.counters.lock().unwrap().entry(key(name, label)).or_insert(0) += by;
+    }
+
+    pub fn set_gauge(&self, name: &str, label: &str, value: i64) {
+        self.gauges.lock().unwrap().insert(key(name, label), value);
+    }
+
+    pub fn observe(&self, name: &str, label: &str, latency: Duration) {
+        self.histograms
+            .lock()
+            .unwrap()
+            .entry(key(name, label))
+            .or_default()
+            .observe(latency);
+    }
+
+    pub fn counter(&self, name: &str, label: &str) -> u64 {
+        self.counters.lock().unwrap().get(&key(name, label)).copied().unwrap_or_default()
+    }
+
+    pub fn gauge(&self, name: &str, label: &str) -> Option<i64> {
+        self.gauges.lock().unwrap().get(&key(name, label)).copied()
+    }
+
+    pub fn histogram(&self, name: &str, label: &str) -> Option<Histogram> {
+        self.histograms.lock().unwrap().get(&key(name, label)).cloned()
+    }
+
+    pub fn snapshot(&self) -> JsonValue {
+        let mut counters = JsonValue::new_object();
+        for (name, value) in self.counters.lock().unwrap().iter() {
+            counters[name.as_str()] = JsonValue::from(*value);
+        }
+        let mut gauges = JsonValue::new_object();
+        for (name, value) in self.gauges.lock().unwrap().iter() {
+            gauges[name.as_str()] = JsonValue::from(*value);
+        }
+        let mut histograms = JsonValue::new_object();
+        for (name, histogram) in self.histograms.lock().unwrap().iter() {
+            histograms[name.as_str()] = histogram.to_json();
+        }
+        object! {counters: counters, gauges: gauges, histograms: histograms}
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::init()
+    }
+}
+
+fn key(name: &str, label: &str) -> String {
+    if label.is_empty() {
+        return name.to_string();
+    }
+    format!("{}.{}", name, label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn micros(samples: impl IntoIterator<Item = u64>) -> Histogram {
+        let mut histogram = Histogram::init();
+        samples.into_iter().for_each(|us| histogram.observe(Duration::from_micros(us)));
+        histogram
+    }
+
+    #[test]
+    fn percentiles_report_the_upper_bound_of_their_bucket() {
+        let histogram = micros(1..=100);
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.percentile(0.01), 2);
+        assert_eq!(histogram.percentile(0.5), 64);
+        assert_eq!(histogram.percentile(0.9), 100);
+        assert_eq!(histogram.percentile(1.0), 100);
+        assert_eq!(histogram.to_json(), object! {count: 100, mean_us: 50, min_us: 1, max_us: 100, p50_us: 64, p90_us: 100, p99_us: 100});
+    }
+
+    #[test]
+    fn empty_and_single_sample_histograms() {
+        assert_eq!(Histogram::init().percentile(0.5), 0);
+        assert_eq!(Histogram::init().to_json(), object! {count: 0, mean_us: 0, min_us: 0, max_us: 0, p50_us: 0, p90_us: 0, p99_us: 0});
+
+        let single = micros([700]);
+        assert_eq!(single.percentile(0.0), 700);
+        assert_eq!(single.percentile(0.99), 700);
+        assert_eq!(micros([0]).percentile(0.5), 0);
+    }
+
+    #[test]
+    fn snapshots_group_metrics_by_kind_and_label() {
+        let metrics = Metrics::init();
+        metrics.increment("messages.handled", "read");
+        metrics.add("messages.handled", "read", 2);
+        metrics.set_gauge("rpc.outstanding", "", 4);
+        metrics.observe("rpc.latency", "n2", Duration::from_micros(3));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["counters"], object! {"messages.handled.read": 3});
+        assert_eq!(snapshot["gauges"], object! {"rpc.outstanding": 4});
+        assert_eq!(snapshot["histograms"]["rpc.latency.n2"]["count"], 1);
+        assert_eq!(metrics.counter("messages.handled", "write"), 0);
+        assert_eq!(metrics.gauge("rpc.outstanding", ""), Some(4));
+    }
+}
@@ -0,0 +1,258 @@
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+use json::JsonValue;
+use crate::dedup::Lookup;
+use crate::error::{crash, MaelstromError};
+use crate::logging::Level;
+use crate::message_handler::reply_with_error;
+use crate::message_utils::get_sender;
+use crate::node_state::NodeState;
+use crate::router::{Middleware, Next};
+
+pub struct RequestLogging {}
+
+impl<T> Middleware<T> for RequestLogging
+    where T: Deref<Target=NodeState>
+{
+    fn handle(&self, message: &JsonValue, curr_state: &T, next: Next<'_, T>) {
+        let mut event = curr_state.log(Level::Debug, "router");
+        if let Some(msg_id) = message["body"]["msg_id"].as_i32() {
+            event = event.msg_id(msg_id);
+        }
+        event.log(&format!("Handling {} from {}", message["body"]["type"], message["src"]));
+        next.run(message, curr_state);
+    }
+}
+
+pub struct Timing {
+    slow: Duration,
+}
+
+impl Timing {
+    pub fn init(slow: Duration) -> Timing {
+        Timing { slow }
+    }
+}
+
+impl<T> Middleware<T> for Timing
+    where T: Deref<Target=NodeState>
+{
+    fn handle(&self, message: &JsonValue, curr_state: &T, next: Next<'_, T>) {
+        let started = Instant::now();
+        next.run(message, curr_state);
+        let elapsed = started.elapsed();
+        let level = if elapsed >= self.slow { Level::Warn } else { Level::Trace };
+        curr_state.log(level, "router").log(&format!("Handled {} in {:?}", message["body"]["type"], elapsed));
+    }
+}
+
+pub struct CatchPanics {}
+
+impl<T> Middleware<T> for CatchPanics
+    where T: Deref<Target=NodeState>
+{
+    fn handle(&self, message: &JsonValue, curr_state: &T, next: Next<'_, T>) {
+        if panic::catch_unwind(AssertUnwindSafe(|| next.run(message, curr_state))).is_ok() {
+            return;
+        }
+        let message_type = message["body"]["type"].as_str().unwrap_or_default();
+        curr_state.log(Level::Error, "router").log(&format!("Handler for {} panicked", message_type));
+        curr_state.metrics().increment("messages.panicked", message_type);
+        if let Some((src, in_reply_to)) = get_sender(message) {
+            let error = MaelstromError { in_reply_to, error: crash(format!("handler for {} panicked", message_type)) };
+            reply_with_error(&error, JsonValue::from(src), curr_state);
+        }
+    }
+}
+
+/// Answers a retransmitted request with the reply cached for its (src, msg_id) instead
+/// of running the handler again, re-sent under a new msg_id. Duplicates of a request
+/// still being handled are dropped.
+pub struct Dedup {
+    window: Duration,
+}
+
+impl Dedup {
+    pub fn init(window: Duration) -> Dedup {
+        Dedup { window }
+    }
+}
+
+impl<T> Middleware<T> for Dedup
+    where T: Deref<Target=NodeState>
+{
+    fn handle(&self, message: &JsonValue, curr_state: &T, next: Next<'_, T>) {
+        let (src, msg_id) = match get_sender(message) {
+            Some(sender) => sender,
+            None => return next.run(message, curr_state),
+        };
+        let message_type = message["body"]["type"].as_str().unwrap_or_default();
+        let reply_cache = curr_state.reply_cache(self.window);
+        match reply_cache.begin(&src, msg_id) {
+            Lookup::New => {
+                next.run(message, curr_state);
+                reply_cache.abandon(&src, msg_id);
+            }
+            Lookup::InProgress => {
+                curr_state.log(Level::Debug, "dedup").msg_id(msg_id).log(&format!("Dropping duplicate {} from {} still in progress", message_type, src));
+                curr_state.metrics().increment("messages.duplicates", message_type);
+            }
+            Lookup::Replied(mut reply) => {
+                curr_state.log(Level::Debug, "dedup").msg_id(msg_id).log(&format!("Replaying reply to duplicate {} from {}", message_type, src));
+                curr_state.metrics().increment("messages.duplicates", message_type);
+                reply["body"]["msg_id"] = JsonValue::from(curr_state.next_msg_id());
+                curr_state.send_message(reply);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, sync_channel, Receiver, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use json::object;
+    use crate::error::ErrorCode;
+    use crate::message_handler::MessageHandler;
+    use crate::router::{Handlers, Router};
+    use super::*;
+
+    struct TestState(NodeState);
+
+    impl Deref for TestState {
+        type Target = NodeState;
+
+        fn deref(&self) -> &NodeState {
+            &self.0
+        }
+    }
+
+    struct Echo {}
+
+    impl MessageHandler<TestState> for Echo {
+        fn handle_message(&self, message: &JsonValue, curr_state: &TestState) {
+            let mut body = message["body"].clone();
+            body["type"] = JsonValue::from("echo_ok");
+            body["in_reply_to"] = message["body"]["msg_id"].clone();
+            curr_state.send_message(object! {src: curr_state.node_id(), dest: message["src"].clone(), body: body});
+        }
+    }
+
+    /// Echoes once `gate` is released, after saying it started.
+    struct Gated {
+        started: Mutex<Sender<()>>,
+        gate: Mutex<Receiver<()>>,
+    }
+
+    impl MessageHandler<TestState> for Gated {
+        fn handle_message(&self, message: &JsonValue, curr_state: &TestState) {
+            self.started.lock().unwrap().send(()).unwrap();
+            self.gate.lock().unwrap().recv().unwrap();
+            Echo {}.handle_message(message, curr_state);
+        }
+    }
+
+    struct Silent {
+        calls: Arc<Mutex<usize>>,
+    }
+
+    impl MessageHandler<TestState> for Silent {
+        fn handle_message(&self, _message: &JsonValue, _curr_state: &TestState) {
+            *self.calls.lock().unwrap() += 1;
+        }
+    }
+
+    struct Panics {}
+
+    impl MessageHandler<TestState> for Panics {
+        fn handle_message(&self, _message: &JsonValue, _curr_state: &TestState) {
+            panic!("handler failed");
+        }
+    }
+
+    fn node() -> (TestState, Receiver<String>) {
+        let (sender, replies) = sync_channel(16);
+        let state = TestState(NodeState::init(sender));
+        state.set_node_id("n1".to_string());
+        (state, replies)
+    }
+
+    fn handle(handlers: &Handlers<TestState>, message: JsonValue) -> Vec<JsonValue> {
+        let (state, replies) = node();
+        handlers[message["body"]["type"].as_str().unwrap()].handle_message(&message, &state);
+        replies.try_iter().map(|line| json::parse(&line).unwrap()).collect()
+    }
+
+    #[test]
+    fn catch_panics_replies_with_a_crash_error() {
+        let handlers = Router::init().with(CatchPanics {}).route("read", Panics {}).build();
+        let replies = handle(&handlers, object! {src: "c1", dest: "n1", body: {type: "read", msg_id: 4}});
+
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0]["dest"], "c1");
+        assert_eq!(replies[0]["body"]["in_reply_to"], 4);
+        assert_eq!(ErrorCode::from_code(replies[0]["body"]["code"].as_i32().unwrap()), ErrorCode::Crash);
+    }
+
+    #[test]
+    fn logging_and_timing_pass_replies_through_unchanged() {
+        let message = object! {src: "c1", dest: "n1", body: {type: "echo", echo: "hi", msg_id: 2}};
+        let bare = Router::init().route("echo", Echo {}).build();
+        let wrapped = Router::init()
+            .with(RequestLogging {})
+            .with(Timing::init(Duration::ZERO))
+            .route("echo", Echo {})
+            .build();
+
+        let replies = handle(&wrapped, message.clone());
+        assert_eq!(replies, handle(&bare, message));
+        assert_eq!(replies[0]["body"]["echo"], "hi");
+    }
+
+    #[test]
+    fn dedup_drops_duplicates_in_flight_and_replays_the_reply_under_a_new_msg_id() {
+        let (started, running) = channel();
+        let (release, gate) = channel();
+        let handler = Gated { started: Mutex::new(started), gate: Mutex::new(gate) };
+        let handlers = Router::init().with(Dedup::init(Duration::from_secs(5))).route("echo", handler).build();
+        let route = &handlers["echo"];
+        let (state, replies) = node();
+        let message = object! {src: "c1", dest: "n1", body: {type: "echo", echo: "hi", msg_id: 2}};
+
+        thread::scope(|scope| {
+            scope.spawn(|| route.handle_message(&message, &state));
+            running.recv().unwrap();
+            route.handle_message(&message, &state);
+            assert!(replies.try_recv().is_err());
+            release.send(()).unwrap();
+        });
+        let original = json::parse(&replies.recv().unwrap()).unwrap();
+
+        route.handle_message(&message, &state);
+        let replayed = json::parse(&replies.try_recv().unwrap()).unwrap();
+        assert!(running.try_recv().is_err());
+        assert_eq!(replayed["body"]["in_reply_to"], 2);
+        assert_eq!(replayed["body"]["echo"], "hi");
+        assert_ne!(replayed["body"]["msg_id"], original["body"]["msg_id"]);
+        assert_eq!(state.metrics().counter("messages.duplicates", "echo"), 2);
+    }
+
+    #[test]
+    fn dedup_handles_a_retransmit_again_when_the_first_sent_no_reply() {
+        let calls = Arc::new(Mutex::new(0));
+        let handlers = Router::init()
+            .with(Dedup::init(Duration::from_secs(5)))
+            .route("gossip", Silent { calls: calls.clone() })
+            .build();
+        let (state, _replies) = node();
+        let message = object! {src: "n2", dest: "n1", body: {type: "gossip", msg_id: 5}};
+        handlers["gossip"].handle_message(&message, &state);
+        handlers["gossip"].handle_message(&message, &state);
+
+        assert_eq!(*calls.lock().unwrap(), 2);
+        assert_eq!(state.metrics().counter("messages.duplicates", "gossip"), 0);
+        assert!(state.reply_cache(Duration::from_secs(5)).is_empty());
+    }
+}
@@ -1,17 +1,40 @@
 use std::sync::mpsc::SyncSender;
-use std::sync::{Mutex, RwLock};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
 use std::cell::RefCell;
-use std::collections::HashMap;
-use json::JsonValue;
+use json::{stringify, JsonValue};
+use crate::batching::{Batcher, Outbox};
+use crate::callbacks::Callbacks;
+use crate::clock::{Clock, SystemClock};
+use crate::dedup::ReplyCache;
+use crate::hlc::{HybridClock, Timestamp, HLC_FIELD};
+use crate::lifecycle::{Lifecycle, Phase};
+use crate::logging::{event, Event, Level};
 use crate::message_utils::get_in_response_to;
-use crate::stdio::write_log;
+use crate::metrics::Metrics;
+use crate::random::Random;
+use crate::scheduler::Scheduler;
+use crate::topology::{Membership, Overlay, Topology};
 
 pub struct NodeState {
     node_id: RwLock<Option<String>>,
     other_ids: RwLock<Vec<String>>,
     msg_id: Mutex<RefCell<i32>>,
-    callbacks: RwLock<HashMap<i32, SyncSender<JsonValue>>>,
-    response_channel: SyncSender<String>,
+    callbacks: Arc<Callbacks>,
+    response_channel: Outbox,
+    writer: Mutex<Option<JoinHandle<()>>>,
+    lifecycle: Arc<Lifecycle>,
+    rejected_inputs: AtomicUsize,
+    metrics: Arc<Metrics>,
+    reply_cache: OnceLock<Arc<ReplyCache>>,
+    scheduler: OnceLock<Arc<Scheduler>>,
+    clock: RwLock<Arc<dyn Clock>>,
+    random: RwLock<Arc<Random>>,
+    hlc: OnceLock<HybridClock>,
+    batcher: OnceLock<Arc<Batcher>>,
+    membership: RwLock<Arc<Membership>>,
 }
 
 impl NodeState {
@@ -21,13 +44,122 @@ impl NodeState {
             node_id: RwLock::new(None),
             other_ids: RwLock::new(Vec::new()),
             msg_id: Mutex::new(RefCell::new(0)),
-            callbacks: RwLock::new(HashMap::new()),
-            response_channel
+            callbacks: Callbacks::init(),
+            response_channel: Arc::new(Mutex::new(Some(response_channel))),
+            writer: Mutex::new(None),
+            lifecycle: Arc::new(Lifecycle::default()),
+            rejected_inputs: AtomicUsize::new(0),
+            metrics: Arc::new(Metrics::init()),
+            reply_cache: OnceLock::new(),
+            scheduler: OnceLock::new(),
+            clock: RwLock::new(Arc::new(SystemClock {})),
+            random: RwLock::new(Arc::new(Random::from_entropy())),
+            hlc: OnceLock::new(),
+            batcher: OnceLock::new(),
+            membership: RwLock::new(Arc::new(Membership::default())),
         }
     }
 
-    pub fn get_channel(&self) -> SyncSender<String> {
-        self.response_channel.clone()
+    pub fn set_writer(&self, writer: JoinHandle<()>) {
+        self.writer.lock().unwrap().replace(writer);
+    }
+
+    pub fn send_line(&self, line: String) {
+        if let Some(channel) = self.response_channel.lock().unwrap().as_ref() {
+            let _ = channel.send(line);
+        }
+    }
+
+    pub fn send_message(&self, mut message: JsonValue) {
+        if let Some(hlc) = self.hlc.get() {
+            message["body"][HLC_FIELD] = hlc.tick().to_json();
+        }
+        let line = stringify(message.clone());
+        if let Some(reply_cache) = self.reply_cache.get() {
+            if let (Some(dest), Some(in_reply_to)) = (message["dest"].as_str(), message["body"]["in_reply_to"].as_i32()) {
+                reply_cache.record(dest, in_reply_to, &message);
+            }
+        }
+        if let Some(batcher) = self.batcher.get() {
+            if message["dest"].as_str().is_some_and(|dest| self.other_ids.read().unwrap().iter().any(|id| id == dest)) {
+                batcher.push(message);
+                return;
+            }
+        }
+        self.send_line(line);
+    }
+
+    /// Opts in to batching messages to other nodes; replies to clients are never delayed.
+    pub fn enable_batching(&self, window: Duration, max_batch: usize) {
+        self.batcher.get_or_init(|| {
+            Batcher::init(window, max_batch, self.response_channel.clone(), self.scheduler(), self.metrics())
+        });
+    }
+
+    pub fn reply_cache(&self, window: Duration) -> Arc<ReplyCache> {
+        self.reply_cache.get_or_init(|| Arc::new(ReplyCache::init(window, self.clock()))).clone()
+    }
+
+    /// Replaces the system clock for timers, RPC deadlines and backoffs. Call it before
+    /// the scheduler or reply cache are first used, since they keep the clock they start with.
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        self.callbacks.set_clock(clock.clone());
+        *self.clock.write().unwrap() = clock;
+    }
+
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.read().unwrap().clone()
+    }
+
+    /// Runs `task` on a thread of its own, started through the node's clock so a
+    /// simulation can step it like the node's handlers.
+    pub fn spawn<F>(&self, task: F)
+        where F: FnOnce() + Send + 'static
+    {
+        self.clock().spawn(Box::new(task));
+    }
+
+    /// Starts a hybrid logical clock on the node's clock. From then on every outgoing body
+    /// carries an `hlc` timestamp and timestamps on incoming messages are merged into it.
+    pub fn enable_hlc(&self) {
+        self.hlc.get_or_init(|| HybridClock::init(self.clock()));
+    }
+
+    pub fn hlc(&self) -> Option<&HybridClock> {
+        self.hlc.get()
+    }
+
+    pub fn hlc_now(&self) -> Option<Timestamp> {
+        self.hlc.get().map(HybridClock::current)
+    }
+
+    pub fn merge_hlc(&self, message: &JsonValue) {
+        if let (Some(hlc), Some(remote)) = (self.hlc.get(), Timestamp::from_json(&message["body"][HLC_FIELD])) {
+            hlc.update(remote);
+        }
+    }
+
+    pub fn set_seed(&self, seed: u64) {
+        *self.random.write().unwrap() = Arc::new(Random::seeded(seed));
+    }
+
+    pub fn random(&self) -> Arc<Random> {
+        self.random.read().unwrap().clone()
+    }
+
+    /// The node's timer scheduler. Under a real-time clock it gets a background thread the
+    /// first time it is used; otherwise `run_due` has to be called as the clock advances.
+    pub fn scheduler(&self) -> Arc<Scheduler> {
+        self.scheduler
+            .get_or_init(|| {
+                let clock = self.clock();
+                let scheduler = Arc::new(Scheduler::init(clock.clone()));
+                if clock.is_real_time() {
+                    scheduler.spawn();
+                }
+                scheduler
+            })
+            .clone()
     }
 
     pub fn next_msg_id(&self) -> i32 {
@@ -39,6 +171,13 @@ impl NodeState {
         self.node_id.read().unwrap().as_ref().unwrap().clone()
     }
 
+    pub fn log(&self, level: Level, target: &'static str) -> Event {
+        match self.node_id.read().unwrap().as_ref() {
+            Some(node_id) => event(level, target).node(node_id),
+            None => event(level, target),
+        }
+    }
+
     pub fn is_initialized(&self) -> bool {
         self.node_id.read().unwrap().is_some()
     }
@@ -49,35 +188,216 @@ impl NodeState {
     }
 
     pub fn set_other_node_ids(&self, other_ids: Vec<&str>) {
-        let mut ids = self.other_ids.write().unwrap();
         let my_id: String = self.node_id.read().unwrap().as_ref().unwrap().to_string();
-        other_ids.iter().for_each(|id_ref| {
-            let id: String = id_ref.to_string();
-            if my_id != id {
-                ids.push(id);
-            }
-        });
+        *self.other_ids.write().unwrap() = other_ids
+            .iter()
+            .filter(|id| **id != my_id)
+            .map(|id| id.to_string())
+            .collect();
+        self.update_membership(|membership| (membership.supplied().clone(), membership.overlay()));
     }
 
     pub fn other_nodes(&self) -> Vec<String> {
         self.other_ids.read().unwrap().clone()
     }
 
-    pub fn check_for_callback(&self, message: &JsonValue) -> Option<SyncSender<JsonValue>> {
+    /// Replaces the topology, e.g. from a `topology` message, in one step.
+    pub fn set_topology(&self, topology: Topology) {
+        self.update_membership(|membership| (topology, membership.overlay()));
+    }
+
+    pub fn set_overlay(&self, overlay: Overlay) {
+        self.update_membership(|membership| (membership.supplied().clone(), overlay));
+    }
+
+    /// Rebuilds the membership from the current one under a single write lock, so
+    /// concurrent updates are applied one after the other rather than lost. The node ids
+    /// are read first so no other lock is taken while the membership one is held.
+    fn update_membership<F>(&self, update: F)
+        where F: FnOnce(&Membership) -> (Topology, Overlay)
+    {
+        let mut nodes = self.other_nodes();
+        nodes.extend(self.node_id.read().unwrap().clone());
+        let mut membership = self.membership.write().unwrap();
+        let (topology, overlay) = update(&membership);
+        *membership = Arc::new(Membership::init(topology, overlay, &nodes));
+    }
+
+    pub fn membership(&self) -> Arc<Membership> {
+        self.membership.read().unwrap().clone()
+    }
+
+    /// This node's neighbors in the chosen overlay.
+    pub fn neighbors(&self) -> Vec<String> {
+        match self.node_id.read().unwrap().as_ref() {
+            Some(node_id) => self.membership().topology().neighbors(node_id),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn complete_callback(&self, message: &JsonValue) -> bool {
         let in_response_to = get_in_response_to(message);
         match in_response_to {
             Some(id) => {
-                let callback = self.callbacks.write().unwrap().remove(&id);
-                if callback.is_none() {
-                    write_log(format!("Ignoring reply to {} with no callback", id).as_str());
+                let completed = self.callbacks.complete(id, message.clone());
+                if !completed {
+                    self.log(Level::Debug, "callbacks").msg_id(id).log("Ignoring reply with no callback");
                 }
-                callback
+                completed
             },
-            None => None,
+            None => false,
+        }
+    }
+
+    pub fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.lifecycle.phase() == Phase::Running
+    }
+
+    /// Blocks until init has been handled, for at most `timeout` on the node's clock.
+    /// Returns whether the node is running.
+    pub fn wait_until_running(&self, timeout: Duration) -> bool {
+        let clock = self.clock();
+        if clock.is_real_time() {
+            return self.lifecycle.wait_until_running(timeout);
         }
+        let lifecycle = Arc::clone(&self.lifecycle);
+        clock.park(clock.now() + timeout, Arc::new(move || lifecycle.phase() != Phase::Uninitialized));
+        self.is_running()
     }
 
-    pub fn add_callback(&self, message_id: i32, channel: SyncSender<JsonValue>) {
-        self.callbacks.write().unwrap().insert(message_id, channel);
+    pub fn begin_shutdown(&self) {
+        let dropped = self.lifecycle.begin_shutdown();
+        if dropped > 0 {
+            self.log(Level::Warn, "shutdown").log(&format!("Dropped {} messages held for init", dropped));
+        }
+        if let Some(scheduler) = self.scheduler.get() {
+            scheduler.shutdown();
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.lifecycle.phase() == Phase::ShuttingDown
+    }
+
+    /// Closes the reply channel and waits for the writer to flush what is already queued.
+    /// Anything sent afterwards is dropped.
+    pub fn close_channel(&self, deadline: Instant) -> bool {
+        if let Some(batcher) = self.batcher.get() {
+            batcher.flush_all();
+        }
+        self.response_channel.lock().unwrap().take();
+        let writer = match self.writer.lock().unwrap().take() {
+            Some(writer) => writer,
+            None => return true,
+        };
+        while !writer.is_finished() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        let _ = writer.join();
+        true
+    }
+
+    pub fn record_rejected_input(&self) -> usize {
+        self.rejected_inputs.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    pub fn rejected_inputs(&self) -> usize {
+        self.rejected_inputs.load(Ordering::SeqCst)
+    }
+
+    pub fn callbacks(&self) -> Arc<Callbacks> {
+        self.callbacks.clone()
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    pub fn stats(&self) -> JsonValue {
+        self.metrics.set_gauge("rpc.outstanding", "", self.callbacks.outstanding() as i64);
+        self.metrics.set_gauge("inputs.rejected", "", self.rejected_inputs() as i64);
+        self.metrics.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+    use std::thread;
+    use json::object;
+    use crate::clock::ManualClock;
+    use super::*;
+
+    #[test]
+    fn concurrent_membership_updates_are_not_lost() {
+        let (sender, _replies) = sync_channel(1);
+        let state = Arc::new(NodeState::init(sender));
+        state.set_node_id("n1".to_string());
+        state.set_other_node_ids(vec!["n1", "n2", "n3"]);
+        let supplied = Topology::from_graph([("n1".to_string(), vec!["n2".to_string()])]);
+
+        let overlay = {
+            let state = state.clone();
+            thread::spawn(move || (0..200).for_each(|_| state.set_overlay(Overlay::Ring)))
+        };
+        for _ in 0..200 {
+            state.set_topology(supplied.clone());
+        }
+        overlay.join().unwrap();
+
+        let membership = state.membership();
+        assert_eq!(membership.overlay(), Overlay::Ring);
+        assert_eq!(membership.supplied(), &supplied);
+        assert_eq!(state.neighbors(), vec!["n2".to_string(), "n3".to_string()]);
+    }
+
+    #[test]
+    fn waiting_for_init_times_out_on_the_node_clock() {
+        let (sender, _replies) = sync_channel(1);
+        let state = Arc::new(NodeState::init(sender));
+        let clock = Arc::new(ManualClock::init());
+        state.set_clock(clock.clone());
+        let wait = |state: &Arc<NodeState>| {
+            let state = state.clone();
+            thread::spawn(move || state.wait_until_running(Duration::from_millis(100)))
+        };
+
+        let waiter = wait(&state);
+        while clock.parked() == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        clock.advance(Duration::from_millis(100));
+        assert!(!waiter.join().unwrap());
+
+        let waiter = wait(&state);
+        while clock.parked() == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(state.lifecycle().start());
+        assert!(waiter.join().unwrap());
+    }
+
+    #[test]
+    fn replies_to_clients_are_never_batched() {
+        let (sender, lines) = sync_channel(16);
+        let state = NodeState::init(sender);
+        state.set_clock(Arc::new(ManualClock::init()));
+        state.set_node_id("n1".to_string());
+        state.set_other_node_ids(vec!["n1", "n2"]);
+        state.enable_batching(Duration::from_millis(5), 64);
+
+        state.send_message(object! {src: "n1", dest: "n2", body: {type: "gossip"}});
+        state.send_message(object! {src: "n1", dest: "c1", body: {type: "read_ok", in_reply_to: 1}});
+        let sent: Vec<JsonValue> = lines.try_iter().map(|line| json::parse(&line).unwrap()).collect();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["dest"], "c1");
+        assert_eq!(sent[0]["body"]["type"], "read_ok");
     }
 }
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use json::JsonValue;
+use crate::mailbox::Mailbox;
+use crate::node_state::NodeState;
+use crate::rpc::{call_with, cancel_rpc};
+
+pub struct Reply {
+    pub from: String,
+    pub message: JsonValue,
+}
+
+pub struct Quorum {
+    pub replies: Vec<Reply>,
+    pub failed: Vec<String>,
+    pub reached: bool,
+}
+
+pub fn majority(cluster_size: usize) -> usize {
+    cluster_size / 2 + 1
+}
+
+/// Sends `request_body` to every peer and feeds replies to `on_reply` as they arrive until it
+/// returns true, every peer has answered or timed out, or `timeout` passes. RPCs still
+/// outstanding when it returns are cancelled.
+pub fn gather<F>(state: &NodeState, request_body: &JsonValue, peers: &[String], timeout: Duration, mut on_reply: F) -> Quorum
+    where F: FnMut(&Reply) -> bool
+{
+    let clock = state.clock();
+    let deadline = clock.now() + timeout;
+    let replies = Mailbox::init();
+    let mut outstanding: HashMap<String, i32> = HashMap::new();
+    for peer in peers {
+        let replies = replies.clone();
+        let from = peer.clone();
+        let msg_id = call_with(state, &mut request_body.clone(), peer, timeout, move |response| {
+            replies.push((from, response));
+        });
+        outstanding.insert(peer.clone(), msg_id);
+    }
+
+    let mut quorum = Quorum { replies: Vec::new(), failed: Vec::new(), reached: false };
+    while !outstanding.is_empty() {
+        let (from, response) = match replies.take_until(&*clock, deadline) {
+            Some(received) => received,
+            None => break,
+        };
+        outstanding.remove(&from);
+        match response {
+            Some(message) => {
+                let reply = Reply { from, message };
+                let reached = on_reply(&reply);
+                quorum.replies.push(reply);
+                if reached {
+                    quorum.reached = true;
+                    break;
+                }
+            }
+            None => quorum.failed.push(from),
+        }
+    }
+    for (peer, msg_id) in outstanding {
+        if cancel_rpc(state, msg_id) && !quorum.reached {
+            quorum.failed.push(peer);
+        }
+    }
+    quorum
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::mpsc::{sync_channel, Receiver};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use json::object;
+    use crate::clock::ManualClock;
+    use crate::rpc::DEFAULT_RPC_TIMEOUT;
+    use super::*;
+
+    fn node() -> (Arc<NodeState>, Arc<ManualClock>, Receiver<String>) {
+        let (sender, sent) = sync_channel(16);
+        let state = Arc::new(NodeState::init(sender));
+        let clock = Arc::new(ManualClock::init());
+        state.set_clock(clock.clone());
+        state.callbacks().run_inline(true);
+        state.set_node_id("n1".to_string());
+        (state, clock, sent)
+    }
+
+    /// Gathers votes from four peers until, with its own, the node has a majority of five.
+    fn gather_votes(state: &Arc<NodeState>) -> JoinHandle<Quorum> {
+        let state = state.clone();
+        thread::spawn(move || {
+            let peers: Vec<String> = ["n2", "n3", "n4", "n5"].iter().map(|p| p.to_string()).collect();
+            let mut votes = 1;
+            gather(&state, &object! {type: "vote"}, &peers, DEFAULT_RPC_TIMEOUT, |_| {
+                votes += 1;
+                votes >= majority(5)
+            })
+        })
+    }
+
+    /// The msg_id of the request sent to each peer.
+    fn requests(sent: &Receiver<String>, count: usize) -> HashMap<String, i32> {
+        (0..count)
+            .map(|_| json::parse(&sent.recv().unwrap()).unwrap())
+            .map(|request| (request["dest"].to_string(), request["body"]["msg_id"].as_i32().unwrap()))
+            .collect()
+    }
+
+    fn vote(state: &NodeState, from: &str, in_reply_to: i32) -> bool {
+        state.complete_callback(&object! {src: from, dest: "n1", body: {type: "vote_ok", in_reply_to: in_reply_to}})
+    }
+
+    fn sources(replies: &[Reply]) -> Vec<&str> {
+        replies.iter().map(|reply| reply.from.as_str()).collect()
+    }
+
+    #[test]
+    fn a_majority_counts_this_node() {
+        let sizes: Vec<usize> = (1..=6).map(majority).collect();
+        assert_eq!(sizes, vec![1, 2, 2, 3, 3, 4]);
+    }
+
+    #[test]
+    fn stops_once_the_quorum_is_reached() {
+        let (state, _clock, sent) = node();
+        let gathering = gather_votes(&state);
+        let requests = requests(&sent, 4);
+        assert!(vote(&state, "n3", requests["n3"]));
+        assert!(vote(&state, "n5", requests["n5"]));
+
+        let quorum = gathering.join().unwrap();
+        assert!(quorum.reached);
+        assert_eq!(sources(&quorum.replies), vec!["n3", "n5"]);
+        assert!(quorum.failed.is_empty());
+        assert_eq!(state.callbacks().outstanding(), 0);
+    }
+
+    #[test]
+    fn a_peer_that_replies_twice_is_counted_once() {
+        let (state, _clock, sent) = node();
+        let gathering = gather_votes(&state);
+        let requests = requests(&sent, 4);
+        assert!(vote(&state, "n2", requests["n2"]));
+        assert!(!vote(&state, "n2", requests["n2"]));
+        thread::sleep(Duration::from_millis(20));
+        assert!(!gathering.is_finished());
+
+        assert!(vote(&state, "n4", requests["n4"]));
+        let quorum = gathering.join().unwrap();
+        assert!(quorum.reached);
+        assert_eq!(sources(&quorum.replies), vec!["n2", "n4"]);
+    }
+
+    #[test]
+    fn gives_up_at_the_deadline_and_fails_the_silent_peers() {
+        let (state, clock, sent) = node();
+        let gathering = gather_votes(&state);
+        let requests = requests(&sent, 4);
+        assert!(vote(&state, "n2", requests["n2"]));
+        while clock.parked() == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        clock.advance(DEFAULT_RPC_TIMEOUT);
+
+        let mut quorum = gathering.join().unwrap();
+        assert!(!quorum.reached);
+        assert_eq!(sources(&quorum.replies), vec!["n2"]);
+        quorum.failed.sort();
+        assert_eq!(quorum.failed, vec!["n3", "n4", "n5"]);
+        assert_eq!(state.callbacks().outstanding(), 0);
+    }
+}
@@ -0,0 +1,39 @@
+use std::sync::Mutex;
+use rand::distributions::uniform::{SampleRange, SampleUniform};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+/// A shareable random source. Nodes built from the same seed make the same choices.
+pub struct Random {
+    rng: Mutex<StdRng>,
+}
+
+impl Random {
+    pub fn from_entropy() -> Random {
+        Random {
+            rng: Mutex::new(StdRng::from_entropy()),
+        }
+    }
+
+    pub fn seeded(seed: u64) -> Random {
+        Random {
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    pub fn gen_range<T, R>(&self, range: R) -> T
+        where T: SampleUniform, R: SampleRange<T>
+    {
+        self.rng.lock().unwrap().gen_range(range)
+    }
+
+    pub fn next_u64(&self) -> u64 {
+        self.rng.lock().unwrap().next_u64()
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Random::from_entropy()
+    }
+}
@@ -1,50 +1,353 @@
-use json::JsonValue;
-use std::{io, thread};
-use std::io::{Write, BufRead, stderr};
+use std::io::ErrorKind;
+use std::process;
+use std::time::{Duration, Instant};
+use crate::batching::unpack;
 use crate::node_state::NodeState;
-use std::collections::HashMap;
-use crate::message_handler::MessageHandler;
+use crate::error::{malformed_request, not_supported, temporarily_unavailable, DefiniteError, MaelstromError};
+use crate::lifecycle::Admission;
+use crate::message_handler::{reject_message, reply_with_error};
+use crate::router::Handlers;
 use std::ops::Deref;
-use crate::message_utils::get_message_type;
-use crate::stdio::write_log;
+use json::JsonValue;
+use crate::message_utils::{get_message_type, get_sender, recover_sender};
+use crate::logging::Level;
+use crate::recording::{session, Session};
+use crate::transport::Transport;
+use crate::worker_pool::{Executor, WorkerPool};
+
+pub const DEFAULT_SHUTDOWN_DEADLINE: Duration = Duration::from_secs(2);
+
+pub fn read_respond_loop<T>(state: &'static T, handlers: &'static Handlers<T>, pool: WorkerPool)
+    where T: Deref<Target = NodeState> + Sync
+{
+    transport_loop(state, handlers, pool, session().transport());
+    if let Session::Replay(replay) = session() {
+        if !replay.report() {
+            process::exit(1);
+        }
+    }
+}
 
-pub fn read_respond_loop<T>(state: &'static T, handlers : &'static HashMap<String, Box<dyn MessageHandler<T>>>)
+pub fn transport_loop<T>(state: &'static T, handlers: &'static Handlers<T>, pool: WorkerPool, transport: &dyn Transport)
     where T: Deref<Target = NodeState> + Sync
 {
-    for result in io::stdin().lock().lines() {
+    while let Some(result) = transport.read_line() {
         match result {
             Ok(line) => {
-                io::stderr().write(format!("Received {}\n", line).as_ref());
-                let parsed_res = json::parse(&line);
-                match parsed_res {
-                    Ok(parsed) => {
-                        match state.check_for_callback(&parsed) {
-                            Some(sender) => {
-                                sender.send(parsed);
-                            }
-                            None => {
-                                let message_type: String = get_message_type(&parsed);
-                                if handlers.contains_key(&message_type) {
-                                    let handler = handlers.get(&message_type).unwrap();
-                                    thread::spawn(move || handler.handle_message(&parsed, state));
-                                } else {
-                                    write_log(&format!(
-                                        "Did not find handler for message: {}",
-                                        parsed
-                                    ));
-                                }
-                            }
-                        }
-                    },
-                    Err(_err) => {
-                        std::process::exit(1);
+                state.log(Level::Debug, "transport").log(&format!("Received {}", line));
+                match json::parse(&line) {
+                    Ok(parsed) => dispatch(parsed, state, handlers, &pool),
+                    Err(err) => {
+                        let text = format!("Could not parse message: {}", err);
+                        reject_input(state, recover_sender(&line), text);
                     }
                 }
             }
-            Err(_err) => {
-                std::process::exit(1);
+            Err(err) if err.kind() == ErrorKind::InvalidData => {
+                reject_input(state, None, format!("Could not read input line: {}", err));
+            }
+            Err(err) => {
+                state.log(Level::Warn, "transport").log(&format!("Stopped reading input: {}", err));
+                break;
+            }
+        }
+    }
+    shutdown(state, pool, Instant::now() + DEFAULT_SHUTDOWN_DEADLINE);
+}
+
+fn shutdown(state: &NodeState, pool: WorkerPool, deadline: Instant) {
+    state.log(Level::Info, "shutdown").log("Input closed, shutting down");
+    let abandoned = state.callbacks().close();
+    if abandoned > 0 {
+        state.log(Level::Info, "shutdown").log(&format!("Abandoned {} outstanding RPCs", abandoned));
+    }
+    // Queued jobs, including an `init` still releasing held messages, finish first.
+    if !pool.shutdown(deadline) {
+        state.log(Level::Warn, "shutdown").log("Handlers still running at the shutdown deadline");
+    }
+    state.begin_shutdown();
+    if !state.close_channel(deadline) {
+        state.log(Level::Warn, "shutdown").log("Replies still unwritten at the shutdown deadline");
+    }
+}
+
+/// Unpacks batches, completes RPC callbacks and holds or rejects messages until init, then
+/// hands each remaining message's handler to `executor`.
+pub fn dispatch<T>(parsed: JsonValue, state: &'static T, handlers: &'static Handlers<T>, executor: &dyn Executor)
+    where T: Deref<Target = NodeState> + Sync
+{
+    if let Some(messages) = unpack(&parsed) {
+        messages.into_iter().for_each(|message| dispatch(message, state, handlers, executor));
+        return;
+    }
+    state.merge_hlc(&parsed);
+    if state.complete_callback(&parsed) {
+        return;
+    }
+    let message_type = match get_message_type(&parsed) {
+        Some(message_type) => message_type,
+        None => {
+            reject_input(state, get_sender(&parsed), "Message has no type".to_string());
+            return;
+        }
+    };
+    state.metrics().increment("messages.received", &message_type);
+    let parsed = match state.lifecycle().admit(parsed) {
+        Admission::Run(parsed) => parsed,
+        Admission::Held => {
+            state.log(Level::Debug, "read_respond").log(&format!("Holding {} until init", message_type));
+            state.metrics().increment("messages.held", &message_type);
+            return;
+        }
+        Admission::Rejected(parsed) => {
+            state.log(Level::Warn, "read_respond").log(&format!("Rejecting {} received before init", message_type));
+            state.metrics().increment("messages.rejected", &message_type);
+            reject_before_init(state, &parsed);
+            return;
+        }
+    };
+    run(parsed, message_type, state, handlers, executor);
+}
+
+/// Hands the message's handler to `executor`; after `init` the messages held for it go
+/// the same way.
+fn run<T>(parsed: JsonValue, message_type: String, state: &'static T, handlers: &'static Handlers<T>, executor: &dyn Executor)
+    where T: Deref<Target = NodeState> + Sync
+{
+    match handlers.get(&message_type) {
+        Some(handler) => {
+            let sender = get_sender(&parsed);
+            let after_init = if message_type == "init" { Some(executor.handle()) } else { None };
+            let job = move || {
+                handler.handle_message(&parsed, state);
+                if let Some(executor) = after_init {
+                    release_held(state, handlers, executor.as_ref());
+                }
+            };
+            if executor.execute(Box::new(job)).is_err() {
+                state.log(Level::Warn, "worker_pool").log(&format!("Worker pool saturated, dropping {} message", message_type));
+                state.metrics().increment("messages.overloaded", &message_type);
+                reply_error(state, sender, temporarily_unavailable("node is overloaded".to_string()));
+            }
+        }
+        None => unsupported(state, &parsed),
+    }
+}
+
+/// Queues the messages held back until init, in order, before the node starts running, so
+/// none of them is overtaken by a message that arrives afterwards.
+fn release_held<T>(state: &'static T, handlers: &'static Handlers<T>, executor: &dyn Executor)
+    where T: Deref<Target = NodeState> + Sync
+{
+    if !state.is_initialized() {
+        return;
+    }
+    while !state.lifecycle().start() {
+        for message in state.lifecycle().take_held() {
+            if let Some(message_type) = get_message_type(&message) {
+                run(message, message_type, state, handlers, executor);
             }
         }
     }
 }
 
+pub fn reject_before_init(state: &NodeState, message: &JsonValue) {
+    if let Some((_, in_reply_to)) = get_sender(message) {
+        let error = temporarily_unavailable("node has not been initialized".to_string());
+        reject_message(&MaelstromError { in_reply_to, error }, message, state);
+    }
+}
+
+/// Tells the sender a request is not supported. Replies are never answered, since a late
+/// one whose callback has expired would otherwise bounce between nodes.
+fn unsupported(state: &NodeState, message: &JsonValue) {
+    state.log(Level::Warn, "read_respond").log(&format!("Did not find handler for message: {}", message));
+    if !message["body"]["in_reply_to"].is_null() {
+        return;
+    }
+    let text = format!("no handler for {}", message["body"]["type"]);
+    reply_error(state, get_sender(message), not_supported(text));
+}
+
+fn reject_input(state: &NodeState, sender: Option<(String, i32)>, text: String) {
+    let rejected = state.record_rejected_input();
+    state.log(Level::Warn, "read_respond").log(&format!("Rejected input #{}: {}", rejected, text));
+    reply_error(state, sender, malformed_request(text));
+}
+
+fn reply_error(state: &NodeState, sender: Option<(String, i32)>, error: DefiniteError) {
+    if let Some((src, in_reply_to)) = sender {
+        let error = MaelstromError { in_reply_to, error };
+        reply_with_error(&error, JsonValue::from(src), state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::mpsc::{channel, sync_channel};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use json::object;
+    use crate::error::ErrorCode;
+    use crate::router::Router;
+    use crate::transport::ChannelTransport;
+    use crate::worker_pool::Backpressure;
+    use super::*;
+
+    struct TestState(NodeState);
+
+    impl Deref for TestState {
+        type Target = NodeState;
+
+        fn deref(&self) -> &NodeState {
+            &self.0
+        }
+    }
+
+    struct SlowHandler {}
+
+    impl crate::message_handler::MessageHandler<TestState> for SlowHandler {
+        fn handle_message(&self, message: &JsonValue, curr_state: &TestState) {
+            if message["body"]["type"] == "init" {
+                curr_state.set_node_id(message["body"]["node_id"].to_string());
+            }
+            thread::sleep(Duration::from_millis(5));
+            let body = object! {type: "ok", in_reply_to: message["body"]["msg_id"].clone()};
+            curr_state.send_message(object! {src: curr_state.node_id(), dest: message["src"].clone(), body: body});
+        }
+    }
+
+    struct Inline {}
+
+    impl Executor for Inline {
+        fn execute(&self, job: crate::worker_pool::Job) -> Result<(), crate::worker_pool::Saturated> {
+            job();
+            Ok(())
+        }
+
+        fn handle(&self) -> Box<dyn Executor + Send> {
+            Box::new(Inline {})
+        }
+    }
+
+    #[test]
+    fn messages_without_a_handler_are_not_supported() {
+        let (reply_sender, replies) = sync_channel(4);
+        let state: &'static TestState = Box::leak(Box::new(TestState(NodeState::init(reply_sender))));
+        let handlers: &'static Handlers<TestState> = Box::leak(Box::new(Router::init().route("work", SlowHandler {}).build()));
+        state.set_node_id("n1".to_string());
+        state.lifecycle().start();
+        dispatch(object! {src: "c1", dest: "n1", body: {type: "frobnicate", msg_id: 3}}, state, handlers, &Inline {});
+
+        let reply = json::parse(&replies.try_recv().unwrap()).unwrap();
+        assert_eq!(reply["body"]["in_reply_to"], 3);
+        assert_eq!(ErrorCode::from_code(reply["body"]["code"].as_i32().unwrap()), ErrorCode::NotSupported);
+
+        dispatch(object! {src: "n2", dest: "n1", body: {type: "vote_ok", in_reply_to: 9}}, state, handlers, &Inline {});
+        assert!(replies.try_recv().is_err());
+    }
+
+    /// Queues jobs to be run by the test, one at a time.
+    #[derive(Clone, Default)]
+    struct Queue(Arc<Mutex<VecDeque<crate::worker_pool::Job>>>);
+
+    impl Queue {
+        fn run_all(&self) {
+            while let Some(job) = self.0.lock().unwrap().pop_front() {
+                job();
+            }
+        }
+    }
+
+    impl Executor for Queue {
+        fn execute(&self, job: crate::worker_pool::Job) -> Result<(), crate::worker_pool::Saturated> {
+            self.0.lock().unwrap().push_back(job);
+            Ok(())
+        }
+
+        fn handle(&self) -> Box<dyn Executor + Send> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn messages_held_for_init_are_queued_ahead_of_later_ones() {
+        let (reply_sender, replies) = sync_channel(8);
+        let state: &'static TestState = Box::leak(Box::new(TestState(NodeState::init(reply_sender))));
+        let handlers: &'static Handlers<TestState> = Box::leak(Box::new(
+            Router::init().route("init", SlowHandler {}).route("work", SlowHandler {}).build(),
+        ));
+        let queue = Queue::default();
+        dispatch(object! {src: "c1", dest: "n1", body: {type: "work", msg_id: 1}}, state, handlers, &queue);
+        dispatch(object! {src: "c1", dest: "n1", body: {type: "init", msg_id: 2, node_id: "n1", node_ids: ["n1"]}}, state, handlers, &queue);
+        dispatch(object! {src: "c1", dest: "n1", body: {type: "work", msg_id: 3}}, state, handlers, &queue);
+        assert!(!state.is_running());
+
+        let init = queue.0.lock().unwrap().pop_front().unwrap();
+        init();
+        assert!(state.is_running());
+        dispatch(object! {src: "c1", dest: "n1", body: {type: "work", msg_id: 4}}, state, handlers, &queue);
+        queue.run_all();
+
+        let answered: Vec<i32> = replies.try_iter().filter_map(|line| json::parse(&line).ok()?["body"]["in_reply_to"].as_i32()).collect();
+        assert_eq!(answered, vec![2, 1, 3, 4]);
+    }
+
+    #[test]
+    fn malformed_input_gets_an_error_reply_and_the_loop_goes_on() {
+        let (reply_sender, replies) = sync_channel(64);
+        let state: &'static TestState = Box::leak(Box::new(TestState(NodeState::init(reply_sender))));
+        let handlers: &'static Handlers<TestState> = Box::leak(Box::new(
+            Router::init().route("init", SlowHandler {}).route("work", SlowHandler {}).build(),
+        ));
+        let (input, incoming) = channel();
+        let (outgoing, _) = channel();
+        input.send(object! {src: "c1", dest: "n1", body: {type: "init", msg_id: 1, node_id: "n1", node_ids: ["n1"]}}.dump()).unwrap();
+        let pool = WorkerPool::init(1, 32, Backpressure::Block);
+        let node = thread::spawn(move || transport_loop(state, handlers, pool, &ChannelTransport::init(incoming, outgoing)));
+        // Errors can only be sent once init has told the node its id.
+        while !state.is_running() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        input.send(r#"{"src": "c1", "dest": "n1", "body": {"type": "work", "msg_id": 5"#.to_string()).unwrap();
+        input.send(object! {dest: "n1", body: {type: "work", msg_id: 6}}.dump()).unwrap();
+        input.send(object! {src: "c1", dest: "n1", body: {msg_id: 7}}.dump()).unwrap();
+        input.send(object! {src: "c1", dest: "n1", body: {type: "work", msg_id: 8}}.dump()).unwrap();
+        drop(input);
+        node.join().unwrap();
+
+        let replies: Vec<JsonValue> = replies.try_iter().map(|line| json::parse(&line).unwrap()).collect();
+        let errors: Vec<(i32, ErrorCode)> = replies
+            .iter()
+            .filter(|reply| reply["body"]["type"] == "error")
+            .map(|reply| (reply["body"]["in_reply_to"].as_i32().unwrap(), ErrorCode::from_code(reply["body"]["code"].as_i32().unwrap())))
+            .collect();
+        assert_eq!(errors, vec![(5, ErrorCode::MalformedRequest), (7, ErrorCode::MalformedRequest)]);
+        assert!(replies.iter().any(|reply| reply["body"]["in_reply_to"] == 8 && reply["body"]["type"] == "ok"));
+        assert_eq!(state.rejected_inputs(), 2);
+    }
+
+    #[test]
+    fn queued_messages_are_handled_when_input_closes() {
+        let (reply_sender, replies) = sync_channel(64);
+        let state: &'static TestState = Box::leak(Box::new(TestState(NodeState::init(reply_sender))));
+        let handlers: &'static Handlers<TestState> = Box::leak(Box::new(
+            Router::init().route("init", SlowHandler {}).route("work", SlowHandler {}).build(),
+        ));
+        let (input, incoming) = channel();
+        let (outgoing, _) = channel();
+        input.send(object! {src: "c1", dest: "n1", body: {type: "init", msg_id: 1, node_id: "n1", node_ids: ["n1"]}}.dump()).unwrap();
+        for msg_id in 2..12 {
+            input.send(object! {src: "c1", dest: "n1", body: {type: "work", msg_id: msg_id}}.dump()).unwrap();
+        }
+        drop(input);
+
+        let pool = WorkerPool::init(2, 32, Backpressure::Block);
+        transport_loop(state, handlers, pool, &ChannelTransport::init(incoming, outgoing));
+
+        let answered: Vec<i32> = replies.try_iter().filter_map(|line| json::parse(&line).ok()?["body"]["in_reply_to"].as_i32()).collect();
+        assert_eq!(answered.len(), 11);
+        assert!(state.is_shutting_down());
+    }
+}
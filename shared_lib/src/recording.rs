@@ -0,0 +1,251 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use json::{object, JsonValue};
+use crate::logging;
+use crate::replay::{Replay, ReplayTransport};
+use crate::transport::{StdioTransport, Transport};
+
+pub const RECORD_ENV: &str = "MAELSTROM_RECORD";
+pub const REPLAY_ENV: &str = "MAELSTROM_REPLAY";
+
+static SESSION: OnceLock<Session> = OnceLock::new();
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::In => "in",
+            Direction::Out => "out",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Direction> {
+        match text {
+            "in" => Some(Direction::In),
+            "out" => Some(Direction::Out),
+            _ => None,
+        }
+    }
+}
+
+/// One line of a recording: `{"ts": <ms since the epoch>, "dir": "in"|"out", "message": ...}`.
+/// Lines that could not be parsed as JSON are kept as strings so they replay verbatim.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub ts: u64,
+    pub direction: Direction,
+    pub message: JsonValue,
+}
+
+impl Entry {
+    pub fn init(direction: Direction, line: &str) -> Entry {
+        Entry {
+            ts: now_millis(),
+            direction,
+            message: json::parse(line).unwrap_or_else(|_| JsonValue::from(line)),
+        }
+    }
+
+    /// Accepts recorded entries and, for hand-written inputs, bare messages, which are
+    /// taken as inbound with no timing.
+    pub fn from_json(json: &JsonValue) -> Entry {
+        match json["dir"].as_str().and_then(Direction::parse) {
+            Some(direction) => Entry {
+                ts: json["ts"].as_u64().unwrap_or_default(),
+                direction,
+                message: json["message"].clone(),
+            },
+            None => Entry {
+                ts: 0,
+                direction: Direction::In,
+                message: json.clone(),
+            },
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        object! {ts: self.ts, dir: self.direction.as_str(), message: self.message.clone()}
+    }
+
+    pub fn line(&self) -> String {
+        match self.message.as_str() {
+            Some(raw) => raw.to_string(),
+            None => self.message.dump(),
+        }
+    }
+}
+
+/// Reads a JSONL recording. A file holding a single JSON object or array, like
+/// `topology.json`, is read as that message or list of messages.
+pub fn read_entries(path: &Path) -> io::Result<Vec<Entry>> {
+    let text = fs::read_to_string(path)?;
+    if let Ok(json) = json::parse(&text) {
+        if json.is_array() {
+            return Ok(json.members().map(Entry::from_json).collect());
+        }
+        return Ok(vec![Entry::from_json(&json)]);
+    }
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match json::parse(line) {
+            Ok(json) => entries.push(Entry::from_json(&json)),
+            Err(err) => logging::warn("recording").log(&format!("Skipping line {} of {}: {}", i + 1, path.display(), err)),
+        }
+    }
+    Ok(entries)
+}
+
+pub struct Recorder {
+    file: Mutex<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        Ok(Recorder {
+            file: Mutex::new(BufWriter::new(File::create(path)?)),
+        })
+    }
+
+    pub fn record(&self, direction: Direction, line: &str) {
+        let mut file = self.file.lock().unwrap();
+        let written = writeln!(file, "{}", Entry::init(direction, line).to_json().dump()).and_then(|_| file.flush());
+        if let Err(err) = written {
+            logging::error("recording").log(&format!("Failed to record message: {}", err));
+        }
+    }
+}
+
+/// Passes every line through to `inner` and writes it to the recording as well.
+pub struct RecordingTransport<T: Transport> {
+    inner: T,
+    recorder: Arc<Recorder>,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    pub fn init(inner: T, recorder: Arc<Recorder>) -> RecordingTransport<T> {
+        RecordingTransport { inner, recorder }
+    }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn read_line(&self) -> Option<io::Result<String>> {
+        let line = self.inner.read_line();
+        if let Some(Ok(line)) = &line {
+            self.recorder.record(Direction::In, line);
+        }
+        line
+    }
+
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        self.recorder.record(Direction::Out, line);
+        self.inner.write_line(line)
+    }
+}
+
+/// How the process talks to the outside world, picked once from the environment:
+/// `MAELSTROM_REPLAY=<file>` drives the node from a recording instead of stdin, and
+/// `MAELSTROM_RECORD=<file>` records a normal stdio session.
+pub enum Session {
+    Stdio(StdioTransport),
+    Record(RecordingTransport<StdioTransport>),
+    Replay(ReplayTransport),
+}
+
+impl Session {
+    fn from_env() -> Session {
+        if let Ok(path) = env::var(REPLAY_ENV) {
+            match Replay::load(Path::new(&path)) {
+                Ok(replay) => return Session::Replay(ReplayTransport::init(replay)),
+                Err(err) => logging::error("recording").log(&format!("Cannot replay {}: {}", path, err)),
+            }
+        }
+        if let Ok(path) = env::var(RECORD_ENV) {
+            match Recorder::create(Path::new(&path)) {
+                Ok(recorder) => return Session::Record(RecordingTransport::init(StdioTransport {}, Arc::new(recorder))),
+                Err(err) => logging::error("recording").log(&format!("Cannot record to {}: {}", path, err)),
+            }
+        }
+        Session::Stdio(StdioTransport {})
+    }
+
+    pub fn transport(&self) -> &dyn Transport {
+        match self {
+            Session::Stdio(transport) => transport,
+            Session::Record(transport) => transport,
+            Session::Replay(transport) => transport,
+        }
+    }
+}
+
+pub fn session() -> &'static Session {
+    SESSION.get_or_init(Session::from_env)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+    use std::path::PathBuf;
+    use crate::transport::ChannelTransport;
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("shared-lib-{}-{}", process::id(), name))
+    }
+
+    #[test]
+    fn records_both_directions_and_reads_them_back() {
+        let path = scratch("recording.jsonl");
+        let (node_side, peer) = ChannelTransport::pair();
+        let transport = RecordingTransport::init(node_side, Arc::new(Recorder::create(&path).unwrap()));
+
+        peer.write_line(r#"{"src":"c1","dest":"n1","body":{"type":"init"}}"#).unwrap();
+        peer.write_line("not json").unwrap();
+        assert_eq!(transport.read_line().unwrap().unwrap(), r#"{"src":"c1","dest":"n1","body":{"type":"init"}}"#);
+        assert_eq!(transport.read_line().unwrap().unwrap(), "not json");
+        transport.write_line(r#"{"src":"n1","dest":"c1","body":{"type":"init_ok"}}"#).unwrap();
+        assert_eq!(peer.read_line().unwrap().unwrap(), r#"{"src":"n1","dest":"c1","body":{"type":"init_ok"}}"#);
+
+        let entries = read_entries(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        let directions: Vec<Direction> = entries.iter().map(|entry| entry.direction).collect();
+        assert_eq!(directions, vec![Direction::In, Direction::In, Direction::Out]);
+        assert_eq!(entries[0].message["body"]["type"], "init");
+        assert_eq!(entries[1].line(), "not json");
+        assert_eq!(entries[2].message["body"]["type"], "init_ok");
+        assert!(entries.iter().all(|entry| entry.ts > 0));
+    }
+
+    #[test]
+    fn reads_bare_messages_as_untimed_inputs() {
+        let path = scratch("topology.json");
+        fs::write(&path, r#"[{"body":{"type":"topology"}}, {"body":{"type":"read"}}]"#).unwrap();
+        let entries = read_entries(&path).unwrap();
+
+        fs::write(&path, "{\"body\":{\"type\":\"read\"}}\n\n{\"ts\":5,\"dir\":\"out\",\"message\":{\"body\":{\"type\":\"read_ok\"}}}\nbroken\n").unwrap();
+        let lines = read_entries(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.direction == Direction::In && entry.ts == 0));
+        assert_eq!(entries[0].message["body"]["type"], "topology");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], Entry { ts: 0, direction: Direction::In, message: object! {body: {type: "read"}} });
+        assert_eq!(lines[1], Entry { ts: 5, direction: Direction::Out, message: object! {body: {type: "read_ok"}} });
+    }
+}
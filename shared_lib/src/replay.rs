@@ -0,0 +1,205 @@
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use json::JsonValue;
+use crate::logging;
+use crate::recording::{read_entries, Direction, Entry};
+use crate::transport::Transport;
+
+pub struct Replay {
+    inputs: Vec<Entry>,
+    expected: Vec<JsonValue>,
+    span: Duration,
+}
+
+impl Replay {
+    pub fn init(entries: Vec<Entry>) -> Replay {
+        let start = entries.first().map(|entry| entry.ts).unwrap_or_default();
+        let end = entries.iter().map(|entry| entry.ts).max().unwrap_or_default();
+        let (inputs, outputs): (Vec<Entry>, Vec<Entry>) = entries
+            .into_iter()
+            .map(|entry| Entry { ts: entry.ts.saturating_sub(start), ..entry })
+            .partition(|entry| entry.direction == Direction::In);
+        Replay {
+            inputs,
+            expected: outputs.into_iter().map(|entry| entry.message).collect(),
+            span: Duration::from_millis(end.saturating_sub(start)),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        read_entries(path).map(Replay::init)
+    }
+
+    pub fn inputs(&self) -> &[Entry] {
+        &self.inputs
+    }
+
+    pub fn expected(&self) -> &[JsonValue] {
+        &self.expected
+    }
+}
+
+/// Outputs that differ between a recording and its replay. Messages are compared whole
+/// and without regard to order.
+#[derive(Debug, Default)]
+pub struct Diff {
+    pub missing: Vec<JsonValue>,
+    pub unexpected: Vec<JsonValue>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+pub fn diff(expected: &[JsonValue], actual: &[JsonValue]) -> Diff {
+    let mut unmatched: Vec<&JsonValue> = actual.iter().collect();
+    let mut diff = Diff::default();
+    for message in expected {
+        match unmatched.iter().position(|candidate| *candidate == message) {
+            Some(i) => {
+                unmatched.remove(i);
+            }
+            None => diff.missing.push(message.clone()),
+        }
+    }
+    diff.unexpected = unmatched.into_iter().cloned().collect();
+    diff
+}
+
+/// Feeds a recording's inbound messages to the node at their recorded offsets and keeps
+/// whatever the node writes, so it can be compared with the recorded outputs.
+pub struct ReplayTransport {
+    replay: Replay,
+    next: Mutex<usize>,
+    started: OnceLock<Instant>,
+    outputs: Mutex<Vec<JsonValue>>,
+}
+
+impl ReplayTransport {
+    pub fn init(replay: Replay) -> ReplayTransport {
+        ReplayTransport {
+            replay,
+            next: Mutex::new(0),
+            started: OnceLock::new(),
+            outputs: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn outputs(&self) -> Vec<JsonValue> {
+        self.outputs.lock().unwrap().clone()
+    }
+
+    pub fn diff(&self) -> Diff {
+        diff(self.replay.expected(), &self.outputs())
+    }
+
+    /// Logs the differences from the recording and returns whether there were none.
+    pub fn report(&self) -> bool {
+        let diff = self.diff();
+        for message in diff.missing.iter() {
+            logging::warn("replay").log(&format!("Missing output {}", message));
+        }
+        for message in diff.unexpected.iter() {
+            logging::warn("replay").log(&format!("Unexpected output {}", message));
+        }
+        logging::info("replay").log(&format!(
+            "Replayed {} inputs: {} outputs expected, {} missing, {} unexpected",
+            self.replay.inputs().len(),
+            self.replay.expected().len(),
+            diff.missing.len(),
+            diff.unexpected.len()
+        ));
+        diff.is_empty()
+    }
+
+    fn wait_until(&self, offset: Duration) {
+        let started = *self.started.get_or_init(Instant::now);
+        let remaining = (started + offset).saturating_duration_since(Instant::now());
+        if !remaining.is_zero() {
+            thread::sleep(remaining);
+        }
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn read_line(&self) -> Option<io::Result<String>> {
+        let entry = {
+            let mut next = self.next.lock().unwrap();
+            let entry = self.replay.inputs().get(*next);
+            *next += 1;
+            entry
+        };
+        match entry {
+            Some(entry) => {
+                self.wait_until(Duration::from_millis(entry.ts));
+                Some(Ok(entry.line()))
+            }
+            None => {
+                self.wait_until(self.replay.span);
+                None
+            }
+        }
+    }
+
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let message = json::parse(line).unwrap_or_else(|_| JsonValue::from(line));
+        self.outputs.lock().unwrap().push(message);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::object;
+    use super::*;
+
+    fn entry(ts: u64, direction: Direction, message: JsonValue) -> Entry {
+        Entry { ts, direction, message }
+    }
+
+    #[test]
+    fn splits_a_recording_into_inputs_and_expected_outputs() {
+        let replay = Replay::init(vec![
+            entry(1000, Direction::In, object! {body: {type: "read", msg_id: 1}}),
+            entry(1003, Direction::Out, object! {body: {type: "read_ok", in_reply_to: 1}}),
+            entry(1005, Direction::In, object! {body: {type: "read", msg_id: 2}}),
+        ]);
+        let offsets: Vec<u64> = replay.inputs().iter().map(|entry| entry.ts).collect();
+        assert_eq!(offsets, vec![0, 5]);
+        assert_eq!(replay.expected(), &[object! {body: {type: "read_ok", in_reply_to: 1}}]);
+        assert_eq!(replay.span, Duration::from_millis(5));
+    }
+
+    #[test]
+    fn diffs_outputs_regardless_of_order() {
+        let expected = vec![object! {value: 1}, object! {value: 2}, object! {value: 2}];
+        assert!(diff(&expected, &[object! {value: 2}, object! {value: 1}, object! {value: 2}]).is_empty());
+
+        let found = diff(&expected, &[object! {value: 2}, object! {value: 3}]);
+        assert_eq!(found.missing, vec![object! {value: 1}, object! {value: 2}]);
+        assert_eq!(found.unexpected, vec![object! {value: 3}]);
+    }
+
+    #[test]
+    fn feeds_inputs_in_order_and_compares_what_the_node_writes() {
+        let transport = ReplayTransport::init(Replay::init(vec![
+            entry(0, Direction::In, object! {body: {type: "echo", echo: "a", msg_id: 1}}),
+            entry(1, Direction::Out, object! {body: {type: "echo_ok", echo: "a", in_reply_to: 1}}),
+            entry(2, Direction::In, JsonValue::from("not json")),
+        ]));
+        let first = json::parse(&transport.read_line().unwrap().unwrap()).unwrap();
+        assert_eq!(first["body"]["echo"], "a");
+        assert_eq!(transport.read_line().unwrap().unwrap(), "not json");
+        assert!(transport.read_line().is_none());
+        assert!(!transport.diff().is_empty());
+
+        transport.write_line(r#"{"body":{"type":"echo_ok","echo":"a","in_reply_to":1}}"#).unwrap();
+        assert!(transport.diff().is_empty());
+        assert!(transport.report());
+    }
+}
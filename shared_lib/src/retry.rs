@@ -0,0 +1,220 @@
+use std::time::Duration;
+use json::JsonValue;
+use crate::error::{timeout, DefiniteError};
+use crate::logging::Level;
+use crate::node_state::NodeState;
+use crate::rpc::{call, DEFAULT_RPC_TIMEOUT};
+
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 10;
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_millis(1000);
+
+/// Retries an RPC while it fails with a retryable error (timeout, temporarily
+/// unavailable, crash). A timed out request may still have been applied, so
+/// requests that are not safe to send twice should disable indefinite retries.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    destination: String,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    rpc_timeout: Duration,
+    deadline: Option<Duration>,
+    retry_indefinite: bool,
+}
+
+impl RetryPolicy {
+    pub fn init(destination: &str) -> RetryPolicy {
+        RetryPolicy {
+            destination: destination.to_string(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            rpc_timeout: DEFAULT_RPC_TIMEOUT,
+            deadline: None,
+            retry_indefinite: true,
+        }
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> RetryPolicy {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> RetryPolicy {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    pub fn with_rpc_timeout(mut self, rpc_timeout: Duration) -> RetryPolicy {
+        self.rpc_timeout = rpc_timeout;
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Duration) -> RetryPolicy {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_indefinite_retries(mut self, retry_indefinite: bool) -> RetryPolicy {
+        self.retry_indefinite = retry_indefinite;
+        self
+    }
+
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    pub fn call(&self, state: &NodeState, request_body: &JsonValue) -> Result<JsonValue, DefiniteError> {
+        let clock = state.clock();
+        let deadline = self.deadline.map(|deadline| clock.now() + deadline);
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let rpc_timeout = match deadline {
+                Some(deadline) => self.rpc_timeout.min(deadline.saturating_duration_since(clock.now())),
+                None => self.rpc_timeout,
+            };
+            let error = match self.attempt(state, request_body, rpc_timeout) {
+                Ok(body) => return Ok(body),
+                Err(error) => error,
+            };
+            if !self.should_retry(&error) || attempt >= self.max_attempts || state.is_shutting_down() {
+                return Err(error);
+            }
+            let backoff = self.backoff(state, attempt);
+            if deadline.is_some_and(|deadline| clock.now() + backoff >= deadline) {
+                return Err(error);
+            }
+            state.log(Level::Debug, "retry").log(&format!(
+                "Attempt {} to {} failed with {}, retrying in {:?}",
+                attempt, self.destination, error, backoff
+            ));
+            clock.sleep(backoff);
+        }
+    }
+
+    fn attempt(&self, state: &NodeState, request_body: &JsonValue, rpc_timeout: Duration) -> Result<JsonValue, DefiniteError> {
+        let mut request_body = request_body.clone();
+        let response = call(state, &mut request_body, &self.destination, rpc_timeout).wait();
+        let body = match response {
+            Some(response) => response["body"].clone(),
+            None => return Err(timeout(format!("{} did not respond in {:?}", self.destination, rpc_timeout))),
+        };
+        match DefiniteError::from_body(&body) {
+            Some(error) => Err(error),
+            None => Ok(body),
+        }
+    }
+
+    fn should_retry(&self, error: &DefiniteError) -> bool {
+        error.is_retryable() && (self.retry_indefinite || error.is_definite())
+    }
+
+    fn backoff(&self, state: &NodeState, attempt: u32) -> Duration {
+        let exponential = self.initial_backoff.saturating_mul(2u32.saturating_pow(attempt - 1));
+        let ceiling = exponential.min(self.max_backoff);
+        ceiling.mul_f64(state.random().gen_range(0.5..=1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{sync_channel, Receiver};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use json::object;
+    use crate::error::ErrorCode;
+    use super::*;
+
+    fn node() -> (Arc<NodeState>, Receiver<String>) {
+        let (sender, sent) = sync_channel(16);
+        let state = Arc::new(NodeState::init(sender));
+        state.callbacks().run_inline(true);
+        state.set_node_id("n1".to_string());
+        (state, sent)
+    }
+
+    fn call_in_background(state: &Arc<NodeState>, policy: RetryPolicy) -> JoinHandle<Result<JsonValue, DefiniteError>> {
+        let state = state.clone();
+        thread::spawn(move || policy.call(&state, &object! {type: "read", key: 1}))
+    }
+
+    fn reply(state: &NodeState, sent: &Receiver<String>, body: JsonValue) {
+        let request = json::parse(&sent.recv().unwrap()).unwrap();
+        assert_eq!(request["dest"], "lin-kv");
+        let mut body = body;
+        body["in_reply_to"] = request["body"]["msg_id"].clone();
+        assert!(state.complete_callback(&object! {src: "lin-kv", dest: "n1", body: body}));
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::init("lin-kv").with_backoff(Duration::from_millis(1), Duration::from_millis(1))
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_ceiling_with_jitter() {
+        let (state, _sent) = node();
+        let policy = RetryPolicy::init("lin-kv").with_backoff(Duration::from_millis(10), Duration::from_millis(50));
+        for (attempt, ceiling) in [(1, 10), (2, 20), (3, 40), (4, 50), (20, 50)] {
+            let backoff = policy.backoff(&state, attempt);
+            let ceiling = Duration::from_millis(ceiling);
+            assert!(backoff >= ceiling / 2 && backoff <= ceiling, "attempt {}: {:?}", attempt, backoff);
+        }
+    }
+
+    #[test]
+    fn retries_retryable_errors_until_a_reply_succeeds() {
+        let (state, sent) = node();
+        let call = call_in_background(&state, policy());
+        reply(&state, &sent, object! {type: "error", code: 11});
+        reply(&state, &sent, object! {type: "error", code: 13});
+        reply(&state, &sent, object! {type: "read_ok", value: 4});
+
+        assert_eq!(call.join().unwrap().unwrap()["value"], 4);
+    }
+
+    #[test]
+    fn returns_definite_errors_without_retrying() {
+        let (state, sent) = node();
+        let call = call_in_background(&state, policy());
+        reply(&state, &sent, object! {type: "error", code: 20, text: "not found"});
+
+        let error = call.join().unwrap().unwrap_err();
+        assert_eq!(error.error_code(), ErrorCode::KeyDoesNotExist);
+        assert!(sent.try_recv().is_err());
+    }
+
+    #[test]
+    fn gives_up_after_the_last_attempt() {
+        let (state, sent) = node();
+        let call = call_in_background(&state, policy().with_max_attempts(3));
+        for _ in 0..3 {
+            reply(&state, &sent, object! {type: "error", code: 11});
+        }
+
+        assert_eq!(call.join().unwrap().unwrap_err().error_code(), ErrorCode::TemporarilyUnavailable);
+        assert!(sent.try_recv().is_err());
+    }
+
+    #[test]
+    fn does_not_resend_after_an_indefinite_error_when_asked_not_to() {
+        let (state, sent) = node();
+        let call = call_in_background(&state, policy().with_indefinite_retries(false));
+        reply(&state, &sent, object! {type: "error", code: 13});
+
+        assert_eq!(call.join().unwrap().unwrap_err().error_code(), ErrorCode::Crash);
+        assert!(sent.try_recv().is_err());
+    }
+
+    #[test]
+    fn turns_missing_replies_into_timeouts() {
+        let (state, sent) = node();
+        let policy = policy().with_rpc_timeout(Duration::from_millis(5)).with_max_attempts(2);
+
+        let error = policy.call(&state, &object! {type: "read", key: 1}).unwrap_err();
+        assert_eq!(error.error_code(), ErrorCode::Timeout);
+        assert_eq!(sent.try_iter().count(), 2);
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Arc;
+use json::JsonValue;
+use crate::message_handler::MessageHandler;
+use crate::node_state::NodeState;
+
+pub type Handlers<T> = HashMap<String, Route<T>>;
+
+pub trait Middleware<T>: Send + Sync
+    where T: Deref<Target=NodeState>
+{
+    fn handle(&self, message: &JsonValue, curr_state: &T, next: Next<'_, T>);
+}
+
+pub struct Next<'a, T: Deref<Target=NodeState>> {
+    middleware: &'a [Arc<dyn Middleware<T>>],
+    handler: &'a dyn MessageHandler<T>,
+}
+
+impl<T> Next<'_, T>
+    where T: Deref<Target=NodeState>
+{
+    pub fn run(self, message: &JsonValue, curr_state: &T) {
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => middleware.handle(message, curr_state, Next { middleware: rest, handler: self.handler }),
+            None => self.handler.handle_message(message, curr_state),
+        }
+    }
+}
+
+pub struct Route<T: Deref<Target=NodeState>> {
+    handler: Box<dyn MessageHandler<T>>,
+    middleware: Vec<Arc<dyn Middleware<T>>>,
+}
+
+impl<T> Route<T>
+    where T: Deref<Target=NodeState>
+{
+    pub fn handle_message(&self, message: &JsonValue, curr_state: &T) {
+        Next { middleware: &self.middleware, handler: self.handler.as_ref() }.run(message, curr_state)
+    }
+}
+
+/// Middleware runs in the order it was added, so the first `with` sees a message first.
+pub struct Router<T: Deref<Target=NodeState>> {
+    handlers: HashMap<String, Box<dyn MessageHandler<T>>>,
+    middleware: Vec<Arc<dyn Middleware<T>>>,
+}
+
+impl<T> Router<T>
+    where T: Deref<Target=NodeState>
+{
+    pub fn init() -> Router<T> {
+        Router {
+            handlers: HashMap::new(),
+            middleware: Vec::new(),
+        }
+    }
+
+    pub fn route<H>(mut self, message_type: &str, handler: H) -> Router<T>
+        where H: MessageHandler<T> + 'static
+    {
+        self.handlers.insert(message_type.to_string(), Box::new(handler));
+        self
+    }
+
+    pub fn with<M>(mut self, middleware: M) -> Router<T>
+        where M: Middleware<T> + 'static
+    {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    pub fn build(self) -> Handlers<T> {
+        let middleware = self.middleware;
+        self.handlers
+            .into_iter()
+            .map(|(message_type, handler)| (message_type, Route { handler, middleware: middleware.clone() }))
+            .collect()
+    }
+}
+
+impl<T> Default for Router<T>
+    where T: Deref<Target=NodeState>
+{
+    fn default() -> Self {
+        Router::init()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+    use std::sync::Mutex;
+    use json::object;
+    use super::*;
+
+    struct TestState(NodeState);
+
+    impl Deref for TestState {
+        type Target = NodeState;
+
+        fn deref(&self) -> &NodeState {
+            &self.0
+        }
+    }
+
+    type Calls = Arc<Mutex<Vec<String>>>;
+
+    struct Record {
+        name: &'static str,
+        calls: Calls,
+    }
+
+    impl Middleware<TestState> for Record {
+        fn handle(&self, message: &JsonValue, curr_state: &TestState, next: Next<'_, TestState>) {
+            self.calls.lock().unwrap().push(format!("{} before", self.name));
+            next.run(message, curr_state);
+            self.calls.lock().unwrap().push(format!("{} after", self.name));
+        }
+    }
+
+    struct Handler {
+        calls: Calls,
+    }
+
+    impl MessageHandler<TestState> for Handler {
+        fn handle_message(&self, message: &JsonValue, _curr_state: &TestState) {
+            self.calls.lock().unwrap().push(format!("handled {}", message["body"]["type"]));
+        }
+    }
+
+    #[test]
+    fn middleware_wraps_handlers_in_the_order_it_was_added() {
+        let calls: Calls = Arc::default();
+        let handlers = Router::init()
+            .with(Record { name: "outer", calls: calls.clone() })
+            .route("read", Handler { calls: calls.clone() })
+            .with(Record { name: "inner", calls: calls.clone() })
+            .build();
+        let (sender, _) = sync_channel(1);
+        let state = TestState(NodeState::init(sender));
+        handlers["read"].handle_message(&object! {src: "c1", dest: "n1", body: {type: "read", msg_id: 1}}, &state);
+
+        assert_eq!(*calls.lock().unwrap(), vec!["outer before", "inner before", "handled read", "inner after", "outer after"]);
+        assert!(!handlers.contains_key("write"));
+    }
+}
@@ -1,60 +1,229 @@
+use crate::callbacks::{Callback, Callbacks};
+use crate::clock::Clock;
 use crate::node_state::NodeState;
-use crate::stdio::write_log;
-use json::{JsonValue, stringify, object};
-use std::sync::mpsc::{sync_channel, TryRecvError, Receiver, RecvTimeoutError};
-use std::time::Duration;
-use std::thread;
-use std::borrow::Borrow;
-use std::thread::JoinHandle;
+use crate::logging;
+use crate::mailbox::Mailbox;
+use crate::metrics::Metrics;
+use json::{JsonValue, object};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_millis(5000);
+
+pub struct RpcHandle {
+    msg_id: i32,
+    deadline: Instant,
+    mailbox: Arc<Mailbox<JsonValue>>,
+    clock: Arc<dyn Clock>,
+    callbacks: Arc<Callbacks>,
+    destination: String,
+    sent_at: Instant,
+    metrics: Arc<Metrics>,
+}
+
+impl RpcHandle {
+    pub fn msg_id(&self) -> i32 {
+        self.msg_id
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Blocks until the response arrives or the node's clock passes the deadline.
+    pub fn wait(self) -> Option<JsonValue> {
+        match self.mailbox.take_until(&*self.clock, self.deadline) {
+            Some(jv) => {
+                self.record_response();
+                Some(jv)
+            }
+            None => {
+                logging::debug("rpc").msg_id(self.msg_id).log("No response before the deadline");
+                self.metrics.increment("rpc.timeouts", &self.destination);
+                None
+            }
+        }
+    }
+
+    pub fn try_response(&self) -> Option<JsonValue> {
+        let response = self.mailbox.try_take();
+        if response.is_some() {
+            self.record_response();
+        }
+        response
+    }
+
+    fn record_response(&self) {
+        let latency = self.clock.now().saturating_duration_since(self.sent_at);
+        self.metrics.observe("rpc.latency", &self.destination, latency);
+    }
+
+    pub fn cancel(self) -> bool {
+        self.callbacks.cancel(self.msg_id)
+    }
+}
+
+impl Drop for RpcHandle {
+    fn drop(&mut self) {
+        self.callbacks.cancel(self.msg_id);
+    }
+}
 
 pub fn send_ff(state: &NodeState, request_body: &mut JsonValue, to: &str) {
     let msg_id = state.next_msg_id();
     request_body["msg_id"] = JsonValue::from(msg_id);
     let request =
         object! {dest: to, src: state.node_id(), body: request_body.clone()};
-    state.get_channel().send(stringify(request));
+    state.metrics().increment("messages.sent", to);
+    state.send_message(request);
 }
 
-pub fn send_rpc(state: &NodeState, request_body: &mut JsonValue, to: &str) -> Option<JsonValue> {
+pub fn call(state: &NodeState, request_body: &mut JsonValue, to: &str, timeout: Duration) -> RpcHandle {
+    let mailbox = Mailbox::init();
+    let clock = state.clock();
+    let sent_at = clock.now();
+    let msg_id = send_with_callback(state, request_body, to, Callback::Mailbox(mailbox.clone()), timeout);
+    RpcHandle {
+        msg_id,
+        deadline: sent_at + timeout,
+        mailbox,
+        clock,
+        callbacks: state.callbacks(),
+        destination: to.to_string(),
+        sent_at,
+        metrics: state.metrics(),
+    }
+}
+
+pub fn call_with<F>(state: &NodeState, request_body: &mut JsonValue, to: &str, timeout: Duration, on_complete: F) -> i32
+    where F: FnOnce(Option<JsonValue>) + Send + 'static
+{
+    let metrics = state.metrics();
+    let destination = to.to_string();
+    let clock = state.clock();
+    let sent_at = clock.now();
+    let on_complete = move |response: Option<JsonValue>| {
+        match response {
+            Some(_) => metrics.observe("rpc.latency", &destination, clock.now().saturating_duration_since(sent_at)),
+            None => metrics.increment("rpc.timeouts", &destination),
+        }
+        on_complete(response)
+    };
+    send_with_callback(state, request_body, to, Callback::Closure(Box::new(on_complete)), timeout)
+}
+
+pub fn cancel_rpc(state: &NodeState, msg_id: i32) -> bool {
+    state.callbacks().cancel(msg_id)
+}
+
+fn send_with_callback(state: &NodeState, request_body: &mut JsonValue, to: &str, callback: Callback, timeout: Duration) -> i32 {
     let msg_id = state.next_msg_id();
     request_body["msg_id"] = JsonValue::from(msg_id);
     let request =
         object! {dest: to, src: state.node_id(), body: request_body.clone()};
-    let (sender, receiver) = sync_channel(1);
-    state.add_callback(msg_id, sender);
-    state.get_channel().send(stringify(request.clone()));
-    let response = receiver.recv_timeout(Duration::from_millis(5000));
-    match response {
-        Ok(jv) => Some(jv),
-        Err(err) => {
-            write_log(format!("RPC timout error. {}", request).as_str());
-            None
-        }
-    }
+    state.callbacks().register(msg_id, callback, timeout);
+    state.metrics().increment("rpc.sent", to);
+    state.send_message(request);
+    msg_id
 }
 
-pub fn retry_rpc(state: &NodeState, request_body: &mut JsonValue) -> JsonValue {
-    while let rpc_response = send_rpc(state, request_body, "lin-kv").unwrap() {
-        if rpc_response["body"]["type"] != "error" {
-            return rpc_response["body"].clone();
+pub fn send_rpc(state: &NodeState, request_body: &mut JsonValue, to: &str) -> Option<JsonValue> {
+    send_rpc_with_timeout(state, request_body, to, DEFAULT_RPC_TIMEOUT)
+}
+
+pub fn send_rpc_with_timeout(state: &NodeState, request_body: &mut JsonValue, to: &str, timeout: Duration) -> Option<JsonValue> {
+    call(state, request_body, to, timeout).wait()
+}
+
+pub fn broadcast_rpc(state: &NodeState, request_body: &mut JsonValue) -> Vec<RpcHandle> {
+    state.other_nodes()
+        .iter()
+        .map(|node| call(state, request_body, node, DEFAULT_RPC_TIMEOUT))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{sync_channel, Receiver};
+    use std::thread;
+    use crate::clock::ManualClock;
+    use crate::quorum::{gather, majority};
+    use super::*;
+
+    fn node() -> (Arc<NodeState>, Arc<ManualClock>, Receiver<String>) {
+        let (sender, sent) = sync_channel(16);
+        let state = Arc::new(NodeState::init(sender));
+        let clock = Arc::new(ManualClock::init());
+        state.set_clock(clock.clone());
+        state.callbacks().run_inline(true);
+        state.set_node_id("n1".to_string());
+        state.set_other_node_ids(vec!["n1", "n2", "n3"]);
+        (state, clock, sent)
+    }
+
+    fn reply_to(sent: &Receiver<String>, body: JsonValue) -> JsonValue {
+        let request = json::parse(&sent.recv().unwrap()).unwrap();
+        let mut body = body;
+        body["in_reply_to"] = request["body"]["msg_id"].clone();
+        object! {src: request["dest"].clone(), dest: "n1", body: body}
+    }
+
+    #[test]
+    fn wait_times_out_when_the_node_clock_passes_the_deadline() {
+        let (state, clock, _sent) = node();
+        let waiter = {
+            let state = state.clone();
+            thread::spawn(move || call(&state, &mut object! {type: "read"}, "n2", Duration::from_millis(100)).wait())
+        };
+        while clock.parked() == 0 {
+            thread::sleep(Duration::from_millis(1));
         }
-        thread::sleep(Duration::from_millis(10));
-    }
-    JsonValue::from("Should not be here!")
-}
-
-pub fn broadcast_rpc(state: &NodeState, request_body: &mut JsonValue) -> Vec<Receiver<JsonValue>> {
-    let other_nodes = state.other_nodes();
-    let mut receivers = Vec::new();
-    for node in other_nodes {
-        let msg_id = state.next_msg_id();
-        request_body["msg_id"] = JsonValue::from(msg_id);
-        let request =
-            object! {dest: node, src: state.node_id(), body: request_body.clone()};
-        let (sender, receiver) = sync_channel(1);
-        state.add_callback(msg_id, sender);
-        state.get_channel().send(stringify(request));
-        receivers.push(receiver);
-    }
-    receivers
-}
\ No newline at end of file
+        clock.advance(Duration::from_millis(99));
+        thread::sleep(Duration::from_millis(20));
+        assert!(!waiter.is_finished());
+
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(waiter.join().unwrap(), None);
+        assert_eq!(state.metrics().counter("rpc.timeouts", "n2"), 1);
+    }
+
+    #[test]
+    fn wait_measures_latency_on_the_node_clock() {
+        let (state, clock, sent) = node();
+        let waiter = {
+            let state = state.clone();
+            thread::spawn(move || call(&state, &mut object! {type: "read"}, "n2", DEFAULT_RPC_TIMEOUT).wait())
+        };
+        let reply = reply_to(&sent, object! {type: "read_ok", value: 3});
+        clock.advance(Duration::from_millis(30));
+        assert!(state.complete_callback(&reply));
+
+        assert_eq!(waiter.join().unwrap(), Some(reply));
+        let latency = state.metrics().histogram("rpc.latency", "n2").unwrap();
+        assert_eq!(latency.percentile(1.0), 30_000);
+    }
+
+    #[test]
+    fn gather_stops_at_a_majority_and_cancels_the_rest() {
+        let (state, _clock, sent) = node();
+        let gathering = {
+            let state = state.clone();
+            thread::spawn(move || {
+                let peers = state.other_nodes();
+                let mut votes = 1;
+                gather(&state, &object! {type: "vote"}, &peers, DEFAULT_RPC_TIMEOUT, |_| {
+                    votes += 1;
+                    votes >= majority(3)
+                })
+            })
+        };
+        let reply = reply_to(&sent, object! {type: "vote_ok"});
+        assert!(state.complete_callback(&reply));
+
+        let quorum = gathering.join().unwrap();
+        assert!(quorum.reached);
+        assert_eq!(quorum.replies.len(), 1);
+        assert!(quorum.failed.is_empty());
+        assert_eq!(state.callbacks().outstanding(), 0);
+    }
+}
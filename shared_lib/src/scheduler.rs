@@ -0,0 +1,315 @@
+use std::collections::{BTreeSet, HashMap};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::clock::Clock;
+use crate::logging;
+
+/// Upper bound on how long the driver thread sleeps between checks, so a clock that is
+/// advanced by hand is still noticed.
+const MAX_IDLE: Duration = Duration::from_millis(100);
+
+type Task = Arc<dyn Fn() + Send + Sync>;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TimerId(u64);
+
+struct Timer {
+    deadline: Instant,
+    period: Option<Duration>,
+    task: Task,
+}
+
+struct Timers {
+    next_id: u64,
+    timers: HashMap<u64, Timer>,
+    queue: BTreeSet<(Instant, u64)>,
+    closed: bool,
+}
+
+impl Timers {
+    fn next_deadline(&self) -> Option<Instant> {
+        self.queue.iter().next().map(|(deadline, _)| *deadline)
+    }
+
+    fn arm(&mut self, id: u64, deadline: Instant) {
+        if let Some(timer) = self.timers.get_mut(&id) {
+            self.queue.remove(&(timer.deadline, id));
+            timer.deadline = deadline;
+            self.queue.insert((deadline, id));
+        }
+    }
+}
+
+/// One-shot and periodic timers driven by a `Clock`. `spawn` runs them on a background
+/// thread; under a manual clock `run_due` can be called after each `advance` instead.
+/// Tasks run one at a time, so long work should be handed off to another thread.
+pub struct Scheduler {
+    clock: Arc<dyn Clock>,
+    timers: Mutex<Timers>,
+    changed: Condvar,
+}
+
+impl Scheduler {
+    pub fn init(clock: Arc<dyn Clock>) -> Scheduler {
+        Scheduler {
+            clock,
+            timers: Mutex::new(Timers {
+                next_id: 0,
+                timers: HashMap::new(),
+                queue: BTreeSet::new(),
+                closed: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    pub fn once<F>(&self, delay: Duration, task: F) -> TimerId
+        where F: Fn() + Send + Sync + 'static
+    {
+        self.add(delay, None, Arc::new(task))
+    }
+
+    /// Runs `task` every `period`, first after one period. The next run is timed from when
+    /// the previous one started, so a stalled scheduler does not fire a burst to catch up.
+    pub fn every<F>(&self, period: Duration, task: F) -> TimerId
+        where F: Fn() + Send + Sync + 'static
+    {
+        self.add(period, Some(period.max(Duration::from_millis(1))), Arc::new(task))
+    }
+
+    fn add(&self, delay: Duration, period: Option<Duration>, task: Task) -> TimerId {
+        let deadline = self.clock.now() + delay;
+        let mut timers = self.timers.lock().unwrap();
+        timers.next_id += 1;
+        let id = timers.next_id;
+        if !timers.closed {
+            timers.timers.insert(id, Timer { deadline, period, task });
+            timers.queue.insert((deadline, id));
+            self.changed.notify_all();
+        }
+        TimerId(id)
+    }
+
+    /// Moves the timer's next run to `delay` from now. Returns false if it already fired
+    /// (for one-shot timers) or was cancelled.
+    pub fn reschedule(&self, id: TimerId, delay: Duration) -> bool {
+        let deadline = self.clock.now() + delay;
+        let mut timers = self.timers.lock().unwrap();
+        if !timers.timers.contains_key(&id.0) {
+            return false;
+        }
+        timers.arm(id.0, deadline);
+        self.changed.notify_all();
+        true
+    }
+
+    pub fn cancel(&self, id: TimerId) -> bool {
+        let mut timers = self.timers.lock().unwrap();
+        match timers.timers.remove(&id.0) {
+            Some(timer) => {
+                timers.queue.remove(&(timer.deadline, id.0));
+                self.changed.notify_all();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.timers.lock().unwrap().next_deadline()
+    }
+
+    pub fn len(&self) -> usize {
+        self.timers.lock().unwrap().timers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Runs every timer that is due now and returns how many ran. Timers that become due
+    /// while these run wait for the next call.
+    pub fn run_due(&self) -> usize {
+        let now = self.clock.now();
+        let mut due = Vec::new();
+        {
+            let mut timers = self.timers.lock().unwrap();
+            while let Some((deadline, id)) = timers.queue.iter().next().copied() {
+                if deadline > now {
+                    break;
+                }
+                timers.queue.remove(&(deadline, id));
+                let (task, period) = {
+                    let timer = &timers.timers[&id];
+                    (timer.task.clone(), timer.period)
+                };
+                match period {
+                    Some(period) => timers.arm(id, now + period),
+                    None => {
+                        timers.timers.remove(&id);
+                    }
+                }
+                due.push(task);
+            }
+        }
+        for task in due.iter() {
+            if panic::catch_unwind(AssertUnwindSafe(|| task())).is_err() {
+                logging::error("scheduler").log("Recovered from a panicking timer task");
+            }
+        }
+        due.len()
+    }
+
+    /// Runs timers as they come due until `shutdown` is called.
+    pub fn run(&self) {
+        let mut timers = self.timers.lock().unwrap();
+        while !timers.closed {
+            let now = self.clock.now();
+            let wait = match timers.next_deadline() {
+                Some(deadline) if deadline <= now => {
+                    drop(timers);
+                    self.run_due();
+                    timers = self.timers.lock().unwrap();
+                    continue;
+                }
+                Some(deadline) => deadline.duration_since(now).min(MAX_IDLE),
+                None => MAX_IDLE,
+            };
+            timers = self.changed.wait_timeout(timers, wait).unwrap().0;
+        }
+    }
+
+    pub fn spawn(self: &Arc<Self>) -> JoinHandle<()> {
+        let scheduler = Arc::clone(self);
+        thread::spawn(move || scheduler.run())
+    }
+
+    /// Stops `run` and drops every pending timer.
+    pub fn shutdown(&self) {
+        let mut timers = self.timers.lock().unwrap();
+        timers.closed = true;
+        timers.timers.clear();
+        timers.queue.clear();
+        self.changed.notify_all();
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.timers.lock().unwrap().closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::clock::ManualClock;
+    use super::*;
+
+    fn scheduler() -> (Scheduler, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::init());
+        (Scheduler::init(clock.clone()), clock)
+    }
+
+    fn counter() -> (Arc<AtomicUsize>, impl Fn() + Send + Sync + 'static) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = count.clone();
+        (count, move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    #[test]
+    fn one_shot_timers_fire_once_when_due() {
+        let (scheduler, clock) = scheduler();
+        let (count, task) = counter();
+        scheduler.once(Duration::from_millis(10), task);
+
+        clock.advance(Duration::from_millis(9));
+        assert_eq!(scheduler.run_due(), 0);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(scheduler.run_due(), 1);
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(scheduler.run_due(), 0);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn periodic_timers_are_timed_from_their_last_run() {
+        let (scheduler, clock) = scheduler();
+        let (count, task) = counter();
+        scheduler.every(Duration::from_millis(10), task);
+
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(scheduler.run_due(), 1);
+        clock.advance(Duration::from_millis(35));
+        assert_eq!(scheduler.run_due(), 1);
+        assert_eq!(scheduler.next_deadline(), Some(clock.now() + Duration::from_millis(10)));
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(scheduler.run_due(), 1);
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn rescheduling_pushes_a_timer_back_and_cancelling_removes_it() {
+        let (scheduler, clock) = scheduler();
+        let (count, task) = counter();
+        let election = scheduler.once(Duration::from_millis(10), task);
+
+        clock.advance(Duration::from_millis(8));
+        assert!(scheduler.reschedule(election, Duration::from_millis(10)));
+        clock.advance(Duration::from_millis(8));
+        assert_eq!(scheduler.run_due(), 0);
+        clock.advance(Duration::from_millis(2));
+        assert_eq!(scheduler.run_due(), 1);
+        assert!(!scheduler.reschedule(election, Duration::from_millis(10)));
+
+        let (_, task) = counter();
+        let heartbeat = scheduler.every(Duration::from_millis(5), task);
+        assert!(scheduler.cancel(heartbeat));
+        assert!(!scheduler.cancel(heartbeat));
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(scheduler.run_due(), 0);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn a_panicking_task_does_not_stop_the_others() {
+        let (scheduler, clock) = scheduler();
+        let (count, task) = counter();
+        scheduler.every(Duration::from_millis(10), || panic!("timer task failed"));
+        scheduler.once(Duration::from_millis(10), task);
+
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(scheduler.run_due(), 2);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert_eq!(scheduler.len(), 1);
+    }
+
+    #[test]
+    fn the_driver_thread_runs_due_timers_until_shutdown() {
+        let (scheduler, clock) = scheduler();
+        let scheduler = Arc::new(scheduler);
+        let (count, task) = counter();
+        scheduler.every(Duration::from_millis(10), task);
+        let driver = scheduler.spawn();
+
+        clock.advance(Duration::from_millis(10));
+        while count.load(Ordering::SeqCst) == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        scheduler.shutdown();
+        driver.join().unwrap();
+        assert!(scheduler.is_shut_down());
+        assert!(scheduler.is_empty());
+
+        let (_, task) = counter();
+        scheduler.once(Duration::ZERO, task);
+        assert!(scheduler.is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+use json::{object, JsonValue};
+use crate::error::{malformed_request, DefiniteError};
+use crate::node_state::NodeState;
+use crate::retry::RetryPolicy;
+
+pub const LIN_KV: &str = "lin-kv";
+pub const SEQ_KV: &str = "seq-kv";
+pub const LWW_KV: &str = "lww-kv";
+pub const LIN_TSO: &str = "lin-tso";
+
+pub trait KvValue: Sized {
+    fn from_json(value: &JsonValue) -> Result<Self, DefiniteError>;
+}
+
+impl KvValue for JsonValue {
+    fn from_json(value: &JsonValue) -> Result<Self, DefiniteError> {
+        Ok(value.clone())
+    }
+}
+
+impl KvValue for i32 {
+    fn from_json(value: &JsonValue) -> Result<Self, DefiniteError> {
+        value.as_i32().ok_or_else(|| unexpected_value(value, "an integer"))
+    }
+}
+
+impl KvValue for i64 {
+    fn from_json(value: &JsonValue) -> Result<Self, DefiniteError> {
+        value.as_i64().ok_or_else(|| unexpected_value(value, "an integer"))
+    }
+}
+
+impl KvValue for bool {
+    fn from_json(value: &JsonValue) -> Result<Self, DefiniteError> {
+        value.as_bool().ok_or_else(|| unexpected_value(value, "a boolean"))
+    }
+}
+
+impl KvValue for String {
+    fn from_json(value: &JsonValue) -> Result<Self, DefiniteError> {
+        value.as_str().map(|s| s.to_string()).ok_or_else(|| unexpected_value(value, "a string"))
+    }
+}
+
+impl<T: KvValue> KvValue for Vec<T> {
+    fn from_json(value: &JsonValue) -> Result<Self, DefiniteError> {
+        if !value.is_array() {
+            return Err(unexpected_value(value, "an array"));
+        }
+        value.members().map(T::from_json).collect()
+    }
+}
+
+pub struct KvClient<'a> {
+    state: &'a NodeState,
+    retry: RetryPolicy,
+}
+
+impl<'a> KvClient<'a> {
+    pub fn init(state: &'a NodeState, service: &str) -> KvClient<'a> {
+        KvClient {
+            state,
+            retry: RetryPolicy::init(service),
+        }
+    }
+
+    pub fn lin_kv(state: &'a NodeState) -> KvClient<'a> {
+        KvClient::init(state, LIN_KV)
+    }
+
+    pub fn seq_kv(state: &'a NodeState) -> KvClient<'a> {
+        KvClient::init(state, SEQ_KV)
+    }
+
+    pub fn lww_kv(state: &'a NodeState) -> KvClient<'a> {
+        KvClient::init(state, LWW_KV)
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> KvClient<'a> {
+        self.retry = retry;
+        self
+    }
+
+    pub fn service(&self) -> &str {
+        self.retry.destination()
+    }
+
+    pub fn read<V: KvValue>(&self, key: impl Into<JsonValue>) -> Result<V, DefiniteError> {
+        let body = self.retry.call(self.state, &object! {type: "read", key: key.into()})?;
+        expect_type(&body, "read_ok")?;
+        V::from_json(&body["value"])
+    }
+
+    pub fn write(&self, key: impl Into<JsonValue>, value: impl Into<JsonValue>) -> Result<(), DefiniteError> {
+        let body = self.retry.call(self.state, &object! {type: "write", key: key.into(), value: value.into()})?;
+        expect_type(&body, "write_ok")
+    }
+
+    /// A cas that timed out may still have been applied, so only definite failures are retried.
+    pub fn cas(
+        &self,
+        key: impl Into<JsonValue>,
+        from: impl Into<JsonValue>,
+        to: impl Into<JsonValue>,
+        create_if_not_exists: bool,
+    ) -> Result<(), DefiniteError> {
+        let request = object! {
+            type: "cas",
+            key: key.into(),
+            from: from.into(),
+            to: to.into(),
+            create_if_not_exists: create_if_not_exists
+        };
+        let body = self.retry.clone().with_indefinite_retries(false).call(self.state, &request)?;
+        expect_type(&body, "cas_ok")
+    }
+}
+
+pub struct TsoClient<'a> {
+    state: &'a NodeState,
+    retry: RetryPolicy,
+}
+
+impl<'a> TsoClient<'a> {
+    pub fn init(state: &'a NodeState) -> TsoClient<'a> {
+        TsoClient {
+            state,
+            retry: RetryPolicy::init(LIN_TSO),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> TsoClient<'a> {
+        self.retry = retry;
+        self
+    }
+
+    pub fn ts(&self) -> Result<i64, DefiniteError> {
+        let body = self.retry.call(self.state, &object! {type: "ts"})?;
+        expect_type(&body, "ts_ok")?;
+        i64::from_json(&body["ts"])
+    }
+}
+
+fn expect_type(body: &JsonValue, expected: &str) -> Result<(), DefiniteError> {
+    if body["type"] == expected {
+        return Ok(());
+    }
+    Err(malformed_request(format!("Expected a {} reply but got {}", expected, body)))
+}
+
+fn unexpected_value(value: &JsonValue, expected: &str) -> DefiniteError {
+    malformed_request(format!("Expected {} but got {}", expected, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::mpsc::{sync_channel, Receiver};
+    use std::sync::{Arc, Weak};
+    use std::thread;
+    use std::time::Duration;
+    use crate::error::ErrorCode;
+    use super::*;
+
+    /// A node whose requests are answered by `serve` on another thread, until the node is dropped.
+    fn node<F>(serve: F) -> Arc<NodeState>
+        where F: FnMut(&JsonValue) -> JsonValue + Send + 'static
+    {
+        let (sender, sent) = sync_channel(16);
+        let state = Arc::new(NodeState::init(sender));
+        state.callbacks().run_inline(true);
+        state.set_node_id("n1".to_string());
+        let service = Arc::downgrade(&state);
+        thread::spawn(move || answer(service, sent, serve));
+        state
+    }
+
+    fn answer<F>(state: Weak<NodeState>, sent: Receiver<String>, mut serve: F)
+        where F: FnMut(&JsonValue) -> JsonValue
+    {
+        while let Ok(line) = sent.recv() {
+            let request = json::parse(&line).unwrap();
+            let mut body = serve(&request["body"]);
+            body["in_reply_to"] = request["body"]["msg_id"].clone();
+            let reply = object! {src: request["dest"].clone(), dest: "n1", body: body};
+            match state.upgrade() {
+                Some(state) => state.complete_callback(&reply),
+                None => return,
+            };
+        }
+    }
+
+    /// Answers like Maelstrom's lin-kv, over an in-memory map.
+    fn kv() -> impl FnMut(&JsonValue) -> JsonValue {
+        let mut values: HashMap<String, JsonValue> = HashMap::new();
+        move |body| {
+            let key = body["key"].dump();
+            match (body["type"].as_str().unwrap(), values.get(&key)) {
+                ("read", Some(value)) => object! {type: "read_ok", value: value.clone()},
+                ("write", _) => {
+                    values.insert(key, body["value"].clone());
+                    object! {type: "write_ok"}
+                }
+                ("cas", Some(value)) if *value != body["from"] => {
+                    object! {type: "error", code: 22, text: format!("expected {} but had {}", body["from"], value)}
+                }
+                ("cas", current) if current.is_some() || body["create_if_not_exists"] == true => {
+                    values.insert(key, body["to"].clone());
+                    object! {type: "cas_ok"}
+                }
+                _ => object! {type: "error", code: 20, text: "key does not exist"},
+            }
+        }
+    }
+
+    #[test]
+    fn reads_what_was_written_and_reports_missing_keys() {
+        let state = node(kv());
+        let client = KvClient::lin_kv(&state);
+
+        assert_eq!(client.read::<i32>(1).unwrap_err().error_code(), ErrorCode::KeyDoesNotExist);
+        client.write(1, vec![3, 4]).unwrap();
+        assert_eq!(client.read::<Vec<i32>>(1).unwrap(), vec![3, 4]);
+        assert_eq!(client.read::<String>(1).unwrap_err().error_code(), ErrorCode::MalformedRequest);
+    }
+
+    #[test]
+    fn cas_succeeds_only_from_the_current_value() {
+        let state = node(kv());
+        let client = KvClient::lin_kv(&state);
+
+        assert_eq!(client.cas("x", 1, 2, false).unwrap_err().error_code(), ErrorCode::KeyDoesNotExist);
+        client.cas("x", 1, 2, true).unwrap();
+        assert_eq!(client.cas("x", 1, 3, false).unwrap_err().error_code(), ErrorCode::PreconditionFailed);
+        client.cas("x", 2, 3, false).unwrap();
+        assert_eq!(client.read::<i32>("x").unwrap(), 3);
+    }
+
+    #[test]
+    fn does_not_resend_a_cas_that_may_have_been_applied() {
+        let mut requests = 0;
+        let state = node(move |_| {
+            requests += 1;
+            object! {type: "error", code: 13, text: format!("crashed on request {}", requests)}
+        });
+        let retry = RetryPolicy::init(LIN_KV).with_backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let client = KvClient::lin_kv(&state).with_retry(retry);
+
+        let error = client.cas("x", 1, 2, false).unwrap_err();
+        assert_eq!(error.error_code(), ErrorCode::Crash);
+        assert!(error.text.ends_with("request 1"));
+
+        let error = client.write("x", 2).unwrap_err();
+        assert!(error.text.ends_with(&format!("request {}", 1 + crate::retry::DEFAULT_MAX_ATTEMPTS)));
+    }
+
+    #[test]
+    fn rejects_replies_of_the_wrong_type() {
+        let state = node(|_| object! {type: "write_ok"});
+        let error = KvClient::seq_kv(&state).read::<i32>(1).unwrap_err();
+        assert_eq!(error.error_code(), ErrorCode::MalformedRequest);
+        assert!(error.text.starts_with("Expected a read_ok reply"));
+    }
+
+    #[test]
+    fn timestamps_increase() {
+        let mut next = 0;
+        let state = node(move |body| {
+            assert_eq!(body["type"], "ts");
+            next += 1;
+            object! {type: "ts_ok", ts: next}
+        });
+        let tso = TsoClient::init(&state);
+        let first = tso.ts().unwrap();
+        let second = tso.ts().unwrap();
+        assert!(first < second);
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use json::JsonValue;
+
+/// A directed neighbor graph as sent in Maelstrom's `topology` message. Neighbors keep
+/// the order they were given in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Topology {
+    graph: BTreeMap<String, Vec<String>>,
+}
+
+impl Topology {
+    pub fn init() -> Topology {
+        Topology::default()
+    }
+
+    pub fn from_graph<I>(graph: I) -> Topology
+        where I: IntoIterator<Item = (String, Vec<String>)>
+    {
+        Topology {
+            graph: graph.into_iter().collect(),
+        }
+    }
+
+    pub fn from_json(json: &JsonValue) -> Option<Topology> {
+        if !json.is_object() {
+            return None;
+        }
+        let mut graph = BTreeMap::new();
+        for (node, neighbors) in json.entries() {
+            let neighbors = neighbors
+                .members()
+                .map(|id| id.as_str().map(str::to_string))
+                .collect::<Option<Vec<String>>>()?;
+            graph.insert(node.to_string(), neighbors);
+        }
+        Some(Topology { graph })
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut json = JsonValue::new_object();
+        for (node, neighbors) in self.graph.iter() {
+            json[node.as_str()] = JsonValue::from(neighbors.clone());
+        }
+        json
+    }
+
+    /// Every node named in the graph, as a source or a neighbor, in sorted order.
+    pub fn nodes(&self) -> Vec<String> {
+        let mut nodes: BTreeSet<&String> = self.graph.keys().collect();
+        nodes.extend(self.graph.values().flatten());
+        nodes.into_iter().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.graph.is_empty()
+    }
+
+    pub fn neighbors(&self, node: &str) -> Vec<String> {
+        self.graph.get(node).cloned().unwrap_or_default()
+    }
+
+    pub fn is_neighbor(&self, node: &str, other: &str) -> bool {
+        self.graph.get(node).is_some_and(|neighbors| neighbors.iter().any(|n| n == other))
+    }
+
+    /// The shortest path from `from` to `to` following edges, both ends included.
+    pub fn path(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        let mut previous: HashMap<&str, &str> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        previous.insert(from, from);
+        while let Some(node) = queue.pop_front() {
+            if node == to {
+                let mut path = vec![to.to_string()];
+                let mut current = to;
+                while current != from {
+                    current = previous[current];
+                    path.push(current.to_string());
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for neighbor in self.graph.get(node).into_iter().flatten() {
+                if !previous.contains_key(neighbor.as_str()) {
+                    previous.insert(neighbor, node);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn distance(&self, from: &str, to: &str) -> Option<usize> {
+        self.path(from, to).map(|path| path.len() - 1)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        let nodes = self.nodes();
+        match nodes.first() {
+            Some(root) => nodes.iter().all(|node| self.path(root, node).is_some()),
+            None => true,
+        }
+    }
+
+    /// A breadth-first spanning tree of the graph rooted at `root`, with edges both ways.
+    /// Nodes unreachable from `root` are left out.
+    pub fn spanning_tree(&self, root: &str) -> Topology {
+        let mut tree = Topology::init();
+        let mut seen = BTreeSet::from([root.to_string()]);
+        let mut queue = VecDeque::from([root.to_string()]);
+        tree.graph.insert(root.to_string(), Vec::new());
+        while let Some(node) = queue.pop_front() {
+            for neighbor in self.neighbors(&node) {
+                if seen.insert(neighbor.clone()) {
+                    tree.connect(&node, &neighbor);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        tree
+    }
+
+    /// Each node linked to the ones before and after it, wrapping around.
+    pub fn ring(nodes: &[String]) -> Topology {
+        let mut ring = Topology::from_graph(nodes.iter().map(|node| (node.clone(), Vec::new())));
+        if nodes.len() > 1 {
+            for (i, node) in nodes.iter().enumerate() {
+                ring.connect(node, &nodes[(i + 1) % nodes.len()]);
+            }
+        }
+        ring
+    }
+
+    /// A tree where node `i` is the parent of nodes `k*i+1` to `k*i+k`, edges both ways.
+    pub fn tree(nodes: &[String], k: usize) -> Topology {
+        let k = k.max(1);
+        let mut tree = Topology::from_graph(nodes.iter().map(|node| (node.clone(), Vec::new())));
+        for (i, node) in nodes.iter().enumerate().skip(1) {
+            tree.connect(&nodes[(i - 1) / k], node);
+        }
+        tree
+    }
+
+    pub fn full(nodes: &[String]) -> Topology {
+        Topology::from_graph(nodes.iter().map(|node| {
+            (node.clone(), nodes.iter().filter(|other| *other != node).cloned().collect())
+        }))
+    }
+
+    fn connect(&mut self, a: &str, b: &str) {
+        for (from, to) in [(a, b), (b, a)] {
+            let neighbors = self.graph.entry(from.to_string()).or_default();
+            if !neighbors.iter().any(|n| n == to) {
+                neighbors.push(to.to_string());
+            }
+        }
+    }
+}
+
+/// Which graph a node gossips over: the one Maelstrom supplied or one built from its nodes.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum Overlay {
+    #[default]
+    Supplied,
+    SpanningTree,
+    Ring,
+    Tree(usize),
+    Full,
+}
+
+impl Overlay {
+    /// Builds the overlay over `nodes`. Node lists are sorted first so every node derives
+    /// the same graph.
+    pub fn apply(&self, supplied: &Topology, nodes: &[String]) -> Topology {
+        let mut nodes: Vec<String> = nodes.iter().chain(supplied.nodes().iter()).cloned().collect();
+        nodes.sort();
+        nodes.dedup();
+        match self {
+            Overlay::Supplied => supplied.clone(),
+            Overlay::SpanningTree => match nodes.first() {
+                Some(root) if !supplied.is_empty() => supplied.spanning_tree(root),
+                _ => Topology::tree(&nodes, 2),
+            },
+            Overlay::Ring => Topology::ring(&nodes),
+            Overlay::Tree(k) => Topology::tree(&nodes, *k),
+            Overlay::Full => Topology::full(&nodes),
+        }
+    }
+}
+
+/// The supplied topology and the overlay chosen over it, swapped as a whole on change.
+#[derive(Clone, Debug, Default)]
+pub struct Membership {
+    supplied: Topology,
+    overlay: Overlay,
+    effective: Topology,
+}
+
+impl Membership {
+    pub fn init(supplied: Topology, overlay: Overlay, nodes: &[String]) -> Membership {
+        let effective = overlay.apply(&supplied, nodes);
+        Membership { supplied, overlay, effective }
+    }
+
+    pub fn supplied(&self) -> &Topology {
+        &self.supplied
+    }
+
+    pub fn overlay(&self) -> Overlay {
+        self.overlay
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.effective
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::object;
+    use super::*;
+
+    fn ids(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn sorted_neighbors(topology: &Topology, node: &str) -> Vec<String> {
+        let mut neighbors = topology.neighbors(node);
+        neighbors.sort();
+        neighbors
+    }
+
+    #[test]
+    fn ring_links_each_node_to_both_sides() {
+        let ring = Topology::ring(&ids(&["n1", "n2", "n3", "n4"]));
+        assert_eq!(sorted_neighbors(&ring, "n1"), ids(&["n2", "n4"]));
+        assert_eq!(sorted_neighbors(&ring, "n3"), ids(&["n2", "n4"]));
+        assert!(ring.is_connected());
+        assert!(Topology::ring(&ids(&["n1"])).neighbors("n1").is_empty());
+    }
+
+    #[test]
+    fn tree_links_parents_and_children() {
+        let tree = Topology::tree(&ids(&["n1", "n2", "n3", "n4", "n5"]), 2);
+        assert_eq!(sorted_neighbors(&tree, "n1"), ids(&["n2", "n3"]));
+        assert_eq!(sorted_neighbors(&tree, "n2"), ids(&["n1", "n4", "n5"]));
+        assert_eq!(sorted_neighbors(&tree, "n5"), ids(&["n2"]));
+        assert_eq!(tree.distance("n4", "n3"), Some(3));
+    }
+
+    #[test]
+    fn spanning_tree_keeps_one_path_to_each_node() {
+        let grid = Topology::from_json(&object! {
+            n1: ["n2", "n3"], n2: ["n1", "n4"], n3: ["n1", "n4"], n4: ["n2", "n3"],
+        }).unwrap();
+        let tree = grid.spanning_tree("n1");
+        assert_eq!(sorted_neighbors(&tree, "n1"), ids(&["n2", "n3"]));
+        assert_eq!(sorted_neighbors(&tree, "n2"), ids(&["n1", "n4"]));
+        assert_eq!(sorted_neighbors(&tree, "n3"), ids(&["n1"]));
+        assert_eq!(sorted_neighbors(&tree, "n4"), ids(&["n2"]));
+        assert_eq!(Topology::from_json(&tree.to_json()), Some(tree));
+    }
+
+    #[test]
+    fn overlays_ignore_node_order() {
+        let supplied = Topology::from_json(&object! {n1: ["n2"], n2: ["n1"]}).unwrap();
+        let a = Overlay::Ring.apply(&supplied, &ids(&["n3", "n1"]));
+        let b = Overlay::Ring.apply(&supplied, &ids(&["n1", "n2", "n3"]));
+        assert_eq!(a, b);
+        assert_eq!(Overlay::Supplied.apply(&supplied, &ids(&["n3"])), supplied);
+    }
+}
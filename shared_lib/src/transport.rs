@@ -0,0 +1,129 @@
+use std::io::{self, BufRead, ErrorKind, Write};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use crate::logging;
+
+pub trait Transport: Send + Sync {
+    fn read_line(&self) -> Option<io::Result<String>>;
+
+    fn write_line(&self, line: &str) -> io::Result<()>;
+}
+
+pub struct StdioTransport {}
+
+impl Transport for StdioTransport {
+    fn read_line(&self) -> Option<io::Result<String>> {
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(trim_newline(line))),
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(line.as_bytes())?;
+        stdout.write_all(b"\n")?;
+        stdout.flush()
+    }
+}
+
+pub struct ChannelTransport {
+    incoming: Mutex<Receiver<String>>,
+    outgoing: Sender<String>,
+}
+
+impl ChannelTransport {
+    pub fn init(incoming: Receiver<String>, outgoing: Sender<String>) -> ChannelTransport {
+        ChannelTransport {
+            incoming: Mutex::new(incoming),
+            outgoing,
+        }
+    }
+
+    pub fn pair() -> (ChannelTransport, ChannelTransport) {
+        let (a_sender, a_receiver) = channel();
+        let (b_sender, b_receiver) = channel();
+        (ChannelTransport::init(a_receiver, b_sender), ChannelTransport::init(b_receiver, a_sender))
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn read_line(&self) -> Option<io::Result<String>> {
+        self.incoming.lock().unwrap().recv().ok().map(Ok)
+    }
+
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        self.outgoing
+            .send(line.to_string())
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "channel transport closed"))
+    }
+}
+
+pub fn while_send(receiver: Receiver<String>, transport: &dyn Transport) {
+    while let Ok(msg) = receiver.recv() {
+        logging::debug("transport").log(&format!("Replying: {}", msg));
+        if let Err(err) = transport.write_line(&msg) {
+            logging::error("transport").log(&format!("Failed to send {}: {}", msg, err));
+        }
+    }
+}
+
+fn trim_newline(mut line: String) -> String {
+    while line.ends_with('\n') || line.ends_with('\r') {
+        line.pop();
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+    use std::thread;
+    use super::*;
+
+    #[test]
+    fn a_pair_delivers_lines_both_ways_in_order() {
+        let (node, client) = ChannelTransport::pair();
+        client.write_line("one").unwrap();
+        client.write_line("two").unwrap();
+        node.write_line("reply").unwrap();
+
+        assert_eq!(node.read_line().unwrap().unwrap(), "one");
+        assert_eq!(node.read_line().unwrap().unwrap(), "two");
+        assert_eq!(client.read_line().unwrap().unwrap(), "reply");
+    }
+
+    #[test]
+    fn dropping_one_end_closes_the_other() {
+        let (node, client) = ChannelTransport::pair();
+        client.write_line("last").unwrap();
+        drop(client);
+
+        assert_eq!(node.read_line().unwrap().unwrap(), "last");
+        assert!(node.read_line().is_none());
+        assert_eq!(node.write_line("lost").unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn while_send_forwards_until_the_sender_is_dropped() {
+        let (node, client) = ChannelTransport::pair();
+        let (sender, receiver) = sync_channel(4);
+        let forwarder = thread::spawn(move || while_send(receiver, &node));
+        sender.send("a".to_string()).unwrap();
+        sender.send("b".to_string()).unwrap();
+        drop(sender);
+        forwarder.join().unwrap();
+
+        assert_eq!(client.read_line().unwrap().unwrap(), "a");
+        assert_eq!(client.read_line().unwrap().unwrap(), "b");
+        assert!(client.read_line().is_none());
+    }
+
+    #[test]
+    fn trims_line_endings() {
+        assert_eq!(trim_newline("msg\r\n".to_string()), "msg");
+        assert_eq!(trim_newline("msg".to_string()), "msg");
+    }
+}
@@ -0,0 +1,213 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::logging;
+
+pub const DEFAULT_WORKERS: usize = 16;
+pub const DEFAULT_QUEUE_SIZE: usize = 256;
+
+pub type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs the jobs `dispatch` hands out for each message.
+pub trait Executor {
+    fn execute(&self, job: Job) -> Result<(), Saturated>;
+
+    /// Another way in to the same workers, for a job that hands out jobs of its own.
+    fn handle(&self) -> Box<dyn Executor + Send>;
+}
+
+/// What `WorkerPool::execute` does when every worker is busy and the queue is full.
+/// `Block` stalls the caller, which for the read loop also stalls delivery of RPC
+/// replies, so handlers that wait on RPCs should use `Reject`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Backpressure {
+    Reject,
+    Block,
+}
+
+#[derive(Debug)]
+pub struct Saturated;
+
+/// Queues jobs for a pool's workers, which keep running while any handle is alive.
+#[derive(Clone)]
+struct PoolHandle {
+    sender: SyncSender<Job>,
+    backpressure: Backpressure,
+}
+
+impl Executor for PoolHandle {
+    fn execute(&self, job: Job) -> Result<(), Saturated> {
+        match self.backpressure {
+            Backpressure::Block => self.sender.send(job).map_err(|_| Saturated),
+            Backpressure::Reject => match self.sender.try_send(job) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => Err(Saturated),
+            },
+        }
+    }
+
+    fn handle(&self) -> Box<dyn Executor + Send> {
+        Box::new(self.clone())
+    }
+}
+
+pub struct WorkerPool {
+    handle: PoolHandle,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub fn init(size: usize, queue_size: usize, backpressure: Backpressure) -> WorkerPool {
+        let (sender, receiver) = sync_channel::<Job>(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..size.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || work(receiver))
+            })
+            .collect();
+        WorkerPool {
+            handle: PoolHandle { sender, backpressure },
+            workers,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn execute<F>(&self, job: F) -> Result<(), Saturated>
+        where F: FnOnce() + Send + 'static
+    {
+        self.handle.execute(Box::new(job))
+    }
+}
+
+impl Executor for WorkerPool {
+    fn execute(&self, job: Job) -> Result<(), Saturated> {
+        WorkerPool::execute(self, job)
+    }
+
+    fn handle(&self) -> Box<dyn Executor + Send> {
+        self.handle.handle()
+    }
+}
+
+impl WorkerPool {
+    /// Stops taking jobs and waits for queued and running ones to finish. Returns false if
+    /// some were still running at the deadline; those threads are left behind.
+    pub fn shutdown(self, deadline: Instant) -> bool {
+        let WorkerPool { handle, workers } = self;
+        drop(handle);
+        while workers.iter().any(|worker| !worker.is_finished()) {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        workers.into_iter().for_each(|worker| {
+            let _ = worker.join();
+        });
+        true
+    }
+}
+
+impl Default for WorkerPool {
+    fn default() -> Self {
+        WorkerPool::init(DEFAULT_WORKERS, DEFAULT_QUEUE_SIZE, Backpressure::Reject)
+    }
+}
+
+fn work(receiver: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = receiver.lock().unwrap().recv();
+        match job {
+            Ok(job) => {
+                if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                    logging::error("worker_pool").log("Worker recovered from a panicking job");
+                }
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{channel, Sender};
+    use super::*;
+
+    /// Occupies the pool's only worker until the returned sender is dropped.
+    fn occupy(pool: &WorkerPool) -> Sender<()> {
+        let (started, running) = channel();
+        let (release, gate) = channel::<()>();
+        pool.execute(move || {
+            started.send(()).unwrap();
+            let _ = gate.recv();
+        }).unwrap();
+        running.recv().unwrap();
+        release
+    }
+
+    #[test]
+    fn rejects_jobs_once_the_queue_is_full() {
+        let pool = WorkerPool::init(1, 1, Backpressure::Reject);
+        let release = occupy(&pool);
+        assert!(pool.execute(|| {}).is_ok());
+        assert!(pool.execute(|| {}).is_err());
+
+        drop(release);
+        assert!(pool.shutdown(Instant::now() + Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn blocks_the_caller_until_the_queue_has_room() {
+        let pool = Arc::new(WorkerPool::init(1, 1, Backpressure::Block));
+        let release = occupy(&pool);
+        pool.execute(|| {}).unwrap();
+        let caller = {
+            let pool = pool.clone();
+            thread::spawn(move || pool.execute(|| {}).is_ok())
+        };
+        thread::sleep(Duration::from_millis(20));
+        assert!(!caller.is_finished());
+
+        drop(release);
+        assert!(caller.join().unwrap());
+    }
+
+    #[test]
+    fn keeps_working_after_a_job_panics() {
+        let pool = WorkerPool::init(1, 4, Backpressure::Reject);
+        let (done, finished) = channel();
+        pool.execute(|| panic!("job failed")).unwrap();
+        pool.execute(move || done.send(()).unwrap()).unwrap();
+        assert!(finished.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn shutdown_drains_queued_jobs_within_the_deadline() {
+        let pool = WorkerPool::init(2, 8, Backpressure::Reject);
+        let count = Arc::new(AtomicUsize::new(0));
+        for _ in 0..6 {
+            let count = count.clone();
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(5));
+                count.fetch_add(1, Ordering::SeqCst);
+            }).unwrap();
+        }
+        assert!(pool.shutdown(Instant::now() + Duration::from_secs(5)));
+        assert_eq!(count.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn shutdown_gives_up_on_jobs_running_past_the_deadline() {
+        let pool = WorkerPool::init(1, 1, Backpressure::Reject);
+        let release = occupy(&pool);
+        assert!(!pool.shutdown(Instant::now() + Duration::from_millis(20)));
+        drop(release);
+    }
+}
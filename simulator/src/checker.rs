@@ -0,0 +1,3 @@
+pub mod history;
+pub mod linearizability;
+pub mod list_append;
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use json::{object, JsonValue};
+use shared_lib::error::ErrorCode;
+use shared_lib::recording::Entry;
+
+use crate::simulation::Delivery;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OpType {
+    Invoke,
+    Ok,
+    Fail,
+    Info,
+}
+
+impl OpType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OpType::Invoke => "invoke",
+            OpType::Ok => "ok",
+            OpType::Fail => "fail",
+            OpType::Info => "info",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<OpType> {
+        match text {
+            "invoke" => Some(OpType::Invoke),
+            "ok" => Some(OpType::Ok),
+            "fail" => Some(OpType::Fail),
+            "info" => Some(OpType::Info),
+            _ => None,
+        }
+    }
+}
+
+/// One event in a Jepsen-style history. `value` holds what the client asked for on an
+/// invocation and what it observed on completion: the value read, `[from, to]` for a
+/// cas, or the list of micro-ops for a txn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Op {
+    pub index: usize,
+    pub time: u64,
+    pub process: String,
+    pub op_type: OpType,
+    pub f: String,
+    pub key: JsonValue,
+    pub value: JsonValue,
+}
+
+impl Op {
+    pub fn from_json(json: &JsonValue) -> Option<Op> {
+        Some(Op {
+            index: json["index"].as_usize()?,
+            time: json["time"].as_u64().unwrap_or_default(),
+            process: json["process"].as_str().map(str::to_string).unwrap_or_else(|| json["process"].dump()),
+            op_type: OpType::parse(json["type"].as_str()?)?,
+            f: json["f"].as_str()?.to_string(),
+            key: json["key"].clone(),
+            value: json["value"].clone(),
+        })
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        object! {
+            index: self.index,
+            time: self.time,
+            process: self.process.clone(),
+            type: self.op_type.as_str(),
+            f: self.f.clone(),
+            key: self.key.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+/// An invocation joined with how it completed. Operations that never completed are
+/// `Info` with no completion index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operation {
+    pub process: String,
+    pub f: String,
+    pub key: JsonValue,
+    pub op_type: OpType,
+    pub invoke: usize,
+    pub complete: Option<usize>,
+    pub value: JsonValue,
+}
+
+/// The `f`, key and value of a request still waiting for its reply.
+type Invocation = (String, JsonValue, JsonValue);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    ops: Vec<Op>,
+}
+
+impl History {
+    pub fn init() -> History {
+        History::default()
+    }
+
+    pub fn push(&mut self, time: u64, process: &str, op_type: OpType, f: &str, key: JsonValue, value: JsonValue) -> usize {
+        let index = self.ops.len();
+        self.ops.push(Op { index, time, process: process.to_string(), op_type, f: f.to_string(), key, value });
+        index
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    pub fn from_json(json: &JsonValue) -> Option<History> {
+        let ops = json.members().map(Op::from_json).collect::<Option<Vec<Op>>>()?;
+        Some(History { ops })
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        JsonValue::from(self.ops.iter().map(Op::to_json).collect::<Vec<JsonValue>>())
+    }
+
+    /// Builds a history from the messages between clients and nodes, in delivery order:
+    /// requests are invocations, `*_ok` replies succeed, definite errors fail and
+    /// indefinite errors or missing replies are `info`. Anything else is ignored.
+    pub fn from_messages<I>(messages: I) -> History
+        where I: IntoIterator<Item = (u64, JsonValue)>
+    {
+        let mut history = History::init();
+        let mut pending: HashMap<(String, i32), Invocation> = HashMap::new();
+        let mut last_time = 0;
+        for (time, message) in messages {
+            last_time = time;
+            let body = &message["body"];
+            let src = message["src"].as_str().unwrap_or_default();
+            let dest = message["dest"].as_str().unwrap_or_default();
+            if is_client(src) && !is_client(dest) {
+                if let (Some(f), Some(msg_id)) = (body["type"].as_str(), body["msg_id"].as_i32()) {
+                    let value = invoke_value(f, body);
+                    history.push(time, src, OpType::Invoke, f, body["key"].clone(), value.clone());
+                    pending.insert((src.to_string(), msg_id), (f.to_string(), body["key"].clone(), value));
+                }
+            } else if is_client(dest) {
+                let invocation = body["in_reply_to"].as_i32().and_then(|id| pending.remove(&(dest.to_string(), id)));
+                if let Some((f, key, value)) = invocation {
+                    let (op_type, value) = completion(&f, body, value);
+                    history.push(time, dest, op_type, &f, key, value);
+                }
+            }
+        }
+        let mut unanswered: Vec<((String, i32), Invocation)> = pending.into_iter().collect();
+        unanswered.sort_by(|a, b| a.0.cmp(&b.0));
+        for ((process, _), (f, key, value)) in unanswered {
+            history.push(last_time, &process, OpType::Info, &f, key, value);
+        }
+        history
+    }
+
+    pub fn from_trace(trace: &[Delivery]) -> History {
+        History::from_messages(trace.iter().map(|delivery| (delivery.time, delivery.message.clone())))
+    }
+
+    /// From a node's recording; only the node's own clients appear in it.
+    pub fn from_recording(entries: &[Entry]) -> History {
+        History::from_messages(entries.iter().map(|entry| (entry.ts, entry.message.clone())))
+    }
+
+    /// Pairs each invocation with the next completion from the same process.
+    pub fn operations(&self) -> Vec<Operation> {
+        let mut open: HashMap<&str, usize> = HashMap::new();
+        let mut operations: Vec<Operation> = Vec::new();
+        for op in self.ops.iter() {
+            match op.op_type {
+                OpType::Invoke => {
+                    open.insert(&op.process, operations.len());
+                    operations.push(Operation {
+                        process: op.process.clone(),
+                        f: op.f.clone(),
+                        key: op.key.clone(),
+                        op_type: OpType::Info,
+                        invoke: op.index,
+                        complete: None,
+                        value: op.value.clone(),
+                    });
+                }
+                completion => {
+                    if let Some(i) = open.remove(op.process.as_str()) {
+                        let operation = &mut operations[i];
+                        operation.op_type = completion;
+                        if completion != OpType::Info {
+                            operation.complete = Some(op.index);
+                        }
+                        if completion == OpType::Ok {
+                            operation.value = op.value.clone();
+                        }
+                    }
+                }
+            }
+        }
+        operations
+    }
+}
+
+/// Maelstrom names clients `c1`, `c2`, ... and nodes `n1`, `n2`, ...
+pub fn is_client(id: &str) -> bool {
+    id.strip_prefix('c').is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn invoke_value(f: &str, body: &JsonValue) -> JsonValue {
+    match f {
+        "read" => JsonValue::Null,
+        "cas" => JsonValue::from(vec![body["from"].clone(), body["to"].clone()]),
+        "txn" => body["txn"].clone(),
+        _ => body["value"].clone(),
+    }
+}
+
+fn completion(f: &str, body: &JsonValue, invoked: JsonValue) -> (OpType, JsonValue) {
+    if body["type"] == "error" {
+        let code = ErrorCode::from_code(body["code"].as_i32().unwrap_or(ErrorCode::Crash.code()));
+        let op_type = if code.is_definite() { OpType::Fail } else { OpType::Info };
+        return (op_type, invoked);
+    }
+    match f {
+        "read" => (OpType::Ok, body["value"].clone()),
+        "txn" => (OpType::Ok, body["txn"].clone()),
+        _ => (OpType::Ok, invoked),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_replies_by_whether_the_error_is_definite() {
+        let messages = vec![
+            (1, object! {src: "c1", dest: "n1", body: {type: "read", key: 1, msg_id: 1}}),
+            (2, object! {src: "c2", dest: "n1", body: {type: "write", key: 1, value: 5, msg_id: 1}}),
+            (3, object! {src: "c3", dest: "n1", body: {type: "cas", key: 1, from: 5, to: 6, msg_id: 1}}),
+            (4, object! {src: "n1", dest: "n2", body: {type: "gossip", msg_id: 2}}),
+            (5, object! {src: "n1", dest: "c1", body: {type: "error", code: 20, in_reply_to: 1}}),
+            (6, object! {src: "n1", dest: "c2", body: {type: "error", code: 0, in_reply_to: 1}}),
+        ];
+        let history = History::from_messages(messages);
+        let events: Vec<(&str, OpType)> = history.ops().iter().map(|op| (op.process.as_str(), op.op_type)).collect();
+        assert_eq!(events, vec![
+            ("c1", OpType::Invoke),
+            ("c2", OpType::Invoke),
+            ("c3", OpType::Invoke),
+            ("c1", OpType::Fail),
+            ("c2", OpType::Info),
+            ("c3", OpType::Info),
+        ]);
+
+        let operations = history.operations();
+        assert_eq!(operations[0].complete, Some(3));
+        assert_eq!(operations[1].complete, None);
+        assert_eq!(operations[2].value, JsonValue::from(vec![5, 6]));
+        assert_eq!(History::from_json(&history.to_json()), Some(history));
+    }
+
+    #[test]
+    fn takes_read_values_from_the_reply() {
+        let messages = vec![
+            (1, object! {src: "c1", dest: "n1", body: {type: "read", key: 1, msg_id: 1}}),
+            (2, object! {src: "n1", dest: "c1", body: {type: "read_ok", value: 4, in_reply_to: 1}}),
+        ];
+        let operations = History::from_messages(messages).operations();
+        assert_eq!(operations.len(), 1);
+        assert_eq!(operations[0].op_type, OpType::Ok);
+        assert_eq!(operations[0].value, JsonValue::from(4));
+    }
+}
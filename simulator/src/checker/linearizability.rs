@@ -0,0 +1,429 @@
+use std::collections::{BTreeMap, HashSet};
+use json::{object, JsonValue};
+
+use crate::checker::history::{History, OpType, Operation};
+
+pub const DEFAULT_MAX_STATES: usize = 1_000_000;
+
+/// The register's value as dumped JSON; `None` until the first write.
+type Register = Option<String>;
+
+/// An operation that has to be placed somewhere between its call and its return. `ret` is
+/// `None` for operations that may or may not have taken effect.
+struct Entry {
+    call: usize,
+    ret: Option<usize>,
+    operation: Operation,
+}
+
+impl Entry {
+    /// The value this operation leaves in the register, if it writes one.
+    fn produces(&self) -> Option<String> {
+        match self.operation.f.as_str() {
+            "write" => Some(self.operation.value.dump()),
+            "cas" => Some(self.operation.value[1].dump()),
+            _ => None,
+        }
+    }
+
+    /// The value this operation needs to find in the register.
+    fn consumes(&self) -> Option<String> {
+        match self.operation.f.as_str() {
+            "read" if !self.operation.value.is_null() => Some(self.operation.value.dump()),
+            "cas" => Some(self.operation.value[0].dump()),
+            _ => None,
+        }
+    }
+
+    fn step(&self, state: &Register) -> Option<Register> {
+        let value = &self.operation.value;
+        match self.operation.f.as_str() {
+            "read" => {
+                let observed = if value.is_null() { None } else { Some(value.dump()) };
+                (observed == *state).then(|| state.clone())
+            }
+            "write" => Some(Some(value.dump())),
+            "cas" => (Some(value[0].dump()) == *state).then(|| Some(value[1].dump())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Counterexample {
+    /// A subset of the key's operations that is still not linearizable, but would be with
+    /// any one of them removed other than the only writer of a value that is read.
+    pub ops: Vec<Operation>,
+    /// The longest order of those operations the search could justify.
+    pub linearized: Vec<Operation>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Linearizable,
+    NotLinearizable(Counterexample),
+    /// The search gave up after exploring the state budget.
+    Unknown,
+}
+
+impl Outcome {
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            Outcome::Linearizable => object! { valid: true },
+            Outcome::Unknown => object! { valid: "unknown" },
+            Outcome::NotLinearizable(counterexample) => object! {
+                valid: false,
+                ops: counterexample.ops.iter().map(operation_json).collect::<Vec<JsonValue>>(),
+                linearized: counterexample.linearized.iter().map(operation_json).collect::<Vec<JsonValue>>(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub keys: BTreeMap<String, Outcome>,
+}
+
+impl Report {
+    pub fn is_linearizable(&self) -> bool {
+        self.keys.values().all(|outcome| *outcome == Outcome::Linearizable)
+    }
+
+    pub fn failures(&self) -> Vec<(&String, &Counterexample)> {
+        self.keys
+            .iter()
+            .filter_map(|(key, outcome)| match outcome {
+                Outcome::NotLinearizable(counterexample) => Some((key, counterexample)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut keys = JsonValue::new_object();
+        for (key, outcome) in self.keys.iter() {
+            keys[key.as_str()] = outcome.to_json();
+        }
+        object! { valid: self.is_linearizable(), keys: keys }
+    }
+}
+
+/// Checks read/write/cas histories against a single register per key, searching for a
+/// linearization the way Wing & Gong (with Lowe's memoization) do.
+pub struct LinearizabilityChecker {
+    max_states: usize,
+}
+
+impl LinearizabilityChecker {
+    pub fn init() -> LinearizabilityChecker {
+        LinearizabilityChecker { max_states: DEFAULT_MAX_STATES }
+    }
+
+    pub fn with_max_states(mut self, max_states: usize) -> LinearizabilityChecker {
+        self.max_states = max_states;
+        self
+    }
+
+    pub fn check(&self, history: &History) -> Report {
+        let mut by_key: BTreeMap<String, Vec<Entry>> = BTreeMap::new();
+        for operation in history.operations() {
+            if let Some(entry) = entry(operation) {
+                by_key.entry(entry.operation.key.dump()).or_default().push(entry);
+            }
+        }
+        let keys = by_key
+            .into_iter()
+            .map(|(key, entries)| (key, self.check_key(entries)))
+            .collect();
+        Report { keys }
+    }
+
+    fn check_key(&self, entries: Vec<Entry>) -> Outcome {
+        match self.search(&entries) {
+            Search::Found => Outcome::Linearizable,
+            Search::Exhausted(_) => Outcome::NotLinearizable(self.minimize(entries)),
+            Search::GaveUp => Outcome::Unknown,
+        }
+    }
+
+    /// Drops operations one at a time for as long as what is left still fails. The last
+    /// writer of a value someone observed stays, or every counterexample would shrink to a
+    /// lone read of a value nobody wrote. Dropping that reader can free its writer, so
+    /// passes repeat until one removes nothing.
+    fn minimize(&self, mut entries: Vec<Entry>) -> Counterexample {
+        let mut shrunk = true;
+        while shrunk {
+            shrunk = false;
+            let mut i = 0;
+            while i < entries.len() {
+                if is_only_source(&entries, i) {
+                    i += 1;
+                    continue;
+                }
+                let removed = entries.remove(i);
+                match self.search(&entries) {
+                    Search::Exhausted(_) => shrunk = true,
+                    _ => {
+                        entries.insert(i, removed);
+                        i += 1;
+                    }
+                }
+            }
+        }
+        let linearized = match self.search(&entries) {
+            Search::Exhausted(longest) => longest.into_iter().map(|i| entries[i].operation.clone()).collect(),
+            _ => Vec::new(),
+        };
+        Counterexample {
+            ops: entries.into_iter().map(|entry| entry.operation).collect(),
+            linearized,
+        }
+    }
+
+    fn search(&self, entries: &[Entry]) -> Search {
+        let required = entries.iter().filter(|entry| entry.ret.is_some()).count();
+        if required == 0 {
+            return Search::Found;
+        }
+        let mut done = vec![false; entries.len()];
+        let mut path: Vec<usize> = Vec::new();
+        let mut states: Vec<Register> = vec![None];
+        let mut longest: Vec<usize> = Vec::new();
+        let mut seen: HashSet<(Vec<u64>, Register)> = HashSet::new();
+        let mut stack: Vec<(Vec<usize>, usize)> = vec![(candidates(entries, &done), 0)];
+        let mut linearized_required = 0;
+        while let Some((pending, next)) = stack.last_mut() {
+            if *next == pending.len() {
+                stack.pop();
+                if let Some(i) = path.pop() {
+                    done[i] = false;
+                    states.pop();
+                    if entries[i].ret.is_some() {
+                        linearized_required -= 1;
+                    }
+                }
+                continue;
+            }
+            let i = pending[*next];
+            *next += 1;
+            let state = match entries[i].step(states.last().unwrap()) {
+                Some(state) => state,
+                None => continue,
+            };
+            done[i] = true;
+            if !seen.insert((bits(&done), state.clone())) {
+                done[i] = false;
+                continue;
+            }
+            if seen.len() > self.max_states {
+                return Search::GaveUp;
+            }
+            path.push(i);
+            states.push(state);
+            if entries[i].ret.is_some() {
+                linearized_required += 1;
+            }
+            if path.len() > longest.len() {
+                longest = path.clone();
+            }
+            if linearized_required == required {
+                return Search::Found;
+            }
+            stack.push((candidates(entries, &done), 0));
+        }
+        Search::Exhausted(longest)
+    }
+}
+
+impl Default for LinearizabilityChecker {
+    fn default() -> Self {
+        LinearizabilityChecker::init()
+    }
+}
+
+enum Search {
+    Found,
+    /// Holds the longest partial linearization found.
+    Exhausted(Vec<usize>),
+    GaveUp,
+}
+
+/// Failed operations never happened and a read that never returned constrains nothing.
+fn entry(operation: Operation) -> Option<Entry> {
+    let ret = match (operation.op_type, operation.f.as_str()) {
+        (_, f) if !matches!(f, "read" | "write" | "cas") => return None,
+        (OpType::Fail, _) | (OpType::Info, "read") => return None,
+        (OpType::Ok, _) => operation.complete,
+        _ => None,
+    };
+    Some(Entry { call: operation.invoke, ret, operation })
+}
+
+fn is_only_source(entries: &[Entry], i: usize) -> bool {
+    let value = match entries[i].produces() {
+        Some(value) => value,
+        None => return false,
+    };
+    let others = entries.iter().enumerate().filter(|(j, _)| *j != i);
+    let observed = others.clone().any(|(_, entry)| entry.consumes().as_ref() == Some(&value));
+    observed && !others.clone().any(|(_, entry)| entry.produces().as_ref() == Some(&value))
+}
+
+/// The operations that could take effect next: anything called before the earliest
+/// return among those not yet linearized.
+fn candidates(entries: &[Entry], done: &[bool]) -> Vec<usize> {
+    let first_return = entries
+        .iter()
+        .zip(done)
+        .filter(|(_, done)| !**done)
+        .filter_map(|(entry, _)| entry.ret)
+        .min()
+        .unwrap_or(usize::MAX);
+    (0..entries.len())
+        .filter(|i| !done[*i] && entries[*i].call < first_return)
+        .collect()
+}
+
+fn bits(done: &[bool]) -> Vec<u64> {
+    let mut words = vec![0u64; done.len().div_ceil(64)];
+    for (i, _) in done.iter().enumerate().filter(|(_, done)| **done) {
+        words[i / 64] |= 1 << (i % 64);
+    }
+    words
+}
+
+fn operation_json(operation: &Operation) -> JsonValue {
+    object! {
+        process: operation.process.clone(),
+        type: operation.op_type.as_str(),
+        f: operation.f.clone(),
+        key: operation.key.clone(),
+        value: operation.value.clone(),
+        invoke: operation.invoke,
+        complete: operation.complete,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::JsonValue;
+    use super::*;
+
+    const KEY: &str = "1";
+
+    fn history(events: &[(&str, OpType, &str, JsonValue)]) -> History {
+        let mut history = History::init();
+        for (time, (process, op_type, f, value)) in events.iter().enumerate() {
+            history.push(time as u64, process, *op_type, f, JsonValue::from(1), value.clone());
+        }
+        history
+    }
+
+    fn outcome(history: &History) -> Outcome {
+        LinearizabilityChecker::init().check(history).keys[KEY].clone()
+    }
+
+    fn cas(from: i32, to: i32) -> JsonValue {
+        JsonValue::from(vec![from, to])
+    }
+
+    #[test]
+    fn a_read_concurrent_with_a_write_may_see_it() {
+        let history = history(&[
+            ("c1", OpType::Invoke, "write", 1.into()),
+            ("c2", OpType::Invoke, "read", JsonValue::Null),
+            ("c2", OpType::Ok, "read", 1.into()),
+            ("c1", OpType::Ok, "write", 1.into()),
+        ]);
+        assert_eq!(outcome(&history), Outcome::Linearizable);
+        assert!(LinearizabilityChecker::init().check(&history).is_linearizable());
+    }
+
+    #[test]
+    fn a_stale_read_is_not_linearizable() {
+        let history = history(&[
+            ("c1", OpType::Invoke, "write", 1.into()),
+            ("c1", OpType::Ok, "write", 1.into()),
+            ("c1", OpType::Invoke, "write", 2.into()),
+            ("c1", OpType::Ok, "write", 2.into()),
+            ("c2", OpType::Invoke, "read", JsonValue::Null),
+            ("c2", OpType::Ok, "read", 1.into()),
+        ]);
+        let report = LinearizabilityChecker::init().check(&history);
+        assert!(!report.is_linearizable());
+        assert_eq!(report.failures().len(), 1);
+        assert_eq!(report.to_json()["keys"][KEY]["valid"], false);
+    }
+
+    #[test]
+    fn an_info_write_may_have_taken_effect() {
+        let events = |write_type| [
+            ("c1", OpType::Invoke, "write", 1.into()),
+            ("c1", OpType::Ok, "write", 1.into()),
+            ("c2", OpType::Invoke, "write", 2.into()),
+            ("c2", write_type, "write", 2.into()),
+            ("c3", OpType::Invoke, "cas", cas(2, 3)),
+            ("c3", OpType::Ok, "cas", cas(2, 3)),
+            ("c3", OpType::Invoke, "read", JsonValue::Null),
+            ("c3", OpType::Ok, "read", 3.into()),
+        ];
+        assert_eq!(outcome(&history(&events(OpType::Info))), Outcome::Linearizable);
+        assert!(matches!(outcome(&history(&events(OpType::Fail))), Outcome::NotLinearizable(_)));
+    }
+
+    #[test]
+    fn gives_up_past_the_state_budget() {
+        let history = history(&[
+            ("c1", OpType::Invoke, "write", 1.into()),
+            ("c2", OpType::Invoke, "write", 2.into()),
+            ("c3", OpType::Invoke, "write", 3.into()),
+            ("c1", OpType::Ok, "write", 1.into()),
+            ("c2", OpType::Ok, "write", 2.into()),
+            ("c3", OpType::Ok, "write", 3.into()),
+        ]);
+        let report = LinearizabilityChecker::init().with_max_states(1).check(&history);
+        assert_eq!(report.keys[KEY], Outcome::Unknown);
+        assert!(!report.is_linearizable());
+        assert!(report.failures().is_empty());
+        assert_eq!(outcome(&history), Outcome::Linearizable);
+    }
+
+    #[test]
+    fn the_counterexample_drops_operations_that_do_not_matter() {
+        let history = history(&[
+            ("c1", OpType::Invoke, "write", 0.into()),
+            ("c1", OpType::Ok, "write", 0.into()),
+            ("c2", OpType::Invoke, "read", JsonValue::Null),
+            ("c2", OpType::Ok, "read", 0.into()),
+            ("c1", OpType::Invoke, "write", 1.into()),
+            ("c1", OpType::Ok, "write", 1.into()),
+            ("c1", OpType::Invoke, "write", 2.into()),
+            ("c1", OpType::Ok, "write", 2.into()),
+            ("c3", OpType::Invoke, "write", 9.into()),
+            ("c3", OpType::Fail, "write", 9.into()),
+            ("c2", OpType::Invoke, "read", JsonValue::Null),
+            ("c2", OpType::Ok, "read", 1.into()),
+            ("c1", OpType::Invoke, "cas", cas(2, 3)),
+            ("c1", OpType::Ok, "cas", cas(2, 3)),
+        ]);
+        let counterexample = match outcome(&history) {
+            Outcome::NotLinearizable(counterexample) => counterexample,
+            other => panic!("expected a counterexample, got {:?}", other),
+        };
+        let ops: Vec<(&str, String)> = counterexample.ops.iter().map(|op| (op.f.as_str(), op.value.dump())).collect();
+        assert_eq!(ops, vec![("write", "1".to_string()), ("write", "2".to_string()), ("read", "1".to_string())]);
+        assert_eq!(counterexample.linearized.len(), 2);
+
+        // The write of 1 stays even though the read of 1 alone would also fail.
+        let checker = LinearizabilityChecker::init();
+        for skipped in 1..counterexample.ops.len() {
+            let mut reduced = History::init();
+            for (i, op) in counterexample.ops.iter().enumerate().filter(|(i, _)| *i != skipped) {
+                reduced.push(i as u64, &op.process, OpType::Invoke, &op.f, op.key.clone(), op.value.clone());
+                reduced.push(i as u64, &op.process, OpType::Ok, &op.f, op.key.clone(), op.value.clone());
+            }
+            assert!(checker.check(&reduced).is_linearizable(), "still fails without op {}", skipped);
+        }
+    }
+}
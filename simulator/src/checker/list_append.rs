@@ -0,0 +1,623 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use json::{object, JsonValue};
+
+use crate::checker::history::{History, OpType, Operation};
+
+const WW: u8 = 1;
+const WR: u8 = 2;
+const RW: u8 = 4;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Dependency {
+    /// The second transaction appended after the first on some key.
+    WriteWrite,
+    /// The second transaction read what the first appended.
+    WriteRead,
+    /// The second transaction appended over a state the first read.
+    ReadWrite,
+}
+
+impl Dependency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dependency::WriteWrite => "ww",
+            Dependency::WriteRead => "wr",
+            Dependency::ReadWrite => "rw",
+        }
+    }
+
+    fn bit(&self) -> u8 {
+        match self {
+            Dependency::WriteWrite => WW,
+            Dependency::WriteRead => WR,
+            Dependency::ReadWrite => RW,
+        }
+    }
+}
+
+/// Isolation levels from weakest to strongest, following Adya.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+    SnapshotIsolation,
+    Serializable,
+}
+
+impl IsolationLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IsolationLevel::ReadUncommitted => "read-uncommitted",
+            IsolationLevel::ReadCommitted => "read-committed",
+            IsolationLevel::SnapshotIsolation => "snapshot-isolation",
+            IsolationLevel::Serializable => "serializable",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum AnomalyKind {
+    /// Two reads of a key that are not prefixes of one another.
+    IncompatibleOrder,
+    /// A cycle of write-write dependencies.
+    G0,
+    /// A read of a value appended by a transaction that failed.
+    G1a,
+    /// A read of a value its transaction later appended over.
+    G1b,
+    /// A cycle of write-write and write-read dependencies.
+    G1c,
+    /// A cycle with exactly one read-write dependency.
+    GSingle,
+    /// A cycle with more than one read-write dependency.
+    G2,
+}
+
+impl AnomalyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnomalyKind::IncompatibleOrder => "incompatible-order",
+            AnomalyKind::G0 => "G0",
+            AnomalyKind::G1a => "G1a",
+            AnomalyKind::G1b => "G1b",
+            AnomalyKind::G1c => "G1c",
+            AnomalyKind::GSingle => "G-single",
+            AnomalyKind::G2 => "G2",
+        }
+    }
+
+    /// The weakest isolation level that rules this anomaly out.
+    pub fn violates(&self) -> IsolationLevel {
+        match self {
+            AnomalyKind::IncompatibleOrder | AnomalyKind::G0 => IsolationLevel::ReadUncommitted,
+            AnomalyKind::G1a | AnomalyKind::G1b | AnomalyKind::G1c => IsolationLevel::ReadCommitted,
+            AnomalyKind::GSingle => IsolationLevel::SnapshotIsolation,
+            AnomalyKind::G2 => IsolationLevel::Serializable,
+        }
+    }
+}
+
+/// For a cycle, `dependencies[i]` leads from `ops[i]` to the next op, wrapping around.
+/// Other anomalies list the writer first and the reader after it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub key: Option<String>,
+    pub ops: Vec<Operation>,
+    pub dependencies: Vec<Dependency>,
+}
+
+impl Anomaly {
+    pub fn to_json(&self) -> JsonValue {
+        object! {
+            type: self.kind.as_str(),
+            key: self.key.clone(),
+            ops: self.ops.iter().map(txn_json).collect::<Vec<JsonValue>>(),
+            dependencies: self.dependencies.iter().map(Dependency::as_str).collect::<Vec<&str>>(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl Report {
+    pub fn is_valid(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+
+    /// The weakest level the history fails to meet, or `None` if it is serializable as far
+    /// as these anomalies can tell.
+    pub fn violated(&self) -> Option<IsolationLevel> {
+        self.anomalies.iter().map(|anomaly| anomaly.kind.violates()).min()
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut counts = JsonValue::new_object();
+        for anomaly in self.anomalies.iter() {
+            let name = anomaly.kind.as_str();
+            counts[name] = (counts[name].as_usize().unwrap_or_default() + 1).into();
+        }
+        object! {
+            valid: self.is_valid(),
+            violated: self.violated().map(|level| level.as_str()),
+            counts: counts,
+            anomalies: self.anomalies.iter().map(Anomaly::to_json).collect::<Vec<JsonValue>>(),
+        }
+    }
+}
+
+/// A transaction's micro-ops with keys and values dumped to strings.
+struct Txn {
+    operation: Operation,
+    reads: Vec<(String, Vec<String>)>,
+    appends: Vec<(String, String)>,
+}
+
+impl Txn {
+    fn init(operation: Operation) -> Txn {
+        let mut reads = Vec::new();
+        let mut appends = Vec::new();
+        for micro_op in operation.value.members() {
+            let key = micro_op[1].dump();
+            match micro_op[0].as_str() {
+                Some("r") if micro_op[2].is_array() => {
+                    reads.push((key, micro_op[2].members().map(JsonValue::dump).collect()))
+                }
+                Some("append") => appends.push((key, micro_op[2].dump())),
+                _ => {}
+            }
+        }
+        Txn { operation, reads, appends }
+    }
+
+    /// Whether this transaction appended anything to `key` after `value`.
+    fn appended_after(&self, key: &str, value: &str) -> bool {
+        self.appends
+            .iter()
+            .filter(|(k, _)| k == key)
+            .skip_while(|(_, v)| v != value)
+            .nth(1)
+            .is_some()
+    }
+}
+
+/// Checks a `txn` history of `r` and `append` micro-ops the way Elle does: version orders
+/// come from the longest read of each key, dependencies between committed transactions
+/// are inferred from them, and cycles are classified by the dependencies they contain.
+pub fn check(history: &History) -> Report {
+    let txns: Vec<Txn> = history
+        .operations()
+        .into_iter()
+        .filter(|operation| operation.f == "txn" && operation.value.is_array())
+        .map(Txn::init)
+        .collect();
+    let mut anomalies = Vec::new();
+
+    let mut writers: HashMap<(&str, &str), usize> = HashMap::new();
+    for (i, txn) in txns.iter().enumerate() {
+        for (key, value) in txn.appends.iter() {
+            writers.insert((key, value), i);
+        }
+    }
+
+    // Only reads that returned say anything, and a transaction that may not have committed
+    // counts once someone has read what it appended.
+    let mut committed: Vec<bool> = txns.iter().map(|txn| txn.operation.op_type == OpType::Ok).collect();
+    for txn in txns.iter().filter(|txn| txn.operation.op_type == OpType::Ok) {
+        for (key, values) in txn.reads.iter() {
+            for value in values {
+                if let Some(&writer) = writers.get(&(key.as_str(), value.as_str())) {
+                    if txns[writer].operation.op_type == OpType::Info {
+                        committed[writer] = true;
+                    }
+                }
+            }
+        }
+    }
+    let readers: Vec<usize> = (0..txns.len()).filter(|i| txns[*i].operation.op_type == OpType::Ok).collect();
+
+    let mut orders: BTreeMap<&str, &Vec<String>> = BTreeMap::new();
+    for &i in readers.iter() {
+        for (key, values) in txns[i].reads.iter() {
+            let longest = orders.entry(key).or_insert(values);
+            if values.len() > longest.len() {
+                *longest = values;
+            }
+        }
+    }
+    let mut incompatible: BTreeSet<&str> = BTreeSet::new();
+    for &i in readers.iter() {
+        for (key, values) in txns[i].reads.iter() {
+            let longest = orders[key.as_str()];
+            if !longest.starts_with(values) && incompatible.insert(key) {
+                let longest_reader = readers
+                    .iter()
+                    .find(|j| txns[**j].reads.iter().any(|(k, v)| k == key && v == longest))
+                    .copied()
+                    .unwrap_or(i);
+                anomalies.push(Anomaly {
+                    kind: AnomalyKind::IncompatibleOrder,
+                    key: Some(key.clone()),
+                    ops: vec![txns[longest_reader].operation.clone(), txns[i].operation.clone()],
+                    dependencies: Vec::new(),
+                });
+            }
+        }
+    }
+
+    for &i in readers.iter() {
+        for (key, values) in txns[i].reads.iter() {
+            let writer = values.last().and_then(|value| writers.get(&(key.as_str(), value.as_str())).map(|w| (value, *w)));
+            for value in values {
+                if let Some(&writer) = writers.get(&(key.as_str(), value.as_str())) {
+                    if txns[writer].operation.op_type == OpType::Fail {
+                        anomalies.push(read_anomaly(AnomalyKind::G1a, key, &txns[writer], &txns[i]));
+                    }
+                }
+            }
+            if let Some((value, writer)) = writer {
+                if writer != i && txns[writer].appended_after(key, value) {
+                    anomalies.push(read_anomaly(AnomalyKind::G1b, key, &txns[writer], &txns[i]));
+                }
+            }
+        }
+    }
+
+    let mut graph = Graph::init(txns.len());
+    for (key, order) in orders.iter() {
+        let versions: Vec<Option<usize>> = order.iter().map(|value| writers.get(&(*key, value.as_str())).copied()).collect();
+        for pair in versions.windows(2) {
+            if let (Some(a), Some(b)) = (pair[0], pair[1]) {
+                graph.add(a, b, Dependency::WriteWrite);
+            }
+        }
+    }
+    for &i in readers.iter() {
+        for (key, values) in txns[i].reads.iter() {
+            if let Some(&writer) = values.last().and_then(|value| writers.get(&(key.as_str(), value.as_str()))) {
+                graph.add(writer, i, Dependency::WriteRead);
+            }
+            let order = orders[key.as_str()];
+            if order.starts_with(values) {
+                if let Some(&next) = order.get(values.len()).and_then(|value| writers.get(&(key.as_str(), value.as_str()))) {
+                    graph.add(i, next, Dependency::ReadWrite);
+                }
+            }
+        }
+    }
+    graph.retain(&committed);
+    for (kind, cycle) in graph.cycles() {
+        let (ops, dependencies) = cycle.into_iter().map(|(i, dependency)| (txns[i].operation.clone(), dependency)).unzip();
+        anomalies.push(Anomaly { kind, key: None, ops, dependencies });
+    }
+
+    Report { anomalies }
+}
+
+fn read_anomaly(kind: AnomalyKind, key: &str, writer: &Txn, reader: &Txn) -> Anomaly {
+    Anomaly {
+        kind,
+        key: Some(key.to_string()),
+        ops: vec![writer.operation.clone(), reader.operation.clone()],
+        dependencies: Vec::new(),
+    }
+}
+
+/// Dependencies between transactions, with the kinds of each edge as a bit set.
+struct Graph {
+    edges: Vec<BTreeMap<usize, u8>>,
+}
+
+impl Graph {
+    fn init(size: usize) -> Graph {
+        Graph { edges: vec![BTreeMap::new(); size] }
+    }
+
+    fn add(&mut self, from: usize, to: usize, dependency: Dependency) {
+        if from != to {
+            *self.edges[from].entry(to).or_default() |= dependency.bit();
+        }
+    }
+
+    fn retain(&mut self, keep: &[bool]) {
+        for (from, edges) in self.edges.iter_mut().enumerate() {
+            if keep[from] {
+                edges.retain(|to, _| keep[*to]);
+            } else {
+                edges.clear();
+            }
+        }
+    }
+
+    fn successors(&self, from: usize, mask: u8) -> impl Iterator<Item = usize> + '_ {
+        self.edges[from].iter().filter(move |(_, kinds)| *kinds & mask != 0).map(|(to, _)| *to)
+    }
+
+    /// One cycle per strongly connected component, classified by the weakest anomaly it
+    /// shows: a G0 component is not reported again as G1c, and so on. Later passes leave
+    /// out the transactions of components already reported, so a cycle among the rest of
+    /// a larger component is still found.
+    fn cycles(&self) -> Vec<(AnomalyKind, Vec<(usize, Dependency)>)> {
+        let mut cycles = Vec::new();
+        let mut covered: Vec<bool> = vec![false; self.edges.len()];
+        let passes = [
+            (AnomalyKind::G0, WW, WW),
+            (AnomalyKind::G1c, WW | WR, WR),
+            (AnomalyKind::GSingle, WW | WR | RW, RW),
+            (AnomalyKind::G2, WW | WR | RW, RW),
+        ];
+        for (kind, mask, required) in passes {
+            for component in self.components(mask, &covered) {
+                if let Some(cycle) = self.cycle_in(&component, kind, mask, required) {
+                    component.iter().for_each(|i| covered[*i] = true);
+                    cycles.push((kind, cycle));
+                }
+            }
+        }
+        cycles
+    }
+
+    /// A cycle in `component` through an edge of kind `required`. The way back from that
+    /// edge avoids `rw` edges for G-single so the cycle has exactly one.
+    fn cycle_in(&self, component: &[usize], kind: AnomalyKind, mask: u8, required: u8) -> Option<Vec<(usize, Dependency)>> {
+        let members: BTreeSet<usize> = component.iter().copied().collect();
+        let back_mask = if kind == AnomalyKind::GSingle { WW | WR } else { mask };
+        for &from in component {
+            for to in self.successors(from, required).filter(|to| members.contains(to)) {
+                if let Some(path) = self.path(to, from, back_mask, &members) {
+                    let mut cycle = vec![(from, self.dependency(from, to, required))];
+                    for step in path.windows(2) {
+                        cycle.push((step[0], self.dependency(step[0], step[1], back_mask)));
+                    }
+                    return Some(cycle);
+                }
+            }
+        }
+        None
+    }
+
+    /// The weakest dependency from `from` to `to` among `mask`.
+    fn dependency(&self, from: usize, to: usize, mask: u8) -> Dependency {
+        let kinds = self.edges[from][&to] & mask;
+        if kinds & WW != 0 {
+            Dependency::WriteWrite
+        } else if kinds & WR != 0 {
+            Dependency::WriteRead
+        } else {
+            Dependency::ReadWrite
+        }
+    }
+
+    fn path(&self, from: usize, to: usize, mask: u8, within: &BTreeSet<usize>) -> Option<Vec<usize>> {
+        let mut parents: HashMap<usize, usize> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        parents.insert(from, from);
+        while let Some(node) = queue.pop_front() {
+            if node == to {
+                let mut path = vec![to];
+                let mut node = to;
+                while node != from {
+                    node = parents[&node];
+                    path.push(node);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for next in self.successors(node, mask).filter(|next| within.contains(next)) {
+                if let Entry::Vacant(entry) = parents.entry(next) {
+                    entry.insert(node);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Strongly connected components with more than one transaction, by Tarjan's algorithm,
+    /// ignoring the `excluded` transactions.
+    fn components(&self, mask: u8, excluded: &[bool]) -> Vec<Vec<usize>> {
+        let size = self.edges.len();
+        let mut index: Vec<Option<usize>> = vec![None; size];
+        let mut low: Vec<usize> = vec![0; size];
+        let mut on_stack: Vec<bool> = vec![false; size];
+        let mut stack: Vec<usize> = Vec::new();
+        let mut components = Vec::new();
+        let mut next_index = 0;
+        let edges_from = |node: usize| self.successors(node, mask).filter(|next| !excluded[*next]).collect::<Vec<usize>>();
+        for root in 0..size {
+            if index[root].is_some() || excluded[root] {
+                continue;
+            }
+            let mut work: Vec<(usize, Vec<usize>, usize)> = Vec::new();
+            index[root] = Some(next_index);
+            low[root] = next_index;
+            next_index += 1;
+            stack.push(root);
+            on_stack[root] = true;
+            work.push((root, edges_from(root), 0));
+            while let Some((node, successors, position)) = work.last_mut() {
+                let node = *node;
+                if let Some(&next) = successors.get(*position) {
+                    *position += 1;
+                    match index[next] {
+                        None => {
+                            index[next] = Some(next_index);
+                            low[next] = next_index;
+                            next_index += 1;
+                            stack.push(next);
+                            on_stack[next] = true;
+                            work.push((next, edges_from(next), 0));
+                        }
+                        Some(seen) if on_stack[next] => low[node] = low[node].min(seen),
+                        Some(_) => {}
+                    }
+                    continue;
+                }
+                work.pop();
+                if let Some((parent, _, _)) = work.last() {
+                    low[*parent] = low[*parent].min(low[node]);
+                }
+                if Some(low[node]) == index[node] {
+                    let mut component = Vec::new();
+                    while let Some(member) = stack.pop() {
+                        on_stack[member] = false;
+                        component.push(member);
+                        if member == node {
+                            break;
+                        }
+                    }
+                    if component.len() > 1 {
+                        component.sort_unstable();
+                        components.push(component);
+                    }
+                }
+            }
+        }
+        components
+    }
+}
+
+fn txn_json(operation: &Operation) -> JsonValue {
+    object! {
+        process: operation.process.clone(),
+        type: operation.op_type.as_str(),
+        value: operation.value.clone(),
+        invoke: operation.invoke,
+        complete: operation.complete,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use json::array;
+
+    fn read(key: &str, values: &[i32]) -> JsonValue {
+        array!["r", key, values.to_vec()]
+    }
+
+    fn append(key: &str, value: i32) -> JsonValue {
+        array!["append", key, value]
+    }
+
+    /// Runs each transaction to completion in turn, each from its own client.
+    fn history(txns: Vec<(OpType, Vec<JsonValue>)>) -> History {
+        let mut history = History::init();
+        for (i, (op_type, ops)) in txns.into_iter().enumerate() {
+            let process = format!("c{}", i + 1);
+            let time = 2 * i as u64;
+            history.push(time, &process, OpType::Invoke, "txn", JsonValue::Null, JsonValue::from(ops.clone()));
+            history.push(time + 1, &process, op_type, "txn", JsonValue::Null, JsonValue::from(ops));
+        }
+        history
+    }
+
+    fn kinds(report: &Report) -> Vec<AnomalyKind> {
+        report.anomalies.iter().map(|anomaly| anomaly.kind).collect()
+    }
+
+    #[test]
+    fn a_serial_history_is_serializable() {
+        let report = check(&history(vec![
+            (OpType::Ok, vec![append("x", 1)]),
+            (OpType::Ok, vec![read("x", &[1]), append("x", 2)]),
+            (OpType::Ok, vec![read("x", &[1, 2])]),
+        ]));
+        assert!(report.is_valid());
+        assert_eq!(report.violated(), None);
+    }
+
+    #[test]
+    fn finds_incompatible_orders() {
+        let report = check(&history(vec![
+            (OpType::Ok, vec![append("x", 1)]),
+            (OpType::Ok, vec![append("x", 2)]),
+            (OpType::Ok, vec![read("x", &[1, 2])]),
+            (OpType::Ok, vec![read("x", &[2])]),
+        ]));
+        assert_eq!(kinds(&report), vec![AnomalyKind::IncompatibleOrder]);
+        assert_eq!(report.violated(), Some(IsolationLevel::ReadUncommitted));
+    }
+
+    #[test]
+    fn finds_g0_write_cycles() {
+        let report = check(&history(vec![
+            (OpType::Ok, vec![append("x", 1), append("y", 2)]),
+            (OpType::Ok, vec![append("x", 2), append("y", 1)]),
+            (OpType::Ok, vec![read("x", &[1, 2]), read("y", &[1, 2])]),
+        ]));
+        assert_eq!(kinds(&report), vec![AnomalyKind::G0]);
+        assert_eq!(report.violated(), Some(IsolationLevel::ReadUncommitted));
+    }
+
+    #[test]
+    fn finds_g1a_aborted_reads() {
+        let report = check(&history(vec![
+            (OpType::Fail, vec![append("x", 1)]),
+            (OpType::Ok, vec![read("x", &[1])]),
+        ]));
+        assert_eq!(kinds(&report), vec![AnomalyKind::G1a]);
+        assert_eq!(report.violated(), Some(IsolationLevel::ReadCommitted));
+    }
+
+    #[test]
+    fn finds_g1b_intermediate_reads() {
+        let report = check(&history(vec![
+            (OpType::Ok, vec![append("x", 1), append("x", 2)]),
+            (OpType::Ok, vec![read("x", &[1])]),
+        ]));
+        assert_eq!(kinds(&report), vec![AnomalyKind::G1b]);
+        assert_eq!(report.violated(), Some(IsolationLevel::ReadCommitted));
+    }
+
+    #[test]
+    fn finds_g1c_circular_information_flow() {
+        let report = check(&history(vec![
+            (OpType::Ok, vec![append("x", 1), read("y", &[1])]),
+            (OpType::Ok, vec![append("y", 1), read("x", &[1])]),
+        ]));
+        assert_eq!(kinds(&report), vec![AnomalyKind::G1c]);
+        assert_eq!(report.violated(), Some(IsolationLevel::ReadCommitted));
+    }
+
+    #[test]
+    fn finds_g_single_read_skew() {
+        let report = check(&history(vec![
+            (OpType::Ok, vec![read("x", &[]), read("y", &[1])]),
+            (OpType::Ok, vec![append("x", 1), append("y", 1)]),
+            (OpType::Ok, vec![read("x", &[1]), read("y", &[1])]),
+        ]));
+        assert_eq!(kinds(&report), vec![AnomalyKind::GSingle]);
+        assert_eq!(report.violated(), Some(IsolationLevel::SnapshotIsolation));
+    }
+
+    #[test]
+    fn finds_g2_write_skew() {
+        let report = check(&history(vec![
+            (OpType::Ok, vec![read("x", &[]), append("y", 1)]),
+            (OpType::Ok, vec![read("y", &[]), append("x", 1)]),
+            (OpType::Ok, vec![read("x", &[1]), read("y", &[1])]),
+        ]));
+        assert_eq!(kinds(&report), vec![AnomalyKind::G2]);
+        assert_eq!(report.violated(), Some(IsolationLevel::Serializable));
+    }
+
+    #[test]
+    fn finds_cycles_among_the_rest_of_a_component_already_reported() {
+        // c1 and c2 form a G0 cycle; c4 and c5 a G2 cycle, joined to the first through
+        // c4's read of x.
+        let report = check(&history(vec![
+            (OpType::Ok, vec![append("x", 1), append("y", 2)]),
+            (OpType::Ok, vec![append("x", 2), append("y", 1)]),
+            (OpType::Ok, vec![read("x", &[1, 2]), read("y", &[1, 2]), read("p", &[1]), read("q", &[1])]),
+            (OpType::Ok, vec![read("p", &[]), append("q", 1), read("x", &[1])]),
+            (OpType::Ok, vec![read("q", &[]), append("p", 1)]),
+        ]));
+        assert_eq!(kinds(&report), vec![AnomalyKind::G0, AnomalyKind::G2]);
+        assert_eq!(report.violated(), Some(IsolationLevel::ReadUncommitted));
+    }
+}
@@ -0,0 +1,3 @@
+pub mod checker;
+pub mod network;
+pub mod simulation;
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+
+#[derive(Clone, Debug)]
+pub struct NetworkConfig {
+    pub min_latency: u64,
+    pub max_latency: u64,
+    pub drop_probability: f64,
+    pub duplicate_probability: f64,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            min_latency: 0,
+            max_latency: 0,
+            drop_probability: 0.0,
+            duplicate_probability: 0.0,
+        }
+    }
+}
+
+pub enum Fate {
+    Dropped,
+    Deliver(Vec<u64>),
+}
+
+pub struct Network {
+    config: NetworkConfig,
+    partition: HashMap<String, usize>,
+}
+
+impl Network {
+    pub fn init(config: NetworkConfig) -> Network {
+        Network {
+            config,
+            partition: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &NetworkConfig {
+        &self.config
+    }
+
+    pub fn partition(&mut self, groups: &[Vec<String>]) {
+        self.partition.clear();
+        for (i, group) in groups.iter().enumerate() {
+            for node in group {
+                self.partition.insert(node.clone(), i);
+            }
+        }
+    }
+
+    pub fn heal(&mut self) {
+        self.partition.clear();
+    }
+
+    pub fn is_partitioned(&self, src: &str, dest: &str) -> bool {
+        match (self.partition.get(src), self.partition.get(dest)) {
+            (Some(a), Some(b)) => a != b,
+            _ => false,
+        }
+    }
+
+    pub fn fate(&self, rng: &mut StdRng, src: &str, dest: &str, reliable: bool) -> Fate {
+        if self.is_partitioned(src, dest) {
+            return Fate::Dropped;
+        }
+        if !reliable && rng.gen_bool(self.config.drop_probability) {
+            return Fate::Dropped;
+        }
+        let mut delays = vec![self.latency(rng)];
+        if !reliable && rng.gen_bool(self.config.duplicate_probability) {
+            delays.push(self.latency(rng));
+        }
+        Fate::Deliver(delays)
+    }
+
+    fn latency(&self, rng: &mut StdRng) -> u64 {
+        if self.config.max_latency <= self.config.min_latency {
+            return self.config.min_latency;
+        }
+        rng.gen_range(self.config.min_latency..=self.config.max_latency)
+    }
+}
@@ -0,0 +1,306 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::ops::Deref;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+use std::time::Duration;
+
+use json::{object, stringify, JsonValue};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use shared_lib::clock::{Clock, ManualClock};
+use shared_lib::node_state::NodeState;
+use shared_lib::read_respond::dispatch;
+use shared_lib::router::Handlers;
+use shared_lib::logging;
+use shared_lib::worker_pool::{Executor, Job, Saturated};
+
+use crate::network::{Fate, Network, NetworkConfig};
+
+const OUTBOX_CAPACITY: usize = 4096;
+/// How long, in real time, a node task may run before the simulation assumes it is
+/// blocked on something other than the clock.
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delivery {
+    pub time: u64,
+    pub message: JsonValue,
+}
+
+struct SimNode<T: Deref<Target=NodeState> + 'static> {
+    state: &'static T,
+    handlers: &'static Handlers<T>,
+    outbox: Receiver<String>,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct InFlight {
+    deliver_at: u64,
+    seq: u64,
+    line: String,
+}
+
+pub struct Simulation<T: Deref<Target=NodeState> + 'static> {
+    seed: u64,
+    rng: StdRng,
+    clock: Arc<ManualClock>,
+    now: u64,
+    seq: u64,
+    network: Network,
+    nodes: BTreeMap<String, SimNode<T>>,
+    in_flight: BinaryHeap<Reverse<InFlight>>,
+    client_msg_id: i32,
+    client_messages: Vec<Delivery>,
+    trace: Vec<Delivery>,
+}
+
+impl<T> Simulation<T>
+    where T: Deref<Target=NodeState> + Sync + 'static
+{
+    pub fn init(seed: u64, config: NetworkConfig) -> Simulation<T> {
+        Simulation {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            clock: Arc::new(ManualClock::stepped()),
+            now: 0,
+            seq: 0,
+            network: Network::init(config),
+            nodes: BTreeMap::new(),
+            in_flight: BinaryHeap::new(),
+            client_msg_id: 0,
+            client_messages: Vec::new(),
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn add_node<S, H>(&mut self, node_id: &str, make_state: S, make_handlers: H)
+        where S: FnOnce(SyncSender<String>) -> T,
+              H: FnOnce(&'static T) -> Handlers<T>
+    {
+        let (sender, outbox) = sync_channel(OUTBOX_CAPACITY);
+        let state: &'static T = Box::leak(Box::new(make_state(sender)));
+        state.callbacks().run_inline(true);
+        state.set_clock(self.clock.clone());
+        state.set_seed(self.rng.gen());
+        let handlers: &'static Handlers<T> = Box::leak(Box::new(make_handlers(state)));
+        self.nodes.insert(node_id.to_string(), SimNode { state, handlers, outbox });
+    }
+
+    pub fn init_nodes(&mut self) {
+        let node_ids: Vec<String> = self.node_ids();
+        for node_id in node_ids.iter() {
+            let body = object! {type: "init", node_id: node_id.clone(), node_ids: node_ids.clone()};
+            self.send_from_client("c0", node_id, body);
+        }
+        self.run_until_quiet(u64::MAX);
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn clock(&self) -> Arc<ManualClock> {
+        self.clock.clone()
+    }
+
+    pub fn node_ids(&self) -> Vec<String> {
+        self.nodes.keys().cloned().collect()
+    }
+
+    pub fn node(&self, node_id: &str) -> Option<&'static T> {
+        self.nodes.get(node_id).map(|node| node.state)
+    }
+
+    pub fn partition(&mut self, groups: &[Vec<String>]) {
+        self.network.partition(groups);
+    }
+
+    pub fn heal(&mut self) {
+        self.network.heal();
+    }
+
+    pub fn send_from_client(&mut self, client: &str, dest: &str, mut body: JsonValue) -> i32 {
+        self.client_msg_id += 1;
+        body["msg_id"] = JsonValue::from(self.client_msg_id);
+        self.enqueue(object! {src: client, dest: dest, body: body});
+        self.client_msg_id
+    }
+
+    pub fn client_messages(&self) -> &[Delivery] {
+        &self.client_messages
+    }
+
+    pub fn reply_to(&self, client: &str, msg_id: i32) -> Option<&JsonValue> {
+        self.client_messages
+            .iter()
+            .map(|delivery| &delivery.message)
+            .find(|message| message["dest"] == client && message["body"]["in_reply_to"] == msg_id)
+    }
+
+    pub fn trace(&self) -> &[Delivery] {
+        &self.trace
+    }
+
+    pub fn step(&mut self) -> bool {
+        let in_flight = match self.in_flight.pop() {
+            Some(Reverse(in_flight)) => in_flight,
+            None => return false,
+        };
+        self.set_now(in_flight.deliver_at);
+        let message = match json::parse(&in_flight.line) {
+            Ok(message) => message,
+            Err(_) => return true,
+        };
+        self.trace.push(Delivery { time: self.now, message: message.clone() });
+        let dest = message["dest"].as_str().unwrap_or_default().to_string();
+        match self.nodes.get(&dest) {
+            Some(node) => dispatch(message, node.state, node.handlers, &Tasks(self.clock.clone())),
+            None => self.client_messages.push(Delivery { time: self.now, message }),
+        }
+        self.settle();
+        true
+    }
+
+    pub fn advance(&mut self, millis: u64) {
+        self.run_until(self.now + millis);
+    }
+
+    /// Delivers messages and fires timers in time order up to `time`.
+    pub fn run_until(&mut self, time: u64) {
+        self.settle();
+        while let Some(next) = self.next_delivery().into_iter().chain(self.next_timer()).min().filter(|t| *t <= time) {
+            if self.next_delivery() == Some(next) {
+                self.step();
+            } else {
+                self.tick(next);
+            }
+        }
+        self.tick(time);
+    }
+
+    /// Delivers messages until none are in flight, moving time on only as far as each
+    /// delivery needs.
+    pub fn run_until_quiet(&mut self, max_steps: u64) -> u64 {
+        let mut steps = 0;
+        self.settle();
+        while steps < max_steps && self.step() {
+            steps += 1;
+        }
+        steps
+    }
+
+    /// Moves virtual time forward, stopping at each timer that comes due on the way so
+    /// periodic tasks fire as often as they would in real time.
+    fn set_now(&mut self, time: u64) {
+        while let Some(next_timer) = self.next_timer().filter(|t| *t <= time) {
+            self.tick(next_timer.max(self.now));
+        }
+        self.tick(time);
+    }
+
+    /// Moves the clock to `time`, expires RPCs, then runs due timers and wakes tasks whose
+    /// wait has timed out.
+    fn tick(&mut self, time: u64) {
+        self.now = self.now.max(time);
+        self.clock.advance_to(Duration::from_millis(self.now));
+        let now = self.clock.now();
+        let tasks = Tasks(self.clock.clone());
+        for node in self.nodes.values() {
+            node.state.callbacks().expire(now);
+            let scheduler = node.state.scheduler();
+            if scheduler.next_deadline().is_some_and(|deadline| deadline <= now) {
+                let _ = tasks.execute(Box::new(move || {
+                    scheduler.run_due();
+                }));
+            }
+        }
+        self.settle();
+    }
+
+    /// Lets node tasks run one at a time, in the order they became runnable, until each
+    /// has finished or waits on the clock. Messages they send are queued as they go.
+    fn settle(&mut self) {
+        loop {
+            if !self.clock.wait_until_parked(STALL_TIMEOUT) {
+                panic!("Simulation stalled at {}ms: a node task is blocked on something other than the clock", self.now);
+            }
+            self.collect_outboxes();
+            if !self.clock.wake_next() {
+                return;
+            }
+        }
+    }
+
+    /// When the next timer fires or a parked task's wait times out.
+    fn next_timer(&self) -> Option<u64> {
+        let now = self.clock.now();
+        self.nodes
+            .values()
+            .filter_map(|node| node.state.scheduler().next_deadline())
+            .chain(self.clock.next_deadline())
+            .min()
+            .map(|deadline| {
+                let at = self.clock.elapsed() + deadline.saturating_duration_since(now);
+                (at.as_nanos() as u64).div_ceil(1_000_000)
+            })
+    }
+
+    fn next_delivery(&self) -> Option<u64> {
+        self.in_flight.peek().map(|Reverse(in_flight)| in_flight.deliver_at)
+    }
+
+    fn collect_outboxes(&mut self) {
+        let mut lines = Vec::new();
+        for node in self.nodes.values() {
+            while let Ok(line) = node.outbox.try_recv() {
+                lines.push(line);
+            }
+        }
+        for line in lines {
+            match json::parse(&line) {
+                Ok(message) => self.enqueue(message),
+                Err(err) => logging::warn("simulator").log(&format!("Dropped unparsable message {}: {}", line, err)),
+            }
+        }
+    }
+
+    fn is_node(&self, id: &JsonValue) -> bool {
+        id.as_str().is_some_and(|id| self.nodes.contains_key(id))
+    }
+
+    fn enqueue(&mut self, message: JsonValue) {
+        let reliable = !self.is_node(&message["src"]) || !self.is_node(&message["dest"]);
+        let src = message["src"].as_str().unwrap_or_default();
+        let dest = message["dest"].as_str().unwrap_or_default();
+        match self.network.fate(&mut self.rng, src, dest, reliable) {
+            Fate::Dropped => logging::debug("simulator").log(&format!("Dropped {}", message)),
+            Fate::Deliver(delays) => {
+                for delay in delays {
+                    self.seq += 1;
+                    let deliver_at = self.now + delay;
+                    self.in_flight.push(Reverse(InFlight { deliver_at, seq: self.seq, line: stringify(message.clone()) }));
+                }
+            }
+        }
+    }
+}
+
+/// Runs each handler as a task on the simulation's clock, so the simulation decides when
+/// it runs and when whatever it waits on returns.
+struct Tasks(Arc<ManualClock>);
+
+impl Executor for Tasks {
+    fn execute(&self, job: Job) -> Result<(), Saturated> {
+        self.0.spawn(job);
+        Ok(())
+    }
+
+    fn handle(&self) -> Box<dyn Executor + Send> {
+        Box::new(Tasks(self.0.clone()))
+    }
+}
@@ -0,0 +1,56 @@
+use json::object;
+use maelstrom::gossip;
+use maelstrom::states::maelstrom_node_state::MaelstromState;
+use simulator::network::NetworkConfig;
+use simulator::simulation::Simulation;
+
+const NODES: [&str; 3] = ["n1", "n2", "n3"];
+
+fn cluster(seed: u64) -> Simulation<MaelstromState> {
+    let mut simulation = Simulation::init(seed, NetworkConfig { min_latency: 1, max_latency: 10, ..NetworkConfig::default() });
+    for node_id in NODES {
+        simulation.add_node(node_id, MaelstromState::init, |state| {
+            gossip::start(state);
+            maelstrom::broadcast_handlers()
+        });
+    }
+    simulation.init_nodes();
+    simulation
+}
+
+fn read(simulation: &mut Simulation<MaelstromState>, node_id: &str) -> Vec<i32> {
+    let msg_id = simulation.send_from_client("c1", node_id, object! {type: "read"});
+    simulation.run_until_quiet(100);
+    let reply = simulation.reply_to("c1", msg_id).expect("no reply to read");
+    reply["body"]["messages"].members().filter_map(|message| message.as_i32()).collect()
+}
+
+fn gossip_rounds(simulation: &Simulation<MaelstromState>) -> usize {
+    simulation.trace().iter().filter(|delivery| delivery.message["body"]["type"] == "gossip").count()
+}
+
+#[test]
+fn gossip_retries_until_a_partitioned_neighbor_acknowledges() {
+    let mut simulation = cluster(3);
+    simulation.partition(&[vec!["n1".to_string(), "n2".to_string()], vec!["n3".to_string()]]);
+    simulation.send_from_client("c1", "n1", object! {type: "broadcast", message: 42});
+    simulation.advance(1_000);
+
+    assert_eq!(read(&mut simulation, "n2"), vec![42]);
+    assert_eq!(read(&mut simulation, "n3"), Vec::<i32>::new());
+    let n1 = simulation.node("n1").unwrap();
+    assert!(n1.unacknowledged("n2").is_empty());
+    assert_eq!(n1.unacknowledged("n3"), vec![42]);
+
+    simulation.heal();
+    simulation.advance(2_000);
+    assert_eq!(read(&mut simulation, "n3"), vec![42]);
+    for node_id in NODES {
+        let node = simulation.node(node_id).unwrap();
+        assert!(NODES.iter().all(|peer| *peer == node_id || node.unacknowledged(peer).is_empty()));
+    }
+
+    let rounds = gossip_rounds(&simulation);
+    simulation.advance(1_000);
+    assert_eq!(gossip_rounds(&simulation), rounds);
+}
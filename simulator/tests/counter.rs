@@ -0,0 +1,50 @@
+use json::object;
+use maelstrom::message_handlers::{add_handler::AddHandler, init_handler::InitHandler, read_handler::ReadHandler, replicate_handler::ReplicateHandler};
+use maelstrom::replicator;
+use maelstrom::states::maelstrom_node_state::MaelstromState;
+use shared_lib::router::Router;
+use simulator::network::NetworkConfig;
+use simulator::simulation::Simulation;
+
+const NODES: [&str; 3] = ["n1", "n2", "n3"];
+
+fn cluster(seed: u64) -> Simulation<MaelstromState> {
+    let mut simulation = Simulation::init(seed, NetworkConfig { min_latency: 1, max_latency: 10, ..NetworkConfig::default() });
+    for node_id in NODES {
+        simulation.add_node(node_id, MaelstromState::init, |state| {
+            replicator::send_values(state);
+            // The simulator has no lin-kv, so init without it.
+            Router::init()
+                .route("init", InitHandler::without_kv())
+                .route("add", AddHandler {})
+                .route("read", ReadHandler {})
+                .route("replicate", ReplicateHandler {})
+                .build()
+        });
+    }
+    simulation.init_nodes();
+    simulation
+}
+
+fn read(simulation: &mut Simulation<MaelstromState>, node_id: &str) -> i32 {
+    let msg_id = simulation.send_from_client("c1", node_id, object! {type: "read"});
+    simulation.run_until_quiet(100);
+    let reply = simulation.reply_to("c1", msg_id).expect("no reply to read");
+    reply["body"]["value"].as_i32().unwrap()
+}
+
+#[test]
+fn the_replicator_spreads_counters_on_the_scheduler() {
+    let mut simulation = cluster(5);
+    simulation.send_from_client("c1", "n1", object! {type: "add", delta: 3});
+    simulation.send_from_client("c2", "n2", object! {type: "add", delta: 4});
+    simulation.run_until_quiet(100);
+    assert_eq!(read(&mut simulation, "n3"), 0);
+
+    simulation.advance(5_100);
+    for node_id in NODES {
+        assert_eq!(read(&mut simulation, node_id), 7);
+    }
+    let replications = simulation.trace().iter().filter(|delivery| delivery.message["body"]["type"] == "replicate").count();
+    assert_eq!(replications, NODES.len() * (NODES.len() - 1));
+}
@@ -0,0 +1,47 @@
+use json::object;
+use raft::election_state;
+use raft::raft_node_state::RaftState;
+use simulator::network::NetworkConfig;
+use simulator::simulation::Simulation;
+
+const NODES: [&str; 3] = ["n1", "n2", "n3"];
+
+fn lossy() -> NetworkConfig {
+    NetworkConfig { min_latency: 1, max_latency: 20, drop_probability: 0.05, duplicate_probability: 0.05 }
+}
+
+fn run(seed: u64, config: NetworkConfig) -> Simulation<RaftState> {
+    let mut simulation = Simulation::init(seed, config);
+    for node_id in NODES {
+        simulation.add_node(node_id, RaftState::init, |state| raft::handlers(&election_state::start(state)));
+    }
+    simulation.init_nodes();
+    simulation.advance(5_000);
+    for (key, node_id) in NODES.iter().enumerate() {
+        simulation.send_from_client("c1", node_id, object! {type: "write", key: key, value: key * 10});
+    }
+    simulation.advance(5_000);
+    simulation
+}
+
+#[test]
+fn the_same_seed_reproduces_the_same_trace() {
+    let first = run(7, lossy());
+    let second = run(7, lossy());
+    assert!(first.trace().iter().any(|delivery| delivery.message["body"]["type"] == "append_entries"));
+    assert_eq!(first.trace(), second.trace());
+    assert_eq!(first.client_messages(), second.client_messages());
+    assert_ne!(first.trace(), run(8, lossy()).trace());
+}
+
+#[test]
+fn writes_through_followers_are_proxied_to_the_leader() {
+    let simulation = run(11, NetworkConfig { min_latency: 1, max_latency: 20, ..NetworkConfig::default() });
+    let replies: Vec<_> = simulation
+        .client_messages()
+        .iter()
+        .filter(|delivery| delivery.message["dest"] == "c1" && delivery.message["body"]["in_reply_to"].as_i32() > Some(NODES.len() as i32))
+        .map(|delivery| delivery.message["body"]["type"].to_string())
+        .collect();
+    assert_eq!(replies, vec!["write_ok"; NODES.len()]);
+}